@@ -1,3 +1,4 @@
+#[macro_use]
 extern crate dynobject;
 
 use dynobject::DynObject;
@@ -26,22 +27,22 @@ fn run_to_end(pr: &Processor) {
 	}
 }
 
-fn setup_data(outer_obj: &DynObject<Key>) {
-	let mut obj = outer_obj.aquire();
-	obj.create_property("counter1", Box::new(0u32));
-	obj.create_property("counter2", Box::new(1u32));
-	obj.create_property("limit", Box::new(4u32));
-}
-
 fn main() {
-	let obj = DynObject::<Key>::new();
-	setup_data( &obj );
+	let obj: DynObject<Key> = dynobject!{
+		"counter1" => 0u32,
+		"counter2" => 1u32,
+		"limit" => 4u32
+	};
 	let p1 = Processor {
 		shared_data: obj.clone(),
 		runner: Box::new( |data: &DynObject<Key>| -> bool {
-			let mut obj = data.aquire();
+			let mut obj = match data.try_aquire() {
+				Ok(obj) => obj,
+				//someone else is using the data, try again on the next run
+				Err(_) => return true
+			};
 			let value = *obj["counter1"].as_ref::<u32>().unwrap() + 1;
-			*obj["counter1"].as_mut::<u32>().unwrap() = value;
+			*obj.as_mut::<u32>(&"counter1").unwrap() = value;
 			println!("reached {}", value);
 			obj["limit"].as_ref::<u32>().unwrap() >= &value
 		} )
@@ -49,13 +50,14 @@ fn main() {
 	let p2 = Processor {
 		shared_data: obj.clone(),
 		runner: Box::new( |data: &DynObject<Key>| -> bool {
-			let mut obj = data.aquire();
-			*obj["counter2"].as_mut::<u32>().unwrap() += 2;
-            {
-                let ref_2_counter1 = obj["counter1"].as_mut::<u32>().unwrap();
-                *ref_2_counter1 -= 1;
-                *ref_2_counter1 > 0
-            }
+			let mut obj = match data.try_aquire() {
+				Ok(obj) => obj,
+				//someone else is using the data, try again on the next run
+				Err(_) => return true
+			};
+			*obj.as_mut::<u32>(&"counter2").unwrap() += 2;
+			*obj.as_mut::<u32>(&"counter1").unwrap() -= 1;
+			*obj["counter1"].as_ref::<u32>().unwrap() > 0
 		} )
 	};
 	
@@ -0,0 +1,175 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use super::dyn_property::{self, DynProperty};
+
+
+/// converts the inner value of a property, None if the value can not be converted
+type ConvertFn = Box<Fn(&Any) -> Option<DynProperty>>;
+
+/// registers conversions from `$from` to each of the following types using `as`
+macro_rules! widen {
+    ($registry:expr, $from:ty => $($to:ty),+) => {
+        $( $registry.register(|value: &$from| Some(*value as $to)); )+
+    }
+}
+
+/// registers the conversions from and to `String` for each of the types
+macro_rules! stringify_types {
+    ($registry:expr, $($ty:ty),+) => {
+        $(
+            $registry.register(|value: &$ty| Some(value.to_string()));
+            $registry.register(|value: &String| value.parse::<$ty>().ok());
+        )+
+    }
+}
+
+
+/// a set of conversions between property types
+///
+/// `DynProperty::as_ref` only succeeds if the requested type is exactly
+/// the inner type. `DynProperty::get_as` and `DynProperty::convert_in_place`
+/// (and the methods of `InnerDynObject` with the same names) fall back
+/// to the conversions registered in a `ConversionRegistry`, e.g. to
+/// read a `u32` counter as `u64`. Conversions are not chained, a
+/// conversion from `u8` to `String` is not done over `u32`.
+///
+/// `new` creates a registry with the built-in conversions:
+///
+/// - lossless numeric widening, e.g. `u8` to `u32` or `i32` to `f64`
+/// - all numbers, `bool` and `char` to `String` and back by parsing
+/// - `&'static str` to `String`
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{DynProperty, ConversionRegistry};
+/// let mut registry = ConversionRegistry::new();
+/// let prop = DynProperty::from_value(3u32);
+/// assert_eq!(prop.as_ref::<u64>(), None);
+/// assert_eq!(prop.get_as::<u64>(&registry), Some(3u64));
+/// assert_eq!(prop.get_as::<String>(&registry), Some("3".to_string()));
+/// registry.register(|value: &u32| Some(*value != 0));
+/// assert_eq!(prop.get_as::<bool>(&registry), Some(true));
+/// ```
+///
+pub struct ConversionRegistry {
+    conversions: HashMap<(TypeId, TypeId), ConvertFn>
+}
+
+impl ConversionRegistry {
+
+    /// creates a registry with the built-in conversions
+    ///
+    pub fn new() -> ConversionRegistry {
+        let mut registry = ConversionRegistry::empty();
+        widen!(registry, u8 => u16, u32, u64, usize, i16, i32, i64, isize, f32, f64);
+        widen!(registry, u16 => u32, u64, usize, i32, i64, f32, f64);
+        widen!(registry, u32 => u64, i64, f64);
+        widen!(registry, i8 => i16, i32, i64, isize, f32, f64);
+        widen!(registry, i16 => i32, i64, isize, f32, f64);
+        widen!(registry, i32 => i64, f64);
+        widen!(registry, f32 => f64);
+        stringify_types!(registry, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64, bool, char);
+        registry.register(|value: &&'static str| Some(value.to_string()));
+        registry
+    }
+
+    /// creates a registry without any conversions
+    ///
+    pub fn empty() -> ConversionRegistry {
+        ConversionRegistry {
+            conversions: HashMap::new()
+        }
+    }
+
+    /// registers a conversion from `From` to `To`
+    ///
+    /// `func` returns None if a value can not be converted, e.g. a
+    /// `String` witch is no number. A conversion registered before for
+    /// the same types is replaced, this includes the built-in ones.
+    ///
+    pub fn register<From, To, F>(&mut self, func: F)
+        where From: Any, To: Any, F: Fn(&From) -> Option<To>+'static
+    {
+        let convert = move |value: &Any| match value.downcast_ref::<From>() {
+            Some(value) => func(value).map(DynProperty::from_value),
+            None => None
+        };
+        self.conversions.insert((TypeId::of::<From>(), TypeId::of::<To>()), Box::new(convert) as ConvertFn);
+    }
+
+    /// returns true if a conversion from `From` to `To` is registered
+    ///
+    pub fn can_convert<From: Any, To: Any>(&self) -> bool {
+        self.conversions.contains_key(&(TypeId::of::<From>(), TypeId::of::<To>()))
+    }
+
+    /// returns the number of registered conversions
+    ///
+    pub fn len(&self) -> usize {
+        self.conversions.len()
+    }
+
+    /// returns true if no conversions are registered
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.conversions.is_empty()
+    }
+}
+
+/// converts the value of `prop` to `T` using a registered conversion
+///
+/// Returns None if there is no conversion, it fails or the value
+/// is borrowed mutable. The result does not capture the clone or debug
+/// support of `prop`.
+pub fn convert<T: Any>(registry: &ConversionRegistry, prop: &DynProperty) -> Option<DynProperty> {
+    let key = (dyn_property::inner_type_id(prop), TypeId::of::<T>());
+    match (registry.conversions.get(&key), dyn_property::inner_value(prop)) {
+        (Some(convert), Some(value)) => convert(value),
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::ConversionRegistry;
+    use super::super::dyn_property::DynProperty;
+
+    #[test]
+    fn numbers_should_only_be_widened() {
+        let registry = ConversionRegistry::new();
+        assert_eq!(DynProperty::from_value(200u8).get_as::<i16>(&registry), Some(200i16));
+        assert_eq!(DynProperty::from_value(-3i32).get_as::<f64>(&registry), Some(-3f64));
+        assert_eq!(DynProperty::from_value(1.5f32).get_as::<f64>(&registry), Some(1.5f64));
+        assert!(DynProperty::from_value(3u64).get_as::<u32>(&registry).is_none());
+        assert!(DynProperty::from_value(-3i8).get_as::<u32>(&registry).is_none());
+        assert!(!registry.can_convert::<i64, i32>());
+    }
+
+    #[test]
+    fn strings_should_be_parsed() {
+        let registry = ConversionRegistry::new();
+        let prop = DynProperty::from_value("42".to_string());
+        assert_eq!(prop.get_as::<u16>(&registry), Some(42u16));
+        assert_eq!(prop.get_as::<f32>(&registry), Some(42f32));
+        assert!(prop.get_as::<bool>(&registry).is_none());
+        assert_eq!(DynProperty::from_value("hi").get_as::<String>(&registry), Some("hi".to_string()));
+        assert_eq!(DynProperty::from_value('x').get_as::<String>(&registry), Some("x".to_string()));
+    }
+
+    #[test]
+    fn register_should_replace_conversions() {
+        let mut registry = ConversionRegistry::empty();
+        assert!(registry.is_empty());
+        let prop = DynProperty::from_value(7u8);
+        assert!(prop.get_as::<u32>(&registry).is_none());
+        registry.register(|value: &u8| Some(*value as u32));
+        registry.register(|value: &u8| Some(*value as u32 * 2));
+        assert_eq!(registry.len(), 1);
+        assert_eq!(prop.get_as::<u32>(&registry), Some(14u32));
+        //the exact type does not need a conversion
+        assert_eq!(prop.get_as::<u8>(&registry), Some(7u8));
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+
+use super::inner_dyn_object;
+use super::storage::{PropertyStorage, HashMapStorage};
+use super::weak_dyn_object::WeakDynObject;
+use super::DynObject;
+
+
+/// a reference from one `DynObject` to another, see `CycleCollector::find_cycles`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum CycleLink<Key> {
+    /// the property with the given key contains the next object
+    Property(Key),
+    /// the next object is the prototype
+    Prototype
+}
+
+/// a opt-in collector for cycles of `DynObject`s
+///
+/// A `DynObject` stored in a property (or used as prototype) of a object
+/// it refers to itself is never freed. The collector only knows the
+/// objects passed to `track`, it refers to them weakly. `collect` frees
+/// all tracked objects witch are neither reachable from the given roots nor
+/// referenced from outside of the tracked objects, e.g. by a local variable,
+/// by removing their properties and prototype. Objects witch are aquired
+/// mutable while collecting are treated as roots. References the collector
+/// can not see, like `DynObject`s captured by observers, keep an object alive.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{DynObject, CycleCollector, CycleLink};
+/// let mut collector = CycleCollector::new();
+/// let parent = DynObject::<&'static str>::new();
+/// let child = DynObject::<&'static str>::new();
+/// collector.track(&parent);
+/// collector.track(&child);
+/// parent.aquire().create_value("child", child.clone()).unwrap();
+/// child.aquire().create_value("parent", parent.clone()).unwrap();
+/// assert_eq!(collector.find_cycles(), vec![vec![CycleLink::Property("child"), CycleLink::Property("parent")]]);
+/// let weak = parent.downgrade();
+/// drop(parent);
+/// drop(child);
+/// assert_eq!(collector.collect(&[]), 2);
+/// assert!(weak.upgrade().is_none());
+/// ```
+///
+pub struct CycleCollector<Key, S = HashMapStorage<Key>> {
+    objects: Vec<WeakDynObject<Key, S>>
+}
+
+/// returns the address identifying the inner object of `obj`
+fn address<Key, S>(obj: &DynObject<Key, S>) -> *const () {
+    &*obj.inner as *const _ as *const ()
+}
+
+impl<Key, S> CycleCollector<Key, S> where Key: Eq+Clone+'static, S: PropertyStorage<Key>+'static {
+
+    /// creates a collector without tracked objects
+    ///
+    pub fn new() -> CycleCollector<Key, S> {
+        CycleCollector {
+            objects: Vec::new()
+        }
+    }
+
+    /// adds `obj` to the objects considered by `collect`
+    ///
+    /// Tracking a object twice has no effect.
+    ///
+    pub fn track(&mut self, obj: &DynObject<Key, S>) {
+        let weak = obj.downgrade();
+        if !self.objects.iter().any(|other| other.ptr_eq(&weak)) {
+            self.objects.push(weak);
+        }
+    }
+
+    /// returns the number of tracked objects witch are still alive
+    ///
+    pub fn len(&self) -> usize {
+        self.objects.iter().filter(|obj| obj.strong_count() > 0).count()
+    }
+
+    /// returns true if no tracked object is alive
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// frees the unreachable tracked objects, returns how many were cleared
+    ///
+    /// The properties of the unreachable objects are removed without
+    /// calling guards or observers. Dropped objects are untracked. A object
+    /// witch is aquired is in use, so it is kept like a root.
+    ///
+    pub fn collect(&mut self, roots: &[DynObject<Key, S>]) -> usize {
+        let objects: Vec<DynObject<Key, S>> = self.objects.iter().filter_map(|obj| obj.upgrade()).collect();
+        self.objects = objects.iter().map(|obj| obj.downgrade()).collect();
+        let mut index = HashMap::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            index.insert(address(obj), idx);
+        }
+
+        //count the references between tracked objects, a object with more
+        //references is referenced from outside and therefore a root
+        let mut edges = Vec::with_capacity(objects.len());
+        let mut internal = vec![0usize; objects.len()];
+        let mut reachable = vec![false; objects.len()];
+        for (idx, obj) in objects.iter().enumerate() {
+            let mut targets = Vec::new();
+            match obj.try_aquire_ref() {
+                Ok(inner) => for_each_link(&*inner, |_, nested| match index.get(&address(nested)) {
+                    Some(&target) => targets.push(target),
+                    None => {}
+                }),
+                Err(_) => reachable[idx] = true
+            }
+            for &target in targets.iter() {
+                internal[target] += 1;
+            }
+            edges.push(targets);
+        }
+        let mut pending = Vec::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            //one strong reference is held by `objects`
+            if reachable[idx] || obj.strong_count() - 1 > internal[idx] || obj.try_aquire().is_err() {
+                pending.push(idx);
+            }
+        }
+        for root in roots.iter() {
+            match index.get(&address(root)) {
+                Some(&idx) => pending.push(idx),
+                None => {}
+            }
+        }
+
+        while let Some(idx) = pending.pop() {
+            reachable[idx] = true;
+            for &target in edges[idx].iter() {
+                if !reachable[target] {
+                    pending.push(target);
+                }
+            }
+        }
+
+        //the contents are dropped after all objects were cleared,
+        //so that no object is dropped while it is aquired
+        let mut garbage = Vec::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            if reachable[idx] {
+                continue;
+            }
+            //the object was not aquired when the roots were searched, so this does not fail
+            match obj.try_aquire() {
+                Ok(mut inner) => garbage.push(inner_dyn_object::take_contents(&mut *inner)),
+                Err(_) => {}
+            }
+        }
+        let collected = garbage.len();
+        drop(garbage);
+        drop(objects);
+        self.objects.retain(|obj| obj.strong_count() > 0);
+        collected
+    }
+
+    /// returns the cycles reachable from the tracked objects
+    ///
+    /// Each cycle is the list of links leading from the first object of the
+    /// cycle back to itself. Objects witch are aquired mutable are skipped.
+    ///
+    pub fn find_cycles(&self) -> Vec<Vec<CycleLink<Key>>> {
+        let mut cycles = Vec::new();
+        let mut done = HashSet::new();
+        for obj in self.objects.iter().filter_map(|obj| obj.upgrade()) {
+            search(&obj, &mut Vec::new(), &mut Vec::new(), &mut done, &mut cycles);
+        }
+        cycles
+    }
+}
+
+/// calls `func` for each `DynObject` referenced by `obj`
+fn for_each_link<Key, S, F>(obj: &inner_dyn_object::InnerDynObject<Key, S>, mut func: F)
+    where Key: Eq+Clone+'static, S: PropertyStorage<Key>+'static,
+          F: FnMut(CycleLink<Key>, &DynObject<Key, S>)
+{
+    for (key, prop) in inner_dyn_object::properties(obj).iter() {
+        match prop.as_ref::<DynObject<Key, S>>() {
+            Some(nested) => func(CycleLink::Property(key.clone()), nested),
+            None => {}
+        }
+    }
+    match obj.prototype() {
+        Some(prototype) => func(CycleLink::Prototype, prototype),
+        None => {}
+    }
+}
+
+/// depth first search for cycles, `stack` contains the objects of the current
+/// path and `path` the links between them
+fn search<Key, S>(obj: &DynObject<Key, S>, path: &mut Vec<CycleLink<Key>>, stack: &mut Vec<*const ()>,
+                  done: &mut HashSet<*const ()>, cycles: &mut Vec<Vec<CycleLink<Key>>>)
+    where Key: Eq+Clone+'static, S: PropertyStorage<Key>+'static
+{
+    let addr = address(obj);
+    match stack.iter().position(|&other| other == addr) {
+        Some(pos) => return cycles.push(path[pos..].to_vec()),
+        None => {}
+    }
+    if done.contains(&addr) {
+        return;
+    }
+    let inner = match obj.try_aquire_ref() {
+        Ok(inner) => inner,
+        Err(_) => return
+    };
+    stack.push(addr);
+    let mut links = Vec::new();
+    for_each_link(&*inner, |link, nested| links.push((link, nested.clone())));
+    for (link, nested) in links.into_iter() {
+        path.push(link);
+        search(&nested, path, stack, done, cycles);
+        path.pop();
+    }
+    stack.pop();
+    done.insert(addr);
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{CycleCollector, CycleLink};
+    use super::super::DynObject;
+
+    fn create_pair(collector: &mut CycleCollector<&'static str>) -> (DynObject<&'static str>, DynObject<&'static str>) {
+        let first = DynObject::new();
+        let second = DynObject::new();
+        collector.track(&first);
+        collector.track(&second);
+        first.aquire().create_value("next", second.clone()).unwrap();
+        second.aquire().create_value("next", first.clone()).unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn collect_should_keep_objects_referenced_from_outside() {
+        let mut collector = CycleCollector::new();
+        let (first, second) = create_pair(&mut collector);
+        let weak = second.downgrade();
+        drop(second);
+        assert_eq!(collector.collect(&[]), 0);
+        assert!(weak.upgrade().is_some());
+        assert_eq!(first.aquire().len(), 1);
+        drop(first);
+        assert_eq!(collector.collect(&[]), 2);
+        assert!(weak.upgrade().is_none());
+        assert!(collector.is_empty());
+    }
+
+    #[test]
+    fn collect_should_keep_objects_reachable_from_the_roots() {
+        let mut collector = CycleCollector::new();
+        let root = DynObject::new();
+        let (first, second) = create_pair(&mut collector);
+        root.aquire().create_value("first", first.clone()).unwrap();
+        let (other, _) = create_pair(&mut collector);
+        let weak = other.downgrade();
+        drop(other);
+        drop((first, second));
+        assert_eq!(collector.collect(&[root.clone()]), 2);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(collector.len(), 2);
+    }
+
+    #[test]
+    fn collect_should_keep_aquired_objects() {
+        let mut collector = CycleCollector::new();
+        let (first, second) = create_pair(&mut collector);
+        let weak = first.downgrade();
+        //the guard keeps `first` borrowed without a reference from outside
+        let nested = second.aquire().borrow_prop::<DynObject<&'static str>>(&"next").unwrap();
+        drop((first, second));
+        {
+            let _aquired = nested.aquire_ref();
+            assert_eq!(collector.collect(&[]), 0);
+        }
+        assert_eq!(collector.len(), 2);
+        drop(nested);
+        assert_eq!(collector.collect(&[]), 2);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn prototypes_should_be_followed() {
+        let mut collector = CycleCollector::<&'static str>::new();
+        let template = DynObject::new();
+        let obj = DynObject::with_prototype(template.clone());
+        template.aquire().create_value("instance", obj.clone()).unwrap();
+        collector.track(&obj);
+        collector.track(&template);
+        assert_eq!(collector.find_cycles(), vec![vec![CycleLink::Prototype, CycleLink::Property("instance")]]);
+        let weak = obj.downgrade();
+        drop(obj);
+        drop(template);
+        assert_eq!(collector.collect(&[]), 2);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn track_should_ignore_duplicates_and_dropped_objects() {
+        let mut collector = CycleCollector::<&'static str>::new();
+        let obj = DynObject::new();
+        collector.track(&obj);
+        collector.track(&obj.clone());
+        assert_eq!(collector.len(), 1);
+        {
+            let other = DynObject::new();
+            collector.track(&other);
+            assert_eq!(collector.len(), 2);
+        }
+        assert_eq!(collector.len(), 1);
+        assert_eq!(collector.collect(&[]), 0);
+        assert!(obj.aquire_ref().is_empty());
+    }
+
+    #[test]
+    fn find_cycles_should_report_nested_paths() {
+        let mut collector = CycleCollector::new();
+        let root = DynObject::<&'static str>::new();
+        collector.track(&root);
+        root.create_path(&["a", "b"], 1u8).unwrap();
+        assert!(collector.find_cycles().is_empty());
+        let a = root.aquire()["a"].as_ref::<DynObject<&'static str>>().unwrap().clone();
+        a.aquire().create_value("up", root.clone()).unwrap();
+        assert_eq!(collector.find_cycles(), vec![vec![CycleLink::Property("a"), CycleLink::Property("up")]]);
+    }
+}
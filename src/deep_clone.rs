@@ -0,0 +1,51 @@
+use std::error::Error;
+use std::fmt;
+
+
+/// decides what `deep_clone` does with properties witch can not be cloned
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClonePolicy {
+    /// fail with `DeepCloneError::NotCloneable` (or `Rejected`)
+    Fail,
+    /// leave the property out of the clone
+    Skip
+}
+
+/// error returned by `deep_clone`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum DeepCloneError<Key> {
+    /// the property with the given key was not created with clone support
+    NotCloneable(Key),
+    /// the property (or nested object) with the given key is currently borrowed mutable
+    Borrowed(Key),
+    /// the nested object with the given key contains itself
+    Cycle(Key),
+    /// a guard does not allow to access the property with the given key
+    Rejected(Key),
+    /// the object to clone is currently aquired mutable
+    Aquired
+}
+
+impl<Key> fmt::Display for DeepCloneError<Key> where Key: fmt::Debug {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeepCloneError::NotCloneable(ref key) => write!(fter, "property {:?} is not cloneable", key),
+            DeepCloneError::Borrowed(ref key) => write!(fter, "property {:?} is borrowed", key),
+            DeepCloneError::Cycle(ref key) => write!(fter, "property {:?} contains a cycle", key),
+            DeepCloneError::Rejected(ref key) => write!(fter, "access to property {:?} was rejected", key),
+            DeepCloneError::Aquired => fter.write_str(self.description())
+        }
+    }
+}
+
+impl<Key> Error for DeepCloneError<Key> where Key: fmt::Debug {
+    fn description(&self) -> &str {
+        match *self {
+            DeepCloneError::NotCloneable(_) => "property is not cloneable",
+            DeepCloneError::Borrowed(_) => "property is borrowed",
+            DeepCloneError::Cycle(_) => "nested objects contain a cycle",
+            DeepCloneError::Rejected(_) => "access to property was rejected",
+            DeepCloneError::Aquired => "DynObject is aquired mutable"
+        }
+    }
+}
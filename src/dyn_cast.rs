@@ -0,0 +1,308 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::dyn_property::{self, DynProperty};
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::PropertyStorage;
+use super::DynObject;
+
+
+/// the casts of a value of type `T` to the trait object `Trait`
+///
+/// The casts are ordinary functions, because the compiler can only
+/// create a trait object if the concrete type is known. Use the
+/// `dyn_cast!` macro to create them.
+///
+pub struct DynCast<T, Trait: ?Sized> {
+    cast_ref: fn(&T) -> &Trait,
+    cast_mut: fn(&mut T) -> &mut Trait
+}
+
+impl<T, Trait: ?Sized> DynCast<T, Trait> {
+
+    /// creates a `DynCast` from the given functions
+    ///
+    pub fn new(cast_ref: fn(&T) -> &Trait, cast_mut: fn(&mut T) -> &mut Trait) -> DynCast<T, Trait> {
+        DynCast {
+            cast_ref: cast_ref,
+            cast_mut: cast_mut
+        }
+    }
+}
+
+impl<T, Trait: ?Sized> Clone for DynCast<T, Trait> {
+    fn clone(&self) -> DynCast<T, Trait> {
+        DynCast::new(self.cast_ref, self.cast_mut)
+    }
+}
+
+impl<T, Trait: ?Sized> Copy for DynCast<T, Trait> {}
+
+/// a `DynCast` with the concrete type erased
+trait ErasedCast<Trait: ?Sized> {
+    fn cast_ref<'a>(&self, value: &'a Any) -> Option<&'a Trait>;
+    fn cast_mut<'a>(&self, value: &'a mut Any) -> Option<&'a mut Trait>;
+}
+
+impl<T: Any, Trait: ?Sized> ErasedCast<Trait> for DynCast<T, Trait> {
+
+    fn cast_ref<'a>(&self, value: &'a Any) -> Option<&'a Trait> {
+        value.downcast_ref::<T>().map(|value| (self.cast_ref)(value))
+    }
+
+    fn cast_mut<'a>(&self, value: &'a mut Any) -> Option<&'a mut Trait> {
+        value.downcast_mut::<T>().map(|value| (self.cast_mut)(value))
+    }
+}
+
+/// a cast captured by a property, the `Any` is a `Box<ErasedCast<Trait>>`
+#[derive(Clone)]
+pub struct CastEntry {
+    target: TypeId,
+    cast: Rc<Box<Any>>
+}
+
+/// adds `cast` to `casts`, replacing a cast to the same trait
+pub fn add_cast<T, Trait: ?Sized>(casts: &mut Vec<CastEntry>, cast: DynCast<T, Trait>)
+    where T: Any, Trait: 'static
+{
+    let erased: Box<ErasedCast<Trait>> = Box::new(cast);
+    add_entry(casts, CastEntry {
+        target: TypeId::of::<Trait>(),
+        cast: Rc::new(Box::new(erased) as Box<Any>)
+    });
+}
+
+fn add_entry(casts: &mut Vec<CastEntry>, entry: CastEntry) {
+    casts.retain(|other| other.target != entry.target);
+    casts.push(entry);
+}
+
+/// returns the entry of `casts` casting to `Trait`
+pub fn find<Trait: ?Sized+'static>(casts: &[CastEntry]) -> Option<&CastEntry> {
+    casts.iter().find(|entry| entry.target == TypeId::of::<Trait>())
+}
+
+/// casts `value` to `Trait`, `entry` has to be a cast to `Trait`
+pub fn cast_ref<'a, Trait: ?Sized+'static>(entry: &CastEntry, value: &'a Any) -> Option<&'a Trait> {
+    entry.cast.downcast_ref::<Box<ErasedCast<Trait>>>().unwrap().cast_ref(value)
+}
+
+/// casts `value` mutable to `Trait`, `entry` has to be a cast to `Trait`
+pub fn cast_mut<'a, Trait: ?Sized+'static>(entry: &CastEntry, value: &'a mut Any) -> Option<&'a mut Trait> {
+    entry.cast.downcast_ref::<Box<ErasedCast<Trait>>>().unwrap().cast_mut(value)
+}
+
+
+/// the trait casts of types, witch can be attached to properties
+///
+/// A property can only be viewed as trait object (`DynProperty::as_dyn`)
+/// if it captured a cast for the trait, either at creation
+/// (`DynProperty::with_cast`), by `DynProperty::add_cast` or by a
+/// `CastRegistry`. The registry holds casts per type, `attach` lets all
+/// properties of a object capture the casts of their type. Properties
+/// created later do not capture them, `DynProperty::as_dyn_with` and
+/// `InnerDynObject::iter_dyn_with` look up a cast in the registry if
+/// the property did not capture one.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use] extern crate dynobject;
+/// # use std::fmt::Display;
+/// # use dynobject::{InnerDynObject, CastRegistry};
+/// # fn main() {
+/// let mut registry = CastRegistry::new();
+/// registry.register(dyn_cast!(u32 => Display));
+/// registry.register(dyn_cast!(String => Display));
+/// let mut obj = InnerDynObject::<&'static str>::new();
+/// obj.create_value("count", 3u32).unwrap();
+/// obj.create_value("name", "hero".to_string()).unwrap();
+/// obj.create_value("flags", vec![true]).unwrap();
+/// registry.attach(&mut obj);
+/// assert_eq!(obj["count"].as_dyn::<Display>().unwrap().to_string(), "3");
+/// let mut shown: Vec<String> = obj.iter_dyn::<Display>()
+///                                 .map(|(key, value)| format!("{}={}", key, value)).collect();
+/// shown.sort();
+/// assert_eq!(shown, vec!["count=3".to_string(), "name=hero".to_string()]);
+/// # }
+/// ```
+///
+pub struct CastRegistry {
+    by_type: HashMap<TypeId, Vec<CastEntry>>
+}
+
+impl CastRegistry {
+
+    /// creates a empty CastRegistry
+    ///
+    pub fn new() -> CastRegistry {
+        CastRegistry {
+            by_type: HashMap::new()
+        }
+    }
+
+    /// registers a cast of `T` to `Trait`
+    ///
+    /// A cast registered before for the same types is replaced.
+    ///
+    pub fn register<T, Trait: ?Sized>(&mut self, cast: DynCast<T, Trait>)
+        where T: Any, Trait: 'static
+    {
+        add_cast(self.by_type.entry(TypeId::of::<T>()).or_insert_with(Vec::new), cast);
+    }
+
+    /// returns true if a cast of `T` to `Trait` is registered
+    ///
+    pub fn can_cast<T: Any, Trait: ?Sized+'static>(&self) -> bool {
+        registered::<Trait>(self, TypeId::of::<T>()).is_some()
+    }
+
+    /// lets all properties of `obj` capture the casts of their type
+    ///
+    /// Nested objects are attached too, if they are not aquired at the moment.
+    ///
+    pub fn attach<Key, S>(&self, obj: &mut InnerDynObject<Key, S>)
+        where Key: Eq+'static, S: PropertyStorage<Key>+'static
+    {
+        for (_, prop) in inner_dyn_object::properties_mut(obj).iter_mut() {
+            self.attach_property(prop);
+            match prop.as_ref::<DynObject<Key, S>>() {
+                Some(nested) => match nested.try_aquire() {
+                    Ok(mut nested) => self.attach(&mut *nested),
+                    Err(_) => {}
+                },
+                None => {}
+            }
+        }
+    }
+
+    /// lets a property capture the casts of its type
+    ///
+    /// Casts the property captured before are replaced if the registry
+    /// has a cast to the same trait. Returns false if no cast is
+    /// registered for the type of the property.
+    ///
+    pub fn attach_property(&self, prop: &mut DynProperty) -> bool {
+        match self.by_type.get(&dyn_property::inner_type_id(prop)) {
+            Some(casts) => {
+                for entry in casts.iter() {
+                    add_entry(dyn_property::casts_mut(prop), entry.clone());
+                }
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/// returns the registered cast of the type `type_id` to `Trait`
+pub fn registered<Trait: ?Sized+'static>(registry: &CastRegistry, type_id: TypeId) -> Option<&CastEntry> {
+    match registry.by_type.get(&type_id) {
+        Some(casts) => find::<Trait>(casts),
+        None => None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Display;
+    use super::CastRegistry;
+    use super::super::dyn_property::DynProperty;
+    use super::super::inner_dyn_object::InnerDynObject;
+    use super::super::DynObject;
+
+    trait Shape {
+        fn area(&self) -> u32;
+        fn scale(&mut self, factor: u32);
+    }
+
+    struct Square(u32);
+
+    impl Shape for Square {
+        fn area(&self) -> u32 { self.0 * self.0 }
+        fn scale(&mut self, factor: u32) { self.0 *= factor }
+    }
+
+    struct Rect(u32, u32);
+
+    impl Shape for Rect {
+        fn area(&self) -> u32 { self.0 * self.1 }
+        fn scale(&mut self, factor: u32) { self.0 *= factor; self.1 *= factor }
+    }
+
+    #[test]
+    fn properties_should_only_use_captured_casts() {
+        let mut prop = DynProperty::from_value(Square(2));
+        assert!(prop.as_dyn::<Shape>().is_none());
+        assert!(!prop.add_cast(dyn_cast!(Rect => Shape)));
+        assert!(prop.add_cast(dyn_cast!(Square => Shape)));
+        assert!(prop.has_cast::<Shape>());
+        assert!(!prop.has_cast::<Display>());
+        prop.as_dyn_mut::<Shape>().unwrap().scale(3);
+        assert_eq!(prop.as_dyn::<Shape>().unwrap().area(), 36);
+        assert_eq!(prop.as_ref::<Square>().unwrap().0, 6);
+    }
+
+    #[test]
+    fn casts_should_not_be_used_while_borrowed_mutable() {
+        let mut prop = DynProperty::from_value(Square(2));
+        prop.add_cast(dyn_cast!(Square => Shape));
+        let guard = prop.borrow_mut::<Square>().unwrap();
+        assert!(prop.as_dyn::<Shape>().is_none());
+        drop(guard);
+        assert!(prop.as_dyn::<Shape>().is_some());
+    }
+
+    #[test]
+    fn attach_should_add_the_casts_to_nested_objects() {
+        let mut registry = CastRegistry::new();
+        registry.register(dyn_cast!(Square => Shape));
+        registry.register(dyn_cast!(Rect => Shape));
+        assert!(registry.can_cast::<Rect, Shape>());
+        assert!(!registry.can_cast::<u8, Shape>());
+        let nested = DynObject::new();
+        nested.aquire().create_value("rect", Rect(2, 5)).unwrap();
+        let mut obj = InnerDynObject::<&'static str>::new();
+        obj.create_value("square", Square(3)).unwrap();
+        obj.create_value("nested", nested.clone()).unwrap();
+        registry.attach(&mut obj);
+        for (_, mut shape) in obj.iter_dyn_mut::<Shape>() {
+            shape.scale(2);
+        }
+        let total = obj.iter_dyn::<Shape>().fold(0, |total, (_, shape)| total + shape.area());
+        assert_eq!(total, 36);
+        assert_eq!(nested.aquire()["rect"].as_dyn::<Shape>().unwrap().area(), 10);
+    }
+
+    #[test]
+    fn properties_should_fall_back_to_the_registry() {
+        let mut registry = CastRegistry::new();
+        registry.register(dyn_cast!(Rect => Shape));
+        let mut obj = InnerDynObject::<&'static str>::new();
+        registry.attach(&mut obj);
+        //created after attaching, so the properties did not capture the casts
+        obj.insert_property("square", DynProperty::with_cast(Box::new(Square(3)), dyn_cast!(Square => Shape))).unwrap();
+        obj.create_value("rect", Rect(2, 5)).unwrap();
+        obj.create_value("count", 1u8).unwrap();
+        assert!(obj["rect"].as_dyn::<Shape>().is_none());
+        assert_eq!(obj["rect"].as_dyn_with::<Shape>(&registry).unwrap().area(), 10);
+        assert_eq!(obj.iter_dyn::<Shape>().count(), 1);
+        for (_, mut shape) in obj.iter_dyn_mut_with::<Shape>(&registry) {
+            shape.scale(2);
+        }
+        let total = obj.iter_dyn_with::<Shape>(&registry).fold(0, |total, (_, shape)| total + shape.area());
+        assert_eq!(total, 76);
+        assert!(obj["count"].as_dyn_with::<Shape>(&registry).is_none());
+    }
+
+    #[test]
+    fn clones_should_keep_the_casts() {
+        let mut prop = DynProperty::new_cloneable(Box::new(12u8));
+        prop.add_cast(dyn_cast!(u8 => Display));
+        let clone = prop.try_clone().unwrap();
+        assert_eq!(format!("{}", clone.as_dyn::<Display>().unwrap()), "12");
+    }
+}
@@ -1,7 +1,17 @@
 use std::result::Result;
 use std::mem;
-use std::any::Any;
+use std::rc::{self, Rc};
+use std::any::{Any, TypeId};
 use std::boxed::BoxAny;
+use std::fmt;
+
+use super::prop_guard::{self, SharedValue, PropRef, PropMut};
+use super::inline_value::{self, InlineValue};
+use super::property_error::PropertyError;
+use super::conversion::{self, ConversionRegistry};
+use super::dyn_cast::{self, DynCast, CastEntry, CastRegistry};
+#[cfg(feature = "serialize")]
+use super::serialize::EncodeInfo;
 
 
 /// The `DynProperty` is a Wrapper around `Box<Any>` 
@@ -10,13 +20,136 @@ use std::boxed::BoxAny;
 /// inner `Any` Data over typed generic methodes makign 
 /// the inner implementation around `Any` complety transparent
 ///
+/// Small values (up to 3 words with a alignment not bigger than
+/// `usize`) are stored inline instead of being boxed. Values passed
+/// as `Box<T>` are moved out of the box in this case, use `from_value`
+/// to avoid the allocation of the box completely. The functions of the
+/// inner type are kept in a static vtable shared by all properties of
+/// the type, captured casts and encoding support are only allocated if
+/// a property has them.
+///
 /// Note that a `DynProperty` has allways the same inner time 
 /// after creation. E.g. if it is initialised with a `Vec<i32>`
-/// it will allways contains a `Vec<i32>` until destructed
+/// it will allways contains a `Vec<i32>` until destructed or
+/// converted with `convert_in_place`
+///
+/// The inner value can also be borrowed on its own with `borrow_ref`
+/// and `borrow_mut`. The returned guards do not borrow the property
+/// and therefor not the object containing it. While such a guard exists
+/// the borrow is checked at runtime like it is done by `RefCell`.
 ///
+/// The `Debug` output of a `DynProperty` contains the name of the
+/// inner type. If the property was created with `new_debug` it also
+/// contains the inner value.
+///
+/// A property can only be cloned (`try_clone`) if it was created with
+/// `new_cloneable`, because the inner type is not known otherwise.
+/// For the same reason a property can only be viewed as trait
+/// object (`as_dyn`) if it captured a cast with `with_cast`, `add_cast`
+/// or from a `CastRegistry`, or with `as_dyn_with` if the registry has one.
+/// It can only be encoded if it captured the encoding support of a
+/// `TypeRegistry`.
 ///
 pub struct DynProperty {
-    value: Box<Any+'static>
+    type_id: TypeId,
+    vtable: &'static VTable,
+    extra: Option<Box<Extra>>,
+    value: Storage
+}
+
+/// the functions of a inner type, one static instance per type
+/// and captured debug/clone support
+struct VTable {
+    type_name: fn() -> &'static str,
+    as_any: inline_value::AsAnyFn,
+    into_box: inline_value::IntoBoxFn,
+    drop_value: inline_value::DropFn,
+    from_any: fn(Box<Any+'static>) -> Storage,
+    debug: Option<DebugFn>,
+    clone: Option<CloneFn>
+}
+
+//the literal is promoted to a static, so every call returns the same vtable
+macro_rules! vtable {
+    ($T:ty, $debug:expr, $clone:expr) => (
+        &VTable {
+            type_name: type_name::<$T>,
+            as_any: inline_value::as_any::<$T>,
+            into_box: inline_value::into_box::<$T>,
+            drop_value: inline_value::drop_value::<$T>,
+            from_any: storage_from_any::<$T>,
+            debug: $debug,
+            clone: $clone
+        }
+    )
+}
+
+fn vtable<T: Any>() -> &'static VTable {
+    vtable!(T, None, None)
+}
+
+fn debug_vtable<T: Any+fmt::Debug>() -> &'static VTable {
+    vtable!(T, Some(debug_value::<T> as DebugFn), None)
+}
+
+fn cloneable_vtable<T: Any+Clone>() -> &'static VTable {
+    vtable!(T, None, Some(clone_value::<T> as CloneFn))
+}
+
+/// the parts of a property most properties do not use
+#[derive(Clone)]
+struct Extra {
+    casts: Vec<CastEntry>,
+    #[cfg(feature = "serialize")]
+    encode: Option<EncodeInfo>
+}
+
+/// formats the inner value, captured by `DynProperty::new_debug`
+type DebugFn = fn(&Any, &mut fmt::Formatter) -> fmt::Result;
+
+fn debug_value<T: Any+fmt::Debug>(value: &Any, fter: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(value.downcast_ref::<T>().unwrap(), fter)
+}
+
+/// clones the inner value, captured by `DynProperty::new_cloneable`
+type CloneFn = fn(&Any) -> Storage;
+
+fn clone_value<T: Any+Clone>(value: &Any) -> Storage {
+    Storage::from_value(value.downcast_ref::<T>().unwrap().clone())
+}
+
+/// stores a value of the inner type `T` taken from a `Box<Any>`, used by `with_value`
+fn storage_from_any<T: Any>(value: Box<Any+'static>) -> Storage {
+    Storage::from_box(value.downcast::<T>().ok().expect("value of a different type"))
+}
+
+/// the value of a property, it is moved into a `SharedValue`
+/// the first time it is borrowed with `borrow_ref`/`borrow_mut`
+///
+/// A inline value is droped by the property, as only its vtable knows the type.
+enum Storage {
+    Inline(InlineValue),
+    Owned(Box<Any+'static>),
+    Shared(Rc<SharedValue>)
+}
+
+impl Storage {
+
+    fn from_value<T: Any>(value: T) -> Storage {
+        if inline_value::fits::<T>() {
+            Storage::Inline(InlineValue::new(value))
+        } else {
+            Storage::Owned(Box::new(value))
+        }
+    }
+
+    fn from_box<T: Any>(value: Box<T>) -> Storage {
+        if inline_value::fits::<T>() {
+            Storage::Inline(InlineValue::new(*value))
+        } else {
+            Storage::Owned(value)
+        }
+    }
 }
 
 impl DynProperty {
@@ -24,7 +157,186 @@ impl DynProperty {
     /// creats a new DynProperty with given initial value
     ///
     pub fn new<T: Any>(initial_value: Box<T>) -> DynProperty {
-        DynProperty { value: initial_value }
+        DynProperty::with_storage::<T>(vtable::<T>(), Storage::from_box(initial_value))
+    }
+
+    /// creats a new DynProperty with given initial value, taken by value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let prop = DynProperty::from_value(12u32);
+    /// assert_eq!(prop.as_ref::<u32>(), Some(&12u32));
+    /// ```
+    ///
+    pub fn from_value<T: Any>(initial_value: T) -> DynProperty {
+        DynProperty::with_storage::<T>(vtable::<T>(), Storage::from_value(initial_value))
+    }
+
+    fn with_storage<T: Any>(vtable: &'static VTable, storage: Storage) -> DynProperty {
+        DynProperty {
+            type_id: TypeId::of::<T>(),
+            vtable: vtable,
+            extra: None,
+            value: storage
+        }
+    }
+
+    /// creats a new DynProperty witch includes its value in the `Debug` output
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let prop = DynProperty::new_debug(Box::new(12u8));
+    /// assert_eq!(format!("{:?}", prop), "DynProperty(u8 = 12)");
+    /// ```
+    ///
+    pub fn new_debug<T: Any+fmt::Debug>(initial_value: Box<T>) -> DynProperty {
+        DynProperty::with_storage::<T>(debug_vtable::<T>(), Storage::from_box(initial_value))
+    }
+
+    /// creats a new DynProperty witch can be cloned with `try_clone`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let prop = DynProperty::new_cloneable(Box::new(vec![1u8, 2]));
+    /// let clone = prop.try_clone().unwrap();
+    /// assert_eq!(clone.as_ref::<Vec<u8>>(), Some(&vec![1u8, 2]));
+    /// ```
+    ///
+    pub fn new_cloneable<T: Any+Clone>(initial_value: Box<T>) -> DynProperty {
+        DynProperty::with_storage::<T>(cloneable_vtable::<T>(), Storage::from_box(initial_value))
+    }
+
+    /// creats a new DynProperty witch can be cloned, taken by value
+    ///
+    /// Works like `new_cloneable` without boxing the value.
+    ///
+    pub fn from_cloneable_value<T: Any+Clone>(initial_value: T) -> DynProperty {
+        DynProperty::with_storage::<T>(cloneable_vtable::<T>(), Storage::from_value(initial_value))
+    }
+
+    /// creats a new DynProperty witch captured the cast of its value to `Trait`
+    ///
+    /// More casts can be added with `add_cast`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dynobject;
+    /// # use std::fmt::Display;
+    /// # use dynobject::DynProperty;
+    /// # fn main() {
+    /// let prop = DynProperty::with_cast(Box::new(12u32), dyn_cast!(u32 => Display));
+    /// assert_eq!(prop.as_dyn::<Display>().unwrap().to_string(), "12");
+    /// # }
+    /// ```
+    ///
+    pub fn with_cast<T, Trait: ?Sized>(initial_value: Box<T>, cast: DynCast<T, Trait>) -> DynProperty
+        where T: Any, Trait: 'static
+    {
+        let mut prop = DynProperty::new(initial_value);
+        dyn_cast::add_cast(&mut prop.extra_mut().casts, cast);
+        prop
+    }
+
+    /// returns true if the property was created with clone support
+    ///
+    pub fn is_cloneable(&self) -> bool {
+        self.vtable.clone.is_some()
+    }
+
+    /// clones the property including its inner value
+    ///
+    /// Returns None if the property was not created with `new_cloneable`
+    /// or the inner value is currently borrowed mutable. The clone keeps
+    /// the captured clone (and debug/cast/encoding) support.
+    ///
+    pub fn try_clone(&self) -> Option<DynProperty> {
+        let clone = match self.vtable.clone {
+            Some(clone) => clone,
+            None => return None
+        };
+        self.inner_ref().map(|value| DynProperty {
+            type_id: self.type_id,
+            vtable: self.vtable,
+            extra: self.extra.clone(),
+            value: clone(value)
+        })
+    }
+
+    /// returns the name of the inner type
+    ///
+    /// The name is recorded when the property is created, it is meant
+    /// for debugging and error messages only.
+    ///
+    pub fn type_name(&self) -> &'static str {
+        (self.vtable.type_name)()
+    }
+
+    /// returns the captured casts and encoding support, allocating them if needed
+    fn extra_mut(&mut self) -> &mut Extra {
+        if self.extra.is_none() {
+            self.extra = Some(Box::new(Extra {
+                casts: Vec::new(),
+                #[cfg(feature = "serialize")]
+                encode: None
+            }));
+        }
+        &mut **self.extra.as_mut().unwrap()
+    }
+
+    fn casts(&self) -> &[CastEntry] {
+        match self.extra {
+            Some(ref extra) => &extra.casts,
+            None => &[]
+        }
+    }
+
+    /// returns the inner value if it is not borrowed mutable
+    fn inner_ref(&self) -> Option<&Any> {
+        match self.value {
+            Storage::Inline(ref value) => Some(unsafe { value.get(self.vtable.as_any) }),
+            Storage::Owned(ref value) => Some(&**value),
+            Storage::Shared(ref shared) => shared.get()
+        }
+    }
+
+    /// returns the inner value if it is not borrowed at all
+    fn inner_mut(&mut self) -> Option<&mut Any> {
+        match self.value {
+            Storage::Inline(ref mut value) => Some(unsafe { value.get_mut(self.vtable.as_any) }),
+            Storage::Owned(ref mut value) => Some(&mut **value),
+            //we hold self mutable so no new guard can be created
+            Storage::Shared(ref shared) => unsafe { shared.get_mut() }.map(|value| &mut **value)
+        }
+    }
+
+    /// moves the value out, leaving a placeholder witch needs no vtable to be droped
+    fn take_value(&mut self) -> Storage {
+        mem::replace(&mut self.value, Storage::Owned(Box::new(())))
+    }
+
+    /// replaces the value, droping the old one
+    fn replace_value(&mut self, storage: Storage) {
+        if let Storage::Inline(mut old) = mem::replace(&mut self.value, storage) {
+            unsafe { old.drop_value(self.vtable.drop_value) }
+        }
+    }
+
+    /// moves the value into a `SharedValue` if this wasn't done before
+    fn share(&mut self) -> Rc<SharedValue> {
+        let shared = match self.take_value() {
+            Storage::Inline(value) => Rc::new(SharedValue::new(unsafe { value.into_box(self.vtable.into_box) })),
+            Storage::Owned(value) => Rc::new(SharedValue::new(value)),
+            Storage::Shared(shared) => shared
+        };
+        self.value = Storage::Shared(shared.clone());
+        shared
     }
     
 
@@ -33,8 +345,9 @@ impl DynProperty {
     /// This methodes checks if the given new vale has the same type
     /// then the current value if so it will replace the current value
     /// with the new value and return the now old value as `Ok(Box(T))`.
-    /// If this fails it will return the new value as `Err(Box(T))` so
-    /// that it will not be lose.
+    /// If this fails it will return a `PropertyError::TypeMismatch` or,
+    /// if the property is currently borrowed, a `PropertyError::Borrowed`
+    /// containing the new value so that it will not be lose.
     ///
     /// # Examples
     ///
@@ -43,32 +356,57 @@ impl DynProperty {
     /// let mut prop = DynProperty::new(Box::new(123i32));
     /// match prop.set(Box::new(321i32)) {
     ///     Ok(old) => assert_eq!(*old, 123i32),
-    ///     Err(value) => panic!("wont happen here")
+    ///     Err(err) => panic!("wont happen here")
     /// }
     /// match prop.set(Box::new("hallo")) {
     ///     Ok(old) => panic!("wont happen, given data has a  different type"),
-    ///     Err(data) => assert_eq!(*data, "hallo")
+    ///     Err(err) => assert_eq!(*err.into_value(), "hallo")
     /// }
     /// ```
     ///
-    pub fn set<T>(&mut self, value: Box<T>) -> Result<Box<T>,Box<T>> 
+    pub fn set<T>(&mut self, value: Box<T>) -> Result<Box<T>,PropertyError<Box<T>>> 
         where T: Any+'static
     {
-        if self.is_inner_type::<T>() {
-            let mut any_boxed = value as Box<Any+'static>;
-            mem::swap(&mut any_boxed, &mut self.value);
-            Ok(any_boxed.downcast::<T>().unwrap())
-        } else {
-            Err(value)
-        }   
-            
+        if !self.is_inner_type::<T>() {
+            return Err(type_mismatch::<T, _>(self, value));
+        }
+        match self.inner_mut() {
+            Some(inner) => Ok(Box::new(mem::replace(inner.downcast_mut::<T>().unwrap(), *value))),
+            None => Err(PropertyError::Borrowed(value))
+        }
+    }
+
+    /// replaces the current inner value with a new one, taken by value
+    ///
+    /// Works like `set` without boxing the values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let mut prop = DynProperty::from_value(123i32);
+    /// assert_eq!(prop.set_value(321i32).ok(), Some(123i32));
+    /// assert_eq!(prop.set_value(1u8).err().unwrap().into_value(), 1u8);
+    /// ```
+    ///
+    pub fn set_value<T>(&mut self, value: T) -> Result<T,PropertyError<T>>
+        where T: Any+'static
+    {
+        if !self.is_inner_type::<T>() {
+            return Err(type_mismatch::<T, _>(self, value));
+        }
+        match self.inner_mut() {
+            Some(inner) => Ok(mem::replace(inner.downcast_mut::<T>().unwrap(), value)),
+            None => Err(PropertyError::Borrowed(value))
+        }
     }
 
     /// return a referenc to the inner Data if possible 
     ///
     /// If the given type is the same as the inner type 
     /// return a reference to the inner data (typed) wrapped
-    /// into Some, else return None
+    /// into Some, else return None. None is also returned
+    /// if the property is currently borrowed mutable.
     ///
     /// # Examples
     ///
@@ -84,14 +422,18 @@ impl DynProperty {
     pub fn as_ref<'a, T>(&'a self) -> Option<&'a T> 
         where T: Any + 'static 
     {
-        self.value.downcast_ref()
+        match self.inner_ref() {
+            Some(value) => value.downcast_ref(),
+            None => None
+        }
     }
 
     /// return a mutable reference to the inner data if possible
     ///
     /// If the given type is the same as the inner type
     /// return a typed mutable reference to the inner data wrapped
-    /// into Some. If not valide return None
+    /// into Some. If not valide or the property is currently
+    /// borrowed return None
     /// 
     /// # Examples
     ///
@@ -103,13 +445,245 @@ impl DynProperty {
     /// ```
     ///
     pub fn as_mut<'a, T: Any>(&'a mut self) -> Option<&'a mut T> {
-        self.value.downcast_mut::<T>()
+        match self.inner_mut() {
+            Some(value) => value.downcast_mut::<T>(),
+            None => None
+        }
     }
     
+    /// returns a copy of the inner value converted to `T`
+    ///
+    /// If the inner type is `T` the value is cloned, else a conversion
+    /// registered in `registry` is used. None is returned if there is
+    /// no such conversion, it fails or the value is borrowed mutable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::{DynProperty, ConversionRegistry};
+    /// let registry = ConversionRegistry::new();
+    /// let prop = DynProperty::from_value(12u32);
+    /// assert_eq!(prop.get_as::<f64>(&registry), Some(12f64));
+    /// assert_eq!(prop.get_as::<u8>(&registry), None);
+    /// ```
+    ///
+    pub fn get_as<T: Any+Clone>(&self, registry: &ConversionRegistry) -> Option<T> {
+        match self.as_ref::<T>() {
+            Some(value) => Some(value.clone()),
+            None => conversion::convert::<T>(registry, self)
+                .and_then(|prop| prop.destruct::<T>())
+                .map(|value| *value)
+        }
+    }
+
+    /// converts the inner value to `T`, changing the type of the property
+    ///
+    /// Does nothing if the inner type already is `T`. The converted
+    /// property does not keep the captured clone, debug or cast
+    /// support. If the value is borrowed a `PropertyError::Borrowed` is
+    /// returned, if `registry` has no conversion or it fails a
+    /// `PropertyError::TypeMismatch`. The property is unchanged on errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::{DynProperty, ConversionRegistry};
+    /// let registry = ConversionRegistry::new();
+    /// let mut prop = DynProperty::from_value("12".to_string());
+    /// prop.convert_in_place::<i64>(&registry).unwrap();
+    /// assert_eq!(prop.as_ref::<i64>(), Some(&12i64));
+    /// assert!(prop.convert_in_place::<u8>(&registry).is_err());
+    /// ```
+    ///
+    pub fn convert_in_place<T: Any>(&mut self, registry: &ConversionRegistry) -> Result<(), PropertyError<()>> {
+        if self.is_inner_type::<T>() {
+            return Ok(());
+        }
+        if self.is_borrowed() {
+            return Err(PropertyError::Borrowed(()));
+        }
+        match conversion::convert::<T>(registry, self) {
+            Some(prop) => {
+                *self = prop;
+                Ok(())
+            },
+            None => Err(type_mismatch::<T, ()>(self, ()))
+        }
+    }
+
+    /// lets the property capture a cast of its value to the trait object `Trait`
+    ///
+    /// A cast to `Trait` captured before is replaced. Returns false,
+    /// without changing the property, if `T` is not the inner type.
+    /// Use the `dyn_cast!` macro to create the `DynCast`.
+    ///
+    pub fn add_cast<T, Trait: ?Sized>(&mut self, cast: DynCast<T, Trait>) -> bool
+        where T: Any, Trait: 'static
+    {
+        if !self.is_inner_type::<T>() {
+            return false;
+        }
+        dyn_cast::add_cast(&mut self.extra_mut().casts, cast);
+        true
+    }
+
+    /// returns true if the property captured a cast to `Trait`
+    ///
+    pub fn has_cast<Trait: ?Sized+'static>(&self) -> bool {
+        dyn_cast::find::<Trait>(self.casts()).is_some()
+    }
+
+    /// returns the inner value as trait object
+    ///
+    /// Returns None if the property did not capture a cast to `Trait`
+    /// or the value is borrowed mutable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dynobject;
+    /// # use std::fmt::Debug;
+    /// # use dynobject::DynProperty;
+    /// # fn main() {
+    /// let mut prop = DynProperty::from_value(vec![1u8]);
+    /// assert!(prop.as_dyn::<Debug>().is_none());
+    /// prop.add_cast(dyn_cast!(Vec<u8> => Debug));
+    /// assert_eq!(format!("{:?}", prop.as_dyn::<Debug>().unwrap()), "[1]");
+    /// # }
+    /// ```
+    ///
+    pub fn as_dyn<Trait: ?Sized+'static>(&self) -> Option<&Trait> {
+        cast_ref(self, None)
+    }
+
+    /// returns the inner value as mutable trait object
+    ///
+    /// Returns None if the property did not capture a cast to `Trait`
+    /// or the value is borrowed.
+    ///
+    pub fn as_dyn_mut<Trait: ?Sized+'static>(&mut self) -> Option<&mut Trait> {
+        cast_mut(self, None)
+    }
+
+    /// returns the inner value as trait object, using the cast to `Trait`
+    /// registered in `registry` if the property did not capture one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dynobject;
+    /// # use std::fmt::Display;
+    /// # use dynobject::{DynProperty, CastRegistry};
+    /// # fn main() {
+    /// let mut registry = CastRegistry::new();
+    /// registry.register(dyn_cast!(u32 => Display));
+    /// let prop = DynProperty::from_value(12u32);
+    /// assert!(prop.as_dyn::<Display>().is_none());
+    /// assert_eq!(prop.as_dyn_with::<Display>(&registry).unwrap().to_string(), "12");
+    /// # }
+    /// ```
+    ///
+    pub fn as_dyn_with<'a, Trait: ?Sized+'static>(&'a self, registry: &'a CastRegistry) -> Option<&'a Trait> {
+        cast_ref(self, Some(registry))
+    }
+
+    /// returns the inner value as mutable trait object, using the cast
+    /// to `Trait` registered in `registry` if the property did not capture one
+    ///
+    pub fn as_dyn_mut_with<Trait: ?Sized+'static>(&mut self, registry: &CastRegistry) -> Option<&mut Trait> {
+        cast_mut(self, Some(registry))
+    }
+
     /// return true if the given type matches the inner type
     ///
     pub fn is_inner_type<T:Any>(&self) -> bool {
-        self.value.is::<T>()
+        self.type_id == TypeId::of::<T>()
+    }
+
+    /// return true if the inner value is currently borrowed
+    /// over a `PropRef` or `PropMut`
+    ///
+    pub fn is_borrowed(&self) -> bool {
+        match self.value {
+            Storage::Shared(ref shared) => shared.is_borrowed(),
+            _ => false
+        }
+    }
+
+    /// return true if the inner value is currently borrowed
+    /// over a `PropMut`
+    ///
+    pub fn is_borrowed_mutable(&self) -> bool {
+        match self.value {
+            Storage::Shared(ref shared) => shared.is_borrowed_mutable(),
+            _ => false
+        }
+    }
+
+    /// borrows the inner value independent of this property
+    ///
+    /// If the given type matches the inner type and the value is
+    /// not borrowed mutable a `PropRef` guard is returned. The guard
+    /// keeps the value alive on its own, so it can outlive the borrow
+    /// of this property. As long as the guard exists the property can
+    /// not be set, removed or borrowed mutable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let mut prop = DynProperty::new(Box::new(12i32));
+    /// let guard = prop.borrow_ref::<i32>().unwrap();
+    /// assert_eq!(*guard, 12i32);
+    /// assert!(prop.as_mut::<i32>().is_none());
+    /// ```
+    ///
+    pub fn borrow_ref<T: Any>(&mut self) -> Option<PropRef<T>> {
+        if !self.is_inner_type::<T>() || self.is_borrowed_mutable() {
+            return None;
+        }
+        prop_guard::borrow_ref(&self.share())
+    }
+
+    /// mutable borrows the inner value independent of this property
+    ///
+    /// Works like `borrow_ref` but returns a `PropMut` guard. The
+    /// borrow fails if the value is borrowed in any way.
+    ///
+    pub fn borrow_mut<T: Any>(&mut self) -> Option<PropMut<T>> {
+        if !self.is_inner_type::<T>() || self.is_borrowed() {
+            return None;
+        }
+        prop_guard::borrow_mut(&self.share())
+    }
+
+    /// consumes this instance returning the inner data as `Box<Any>`
+    ///
+    /// This works without knowing the inner type. If the value is still
+    /// borrowed over a `PropRef` or `PropMut` the property is returned
+    /// as `Err` so that the value is not lost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::DynProperty;
+    /// let prop = DynProperty::new(Box::new(12u8));
+    /// let value = prop.into_any().ok().unwrap();
+    /// assert_eq!(value.downcast_ref::<u8>(), Some(&12u8));
+    /// ```
+    ///
+    pub fn into_any(mut self) -> Result<Box<Any+'static>, DynProperty> {
+        match self.take_value() {
+            Storage::Inline(value) => Ok(unsafe { value.into_box(self.vtable.into_box) }),
+            Storage::Owned(value) => Ok(value),
+            Storage::Shared(shared) => match rc::try_unwrap(shared) {
+                Ok(shared) => Ok(shared.into_inner()),
+                Err(shared) => {
+                    self.value = Storage::Shared(shared);
+                    Err(self)
+                }
+            }
+        }
     }
 
     /// consumes this instance returning the inner data 
@@ -118,20 +692,171 @@ impl DynProperty {
     /// matches the inner type Some(Box(T)) will be returned. If
     /// the type des not match this instance WILL STILL BE CONSUMED
     /// and the inernal data will be droped running the constreucktor(s) if
-    /// existing
+    /// existing. If the value is still borrowed None is returned and
+    /// the value is droped as soon as the last guard is droped.
     ///
-    pub fn destruct<T:Any>(self) -> Option<Box<T>> where T: 'static {
-        if self.is_inner_type::<T>() {
-            Some(self.value.downcast::<T>().unwrap())
-        } else {
-            None
+    pub fn destruct<T:Any>(mut self) -> Option<Box<T>> where T: 'static {
+        if !self.is_inner_type::<T>() {
+            return None;
+        }
+        let value = match self.take_value() {
+            Storage::Inline(value) => unsafe { value.into_box(self.vtable.into_box) },
+            Storage::Owned(value) => value,
+            Storage::Shared(shared) => match rc::try_unwrap(shared) {
+                Ok(shared) => shared.into_inner(),
+                Err(_) => return None
+            }
+        };
+        Some(value.downcast::<T>().unwrap())
+    }
+}
+
+impl Drop for DynProperty {
+    fn drop(&mut self) {
+        if let Storage::Inline(ref mut value) = self.value {
+            unsafe { value.drop_value(self.vtable.drop_value) }
+        }
+    }
+}
+
+impl fmt::Debug for DynProperty {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        try!(fter.write_str("DynProperty("));
+        try!(fmt_type_and_value(self, fter));
+        fter.write_str(")")
+    }
+}
+
+/// writes the type name of `prop` and, if possible, its value
+///
+/// This is used by the `Debug` implementations of the objects too.
+pub fn fmt_type_and_value(prop: &DynProperty, fter: &mut fmt::Formatter) -> fmt::Result {
+    try!(fter.write_str(prop.type_name()));
+    match prop.vtable.debug {
+        Some(debug) => {
+            try!(fter.write_str(" = "));
+            match prop.inner_ref() {
+                Some(value) => debug(value, fter),
+                None => fter.write_str("<borrowed>")
+            }
+        },
+        None => Ok(())
+    }
+}
+
+/// returns the `TypeId` of the inner value of `prop`
+pub fn inner_type_id(prop: &DynProperty) -> TypeId {
+    prop.type_id
+}
+
+/// creates a property like `prop` containing `value`, witch has to be of the same type
+pub fn with_value(prop: &DynProperty, value: Box<Any+'static>) -> DynProperty {
+    DynProperty {
+        type_id: prop.type_id,
+        vtable: prop.vtable,
+        extra: prop.extra.clone(),
+        value: (prop.vtable.from_any)(value)
+    }
+}
+
+/// replaces the value of `prop` with `value`, witch has to be of the same type
+///
+/// Guards of a borrowed value keep the old value alive.
+pub fn restore_value(prop: &mut DynProperty, value: Box<Any+'static>) {
+    let storage = (prop.vtable.from_any)(value);
+    prop.replace_value(storage);
+}
+
+/// borrows the value of `prop` like `DynProperty::borrow_ref` without changing `prop`
+///
+/// Returns None if the value was never borrowed and therefore is not shared
+/// yet, `Some(None)` if it has a different type or is borrowed mutable.
+pub fn borrow_shared<T: Any>(prop: &DynProperty) -> Option<Option<PropRef<T>>> {
+    match prop.value {
+        Storage::Shared(ref shared) => Some(prop_guard::borrow_ref(shared)),
+        _ => None
+    }
+}
+
+/// returns the casts captured by `prop`
+pub fn casts_mut(prop: &mut DynProperty) -> &mut Vec<CastEntry> {
+    &mut prop.extra_mut().casts
+}
+
+/// returns the encoding support captured by `prop`
+#[cfg(feature = "serialize")]
+pub fn encode_info(prop: &DynProperty) -> Option<EncodeInfo> {
+    prop.extra.as_ref().and_then(|extra| extra.encode)
+}
+
+/// lets `prop` capture the given encoding support
+#[cfg(feature = "serialize")]
+pub fn set_encode_info(prop: &mut DynProperty, info: EncodeInfo) {
+    prop.extra_mut().encode = Some(info);
+}
+
+/// returns the cast of `prop` to `Trait`, the captured one or the one registered in `registry`
+fn find_cast<'a, Trait: ?Sized+'static>(prop: &'a DynProperty, registry: Option<&'a CastRegistry>)
+    -> Option<&'a CastEntry>
+{
+    match dyn_cast::find::<Trait>(prop.casts()) {
+        Some(entry) => Some(entry),
+        None => match registry {
+            Some(registry) => dyn_cast::registered::<Trait>(registry, prop.type_id),
+            None => None
         }
     }
 }
 
+/// returns the inner value of `prop` as `Trait`, see `DynProperty::as_dyn_with`
+pub fn cast_ref<'a, Trait: ?Sized+'static>(prop: &'a DynProperty, registry: Option<&'a CastRegistry>)
+    -> Option<&'a Trait>
+{
+    match (find_cast::<Trait>(prop, registry), prop.inner_ref()) {
+        (Some(entry), Some(value)) => dyn_cast::cast_ref(entry, value),
+        _ => None
+    }
+}
+
+/// returns the inner value of `prop` as mutable `Trait`, see `DynProperty::as_dyn_mut_with`
+pub fn cast_mut<'a, Trait: ?Sized+'static>(prop: &'a mut DynProperty, registry: Option<&CastRegistry>)
+    -> Option<&'a mut Trait>
+{
+    //the entry is cloned because the value borrows the property mutable
+    let entry = match find_cast::<Trait>(prop, registry) {
+        Some(entry) => entry.clone(),
+        None => return None
+    };
+    match prop.inner_mut() {
+        Some(value) => dyn_cast::cast_mut(&entry, value),
+        None => None
+    }
+}
+
+/// returns the inner value of `prop` if it is not borrowed mutable
+pub fn inner_value(prop: &DynProperty) -> Option<&Any> {
+    prop.inner_ref()
+}
+
+/// returns the name of the type `T`
+pub fn type_name<T>() -> &'static str {
+    unsafe { ::std::intrinsics::type_name::<T>() }
+}
+
+/// creates a `PropertyError::TypeMismatch` for a access of `prop` with type `T`
+pub fn type_mismatch<T, V>(prop: &DynProperty, value: V) -> PropertyError<V> {
+    PropertyError::TypeMismatch {
+        expected: type_name::<T>(),
+        found: prop.type_name(),
+        value: value
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::DynProperty;
+    use super::{DynProperty, VTable, Storage, with_value, restore_value};
+    use super::super::property_error::PropertyError;
+    use super::super::conversion::ConversionRegistry;
 
     //a simple data Type
     #[derive(Eq, PartialEq, Debug)]
@@ -240,8 +965,209 @@ mod test {
     fn set_should_return_err_of_the_parameter_if_type_mismatches() {
         let mut x = create_dummy();
         let res = x.set(Box::new(Point3D(1,1,1)));
-        assert_eq!(res, Err(Box::new(Point3D(1,1,1))));    
+        match res {
+            Err(PropertyError::TypeMismatch { expected, found, value }) => {
+                assert!(expected.ends_with("::Point3D"));
+                assert!(found.ends_with("::Point"));
+                assert_eq!(value, Box::new(Point3D(1,1,1)));
+            },
+            _ => panic!("expected a type mismatch")
+        }
+    }
+
+    #[test]
+    fn type_mismatch_should_name_both_types() {
+        let mut x = DynProperty::new(Box::new((1u8, true)));
+        match x.set(Box::new(3u16)) {
+            Err(PropertyError::TypeMismatch { expected, found, .. }) => {
+                assert_eq!(expected, "u16");
+                assert_eq!(found, "(u8, bool)");
+            },
+            _ => panic!("expected a type mismatch")
+        }
+    }
+
+    #[test]
+    fn type_name_should_return_the_name_of_the_inner_type() {
+        let x = DynProperty::new(Box::new(3u16));
+        assert_eq!(x.type_name(), "u16");
+        assert_eq!(DynProperty::new(Box::new((1u8, true))).type_name(), "(u8, bool)");
+    }
+
+    #[test]
+    fn debug_should_only_contain_the_type_if_no_formatter_was_captured() {
+        let x = DynProperty::new(Box::new(3u16));
+        assert_eq!(format!("{:?}", x), "DynProperty(u16)");
+    }
+
+    #[test]
+    fn debug_should_contain_the_value_if_a_formatter_was_captured() {
+        let mut x = DynProperty::new_debug(Box::new((1u8, 2i32)));
+        assert_eq!(format!("{:?}", x), "DynProperty((u8, i32) = (1, 2))");
+        let guard = x.borrow_mut::<(u8, i32)>().unwrap();
+        assert_eq!(format!("{:?}", x), "DynProperty((u8, i32) = <borrowed>)");
+    }
+
+    #[test]
+    fn try_clone_should_return_none_if_not_created_cloneable() {
+        let x = DynProperty::new(Box::new(3u16));
+        assert!(!x.is_cloneable());
+        assert!(x.try_clone().is_none());
+    }
+
+    #[test]
+    fn try_clone_should_create_a_independent_copy() {
+        let x = DynProperty::new_cloneable(Box::new(vec![3u16]));
+        let mut clone = x.try_clone().unwrap();
+        clone.as_mut::<Vec<u16>>().unwrap().push(4);
+        assert_eq!(x.as_ref::<Vec<u16>>(), Some(&vec![3u16]));
+        assert_eq!(clone.as_ref::<Vec<u16>>(), Some(&vec![3u16, 4]));
+        assert!(clone.try_clone().is_some());
+    }
+
+    #[test]
+    fn try_clone_should_return_none_if_borrowed_mutable() {
+        let mut x = DynProperty::new_cloneable(Box::new(3u16));
+        let guard = x.borrow_mut::<u16>().unwrap();
+        assert!(x.try_clone().is_none());
+    }
+
+    #[test]
+    fn borrow_ref_should_allow_multiple_shared_borrows() {
+        let mut x = create_dummy();
+        let first = x.borrow_ref::<Point>().unwrap();
+        let second = x.borrow_ref::<Point>().unwrap();
+        assert_eq!(*first, dummy_value());
+        assert_eq!(*second, dummy_value());
+        assert!(x.is_borrowed());
+        assert_eq!(x.as_ref::<Point>(), Some(&dummy_value()));
+    }
+
+    #[test]
+    fn borrow_ref_should_fail_if_type_mismatches() {
+        let mut x = create_dummy();
+        assert!(x.borrow_ref::<i32>().is_none());
+        assert!(x.borrow_mut::<i32>().is_none());
+    }
+
+    #[test]
+    fn borrow_mut_should_fail_if_already_borrowed() {
+        let mut x = create_dummy();
+        let guard = x.borrow_ref::<Point>().unwrap();
+        assert!(x.borrow_mut::<Point>().is_none());
+        assert!(x.as_mut::<Point>().is_none());
+    }
+
+    #[test]
+    fn borrow_mut_should_be_exclusive() {
+        let mut x = create_dummy();
+        let mut guard = x.borrow_mut::<Point>().unwrap();
+        guard.0 = 3;
+        assert!(x.is_borrowed_mutable());
+        assert!(x.borrow_ref::<Point>().is_none());
+        assert!(x.as_ref::<Point>().is_none());
+        assert_eq!(x.set(box_dummy_value()), Err(PropertyError::Borrowed(box_dummy_value())));
     }
 
+    #[test]
+    fn droping_the_guard_should_release_the_borrow() {
+        let mut x = create_dummy();
+        {
+            let mut guard = x.borrow_mut::<Point>().unwrap();
+            guard.0 = 3;
+        }
+        assert!(!x.is_borrowed());
+        assert_eq!(x.as_mut::<Point>(), Some(&mut Point(3, second_dummy_value())));
+        assert_eq!(x.destruct::<Point>(), Some(Box::new(Point(3, second_dummy_value()))));
+    }
 
+    #[test]
+    fn into_any_should_return_the_property_if_borrowed() {
+        let mut x = create_dummy();
+        let guard = x.borrow_ref::<Point>().unwrap();
+        let x = x.into_any().err().unwrap();
+        drop(guard);
+        let value = x.into_any().ok().unwrap();
+        assert_eq!(value.downcast_ref::<Point>(), Some(&dummy_value()));
+    }
+
+    #[test]
+    fn small_and_big_values_should_behave_the_same() {
+        let mut small = DynProperty::from_value(3u32);
+        let mut big = DynProperty::from_value([1u64; 8]);
+        assert_eq!(small.set_value(4u32).ok(), Some(3u32));
+        assert_eq!(big.set(Box::new([2u64; 8])), Ok(Box::new([1u64; 8])));
+        *small.as_mut::<u32>().unwrap() += 1;
+        big.as_mut::<[u64; 8]>().unwrap()[0] = 3;
+        {
+            let guard = small.borrow_ref::<u32>().unwrap();
+            assert_eq!(*guard, 5u32);
+            assert!(small.set_value(1u32).is_err());
+        }
+        assert_eq!(small.destruct::<u32>(), Some(Box::new(5u32)));
+        assert_eq!(big.as_ref::<[u64; 8]>().unwrap()[..2], [3u64, 2][..]);
+    }
+
+    #[test]
+    fn properties_should_stay_small_and_share_their_vtable() {
+        use std::mem;
+        assert!(mem::size_of::<DynProperty>() <= 8 * mem::size_of::<usize>());
+        let first = DynProperty::from_value(1u32);
+        let second = DynProperty::from_value(2u32);
+        assert!(first.vtable as *const VTable == second.vtable as *const VTable);
+        assert!(first.vtable as *const VTable != DynProperty::new_cloneable(Box::new(3u32)).vtable as *const VTable);
+    }
+
+    #[test]
+    fn inline_values_should_be_droped_with_the_property() {
+        use std::rc::{self, Rc};
+        let load = Rc::new(5i32);
+        let mut prop = DynProperty::from_value(load.clone());
+        assert_eq!(rc::strong_count(&load), 2);
+        restore_value(&mut prop, Box::new(load.clone()));
+        assert_eq!(rc::strong_count(&load), 2);
+        drop(prop);
+        assert_eq!(rc::strong_count(&load), 1);
+    }
+
+    #[test]
+    fn with_value_should_store_small_values_inline() {
+        let prop = DynProperty::new_cloneable(Box::new(3u16));
+        let other = with_value(&prop, Box::new(4u16));
+        match other.value {
+            Storage::Inline(_) => {},
+            _ => panic!("expected a inline value")
+        }
+        assert_eq!(other.as_ref::<u16>(), Some(&4u16));
+        assert!(other.is_cloneable());
+    }
+
+    #[test]
+    fn guard_should_keep_the_value_alive_after_the_property_was_droped() {
+        let mut x = create_dummy();
+        let guard = x.borrow_ref::<Point>().unwrap();
+        assert_eq!(x.destruct::<Point>(), None);
+        assert_eq!(*guard, dummy_value());
+    }
+
+
+    #[test]
+    fn convert_in_place_should_change_the_type() {
+        let registry = ConversionRegistry::new();
+        let mut prop = DynProperty::new_cloneable(Box::new(3u16));
+        assert_eq!(prop.convert_in_place::<u16>(&registry), Ok(()));
+        assert!(prop.is_cloneable());
+        {
+            let guard = prop.borrow_ref::<u16>().unwrap();
+            assert_eq!(prop.convert_in_place::<u64>(&registry), Err(PropertyError::Borrowed(())));
+            assert_eq!(*guard, 3u16);
+        }
+        assert_eq!(prop.convert_in_place::<u64>(&registry), Ok(()));
+        assert!(prop.is_inner_type::<u64>());
+        assert!(!prop.is_cloneable());
+        match prop.convert_in_place::<u8>(&registry) {
+            Err(PropertyError::TypeMismatch { found, .. }) => assert_eq!(found, "u64"),
+            _ => panic!("u64 is not narrowed")
+        }
+    }
 }
@@ -0,0 +1,216 @@
+use std::any::Any;
+
+use super::dyn_property::{self, DynProperty};
+use super::property_error::PropertyError;
+use super::hooks::{Operation, PropertyWrite};
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::{PropertyStorage, HashMapStorage};
+
+
+/// a view into a single property of a `InnerDynObject`, returned by `entry`
+///
+/// Only the own properties are considered, a property of the
+/// prototype is shadowed by inserting into a `Vacant` entry.
+/// All operations are checked by the guards of the object.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::InnerDynObject;
+/// let mut obj = InnerDynObject::<&'static str>::new();
+/// for _ in 0..3 {
+///     *obj.entry( "counter" ).or_insert( Box::new( 0u32 )).unwrap() += 1;
+/// }
+/// assert_eq!( obj["counter"].as_ref::<u32>(), Some( &3u32 ));
+/// assert!( obj.entry( "counter" ).or_insert( Box::new( 0i8 )).is_err() );
+/// ```
+///
+pub enum Entry<'a, Key: 'a, S: 'a = HashMapStorage<Key>> {
+    /// the property exists
+    Occupied( OccupiedEntry<'a, Key, S> ),
+    /// the property does not exist
+    Vacant( VacantEntry<'a, Key, S> )
+}
+
+/// a existing property of a `InnerDynObject`
+pub struct OccupiedEntry<'a, Key: 'a, S: 'a = HashMapStorage<Key>> {
+    obj: &'a mut InnerDynObject<Key, S>,
+    key: Key
+}
+
+/// a missing property of a `InnerDynObject`
+pub struct VacantEntry<'a, Key: 'a, S: 'a = HashMapStorage<Key>> {
+    obj: &'a mut InnerDynObject<Key, S>,
+    key: Key
+}
+
+/// creates the entry of `key`
+pub fn entry<'a, Key, S>( obj: &'a mut InnerDynObject<Key, S>, key: Key ) -> Entry<'a, Key, S>
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    if obj.exists_property( &key ) {
+        Entry::Occupied( OccupiedEntry { obj: obj, key: key } )
+    } else {
+        Entry::Vacant( VacantEntry { obj: obj, key: key } )
+    }
+}
+
+impl<'a, Key, S> Entry<'a, Key, S> where Key: Eq + Clone, S: PropertyStorage<Key> {
+
+    /// returns the key of this entry
+    ///
+    pub fn key( &self ) -> &Key {
+        match *self {
+            Entry::Occupied( ref entry ) => entry.key(),
+            Entry::Vacant( ref entry ) => entry.key()
+        }
+    }
+
+    /// returns the value of the property, inserting `default` if it is missing
+    ///
+    /// The observers are notified with `Change::Write` when the returned
+    /// `PropertyWrite` is droped. If the property exists with a different type `PropertyError::TypeMismatch`
+    /// containing `default` is returned. The same is true for a borrowed property
+    /// or a operation rejected by a guard.
+    ///
+    pub fn or_insert<T>( self, default: Box<T> ) -> Result<PropertyWrite<'a, Key, T>, PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        match self {
+            Entry::Occupied( entry ) => entry.into_mut::<T>().map_err( |err| err.map_value( |_| default )),
+            Entry::Vacant( entry ) => entry.insert( default )
+        }
+    }
+
+    /// returns the value of the property, inserting the result of `default` if it is missing
+    ///
+    /// Works like `or_insert` but `default` is only called if the property is missing.
+    ///
+    pub fn or_insert_with<T, F>( self, default: F ) -> Result<PropertyWrite<'a, Key, T>, PropertyError<()>>
+        where T: Any + 'static, F: FnOnce() -> Box<T>
+    {
+        match self {
+            Entry::Occupied( entry ) => entry.into_mut::<T>(),
+            Entry::Vacant( entry ) => entry.insert( default() ).map_err( |err| err.map_value( |_| () ))
+        }
+    }
+
+    /// calls `func` with the value of the property if it exists
+    ///
+    /// The observers are notified with `Change::Write` afterwards. If the property
+    /// has a different type, is borrowed or the access is rejected by a guard
+    /// a `PropertyError` containing this entry is returned instead.
+    ///
+    pub fn and_modify<T, F>( self, func: F ) -> Result<Entry<'a, Key, S>, PropertyError<Entry<'a, Key, S>>>
+        where T: Any + 'static, F: FnOnce( &mut T )
+    {
+        match self {
+            Entry::Occupied( mut entry ) => {
+                let res = entry.get_mut::<T>().map( |mut value| func( &mut *value ));
+                match res {
+                    Ok( () ) => Ok( Entry::Occupied( entry )),
+                    Err( err ) => Err( err.map_value( |_| Entry::Occupied( entry )))
+                }
+            },
+            vacant => Ok( vacant )
+        }
+    }
+}
+
+impl<'a, Key, S> OccupiedEntry<'a, Key, S> where Key: Eq, S: PropertyStorage<Key> {
+
+    /// returns the key of this entry
+    ///
+    pub fn key( &self ) -> &Key {
+        &self.key
+    }
+
+    /// returns the property
+    ///
+    pub fn get( &self ) -> Result<&DynProperty, PropertyError<()>> {
+        self.obj.access( &self.key )
+    }
+
+    /// returns the value of the property, see `InnerDynObject::as_mut`
+    ///
+    pub fn get_mut<T>( &mut self ) -> Result<PropertyWrite<Key, T>, PropertyError<()>>
+        where T: Any + 'static
+    {
+        {
+            let prop = try!( self.obj.access_mut( &self.key ));
+            if !prop.is_inner_type::<T>() {
+                return Err( dyn_property::type_mismatch::<T, ()>( prop, () ));
+            }
+        }
+        self.obj.as_mut::<T>( &self.key ).ok_or( PropertyError::Borrowed( () ))
+    }
+
+    /// converts the entry into the value of the property
+    ///
+    /// The observers are notified with `Change::Write` when the
+    /// returned `PropertyWrite` is droped.
+    ///
+    pub fn into_mut<T>( self ) -> Result<PropertyWrite<'a, Key, T>, PropertyError<()>>
+        where T: Any + 'static
+    {
+        let OccupiedEntry { obj, key } = self;
+        {
+            let prop = try!( obj.access_mut( &key ));
+            if !prop.is_inner_type::<T>() {
+                return Err( dyn_property::type_mismatch::<T, ()>( prop, () ));
+            }
+        }
+        inner_dyn_object::write_owned( obj, key ).ok_or( PropertyError::Borrowed( () ))
+    }
+
+    /// sets the property, see `InnerDynObject::set_property`
+    ///
+    pub fn set<T>( &mut self, value: Box<T> ) -> Result<Box<T>, PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        self.obj.set_property( &self.key, value )
+    }
+
+    /// removes the property, see `InnerDynObject::remove_property`
+    ///
+    pub fn remove<T>( self ) -> Result<Box<T>, PropertyError<()>>
+        where T: Any + 'static
+    {
+        self.obj.remove_property::<T>( &self.key )
+    }
+}
+
+impl<'a, Key, S> VacantEntry<'a, Key, S> where Key: Eq + Clone, S: PropertyStorage<Key> {
+
+    /// returns the key of this entry
+    ///
+    pub fn key( &self ) -> &Key {
+        &self.key
+    }
+
+    /// returns the key of this entry consuming it
+    ///
+    pub fn into_key( self ) -> Key {
+        self.key
+    }
+
+    /// creates the property returning a `PropertyWrite` of its value
+    ///
+    /// If a guard rejects creating or accessing the property
+    /// `PropertyError::Rejected` containing the value is returned.
+    /// The guards are asked for the access before the property is created,
+    /// and are not asked again afterwards.
+    ///
+    pub fn insert<T>( self, value: Box<T> ) -> Result<PropertyWrite<'a, Key, T>, PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        let VacantEntry { obj, key } = self;
+        if !inner_dyn_object::allows( obj, &key, Operation::AccessMut ) {
+            return Err( PropertyError::Rejected( value ));
+        }
+        try!( obj.create_property( key.clone(), value ));
+        //the new property has the type `T` and is not borrowed, so only a
+        //missing property could fail here, witch create_property rules out
+        Ok( inner_dyn_object::write_owned( obj, key ).expect( "created property is missing" ))
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::any::{Any, TypeId};
+use std::mem;
+
+use super::dyn_property::DynProperty;
+
+
+/// a change witch can be applied to the properties of a `InnerDynObject`
+///
+/// Applying a edit returns the edit witch undoes it.
+pub enum Edit<Key> {
+    /// insert the property, undone by `Remove`
+    Insert( Key, DynProperty ),
+    /// remove the property, undone by `Insert`
+    Remove( Key ),
+    /// replace the property, undone by `Replace` with the old property
+    Replace( Key, DynProperty ),
+    /// replace the value of the given type keeping the property, undone by `Replace`
+    Restore( Key, TypeId, Box<Any> )
+}
+
+/// the undo and redo steps of a `InnerDynObject`, see `enable_history`
+///
+/// A step is a list of edits witch are applied in reverse order.
+/// Changes done while a group is open are collected into one step.
+pub struct History<Key> {
+    undo: VecDeque<Vec<Edit<Key>>>,
+    redo: Vec<Vec<Edit<Key>>>,
+    open: Vec<Edit<Key>>,
+    groups: usize,
+    //the number of edits of the open step dropped by `clear`, as the marks count them
+    cleared: usize,
+    depth: usize,
+    clone_key: fn( &Key ) -> Key
+}
+
+impl<Key> History<Key> {
+
+    /// creates a empty history keeping at most `depth` undo steps
+    pub fn new( depth: usize, clone_key: fn( &Key ) -> Key ) -> History<Key> {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: Vec::new(),
+            groups: 0,
+            cleared: 0,
+            depth: depth,
+            clone_key: clone_key
+        }
+    }
+
+    /// returns the function used to clone keys, the object only requires `Clone`
+    /// for enabling the history
+    pub fn key_cloner( &self ) -> fn( &Key ) -> Key {
+        self.clone_key
+    }
+
+    /// changes the maximal number of undo steps, dropping the oldest ones
+    pub fn set_depth( &mut self, depth: usize ) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    /// records the edit undoing a change, the redo steps are dropped
+    pub fn record( &mut self, edit: Edit<Key> ) {
+        self.redo.clear();
+        self.open.push( edit );
+        if self.groups == 0 {
+            self.close();
+        }
+    }
+
+    /// records the edits undoing several changes as one step
+    pub fn record_step( &mut self, step: Vec<Edit<Key>> ) {
+        let mark = self.begin_group();
+        for edit in step.into_iter() {
+            self.record( edit );
+        }
+        self.end_group( mark, true );
+    }
+
+    /// drops all steps including the open one, used for changes witch can not be undone
+    pub fn clear( &mut self ) {
+        self.undo.clear();
+        self.redo.clear();
+        self.cleared += self.open.len();
+        self.open.clear();
+    }
+
+    /// opens a group returning a mark for `end_group`, groups can be nested
+    pub fn begin_group( &mut self ) -> usize {
+        self.groups += 1;
+        self.cleared + self.open.len()
+    }
+
+    /// closes a group, if `keep` is false the edits recorded since
+    /// the group was opened are dropped
+    pub fn end_group( &mut self, mark: usize, keep: bool ) {
+        if !keep {
+            let len = if mark > self.cleared { mark - self.cleared } else { 0 };
+            self.open.truncate( len );
+        }
+        self.groups -= 1;
+        if self.groups == 0 {
+            self.close();
+        }
+    }
+
+    /// returns the latest undo step, None if there is none or a group is open
+    pub fn take_undo( &mut self ) -> Option<Vec<Edit<Key>>> {
+        if self.groups > 0 {
+            return None;
+        }
+        self.undo.pop_back()
+    }
+
+    /// returns the latest redo step, None if there is none or a group is open
+    pub fn take_redo( &mut self ) -> Option<Vec<Edit<Key>>> {
+        if self.groups > 0 {
+            return None;
+        }
+        self.redo.pop()
+    }
+
+    /// adds the step undoing a undo step
+    pub fn push_redo( &mut self, step: Vec<Edit<Key>> ) {
+        self.redo.push( step );
+    }
+
+    /// adds the step undoing a redo step, keeping the other redo steps
+    pub fn push_undo( &mut self, step: Vec<Edit<Key>> ) {
+        self.undo.push_back( step );
+        self.trim();
+    }
+
+    /// returns the number of undo steps
+    pub fn undo_len( &self ) -> usize {
+        self.undo.len()
+    }
+
+    /// returns the number of redo steps
+    pub fn redo_len( &self ) -> usize {
+        self.redo.len()
+    }
+
+    fn close( &mut self ) {
+        self.cleared = 0;
+        if !self.open.is_empty() {
+            let step = mem::replace( &mut self.open, Vec::new() );
+            self.push_undo( step );
+        }
+    }
+
+    fn trim( &mut self ) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+
+
+/// the kind of operation a guard is asked about
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operation {
+    /// a property is created (or inserted)
+    Create,
+    /// a property is removed
+    Remove,
+    /// a property is read
+    Access,
+    /// a property is written or accessed mutable
+    AccessMut
+}
+
+/// the kind of change a observer is notified about
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Change {
+    /// a property was created (or inserted)
+    Create,
+    /// a property was set to a new value
+    Set,
+    /// a property was removed
+    Remove,
+    /// a property was written over a `PropertyWrite`, e.g. returned by `InnerDynObject::as_mut`
+    Write
+}
+
+/// identifies a observer, returned by `InnerDynObject::subscribe`
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ObserverId(usize);
+
+/// a guard, returns false to reject the operation
+pub type Guard<Key> = Box<Fn(&Key, Operation) -> bool>;
+
+/// a observer, called after a property was changed
+pub type Observer<Key> = Box<FnMut(&Key, Change)>;
+
+/// the guards and observers registered on a `InnerDynObject`
+///
+/// They are only allocated if a hook is added, so that
+/// objects without hooks only have to check for `None`. The hooks of
+/// single keys are searched linear, so keys only need to implement `Eq`.
+/// The observers are kept in a `RefCell`, so that several `PropertyWrite`s,
+/// e.g. the ones of a mutable iterator, can share the hooks.
+pub struct Hooks<Key> {
+    guards: Vec<Guard<Key>>,
+    key_guards: Vec<( Key, Guard<Key> )>,
+    observers: RefCell<Vec<( ObserverId, Observer<Key> )>>,
+    key_observers: RefCell<Vec<( Key, ObserverId, Observer<Key> )>>,
+    //set by the first subscribe, the observers of a created property
+    //receive a clone as the key itself is moved into the object
+    clone_key: Option<fn( &Key ) -> Key>,
+    next_id: usize
+}
+
+impl<Key> Hooks<Key> where Key: Eq {
+
+    pub fn new() -> Hooks<Key> {
+        Hooks {
+            guards: Vec::new(),
+            key_guards: Vec::new(),
+            observers: RefCell::new( Vec::new() ),
+            key_observers: RefCell::new( Vec::new() ),
+            clone_key: None,
+            next_id: 0
+        }
+    }
+
+    /// removes all guards keeping the observers
+    pub fn clear_guards( &mut self ) {
+        self.guards.clear();
+        self.key_guards.clear();
+    }
+
+    /// adds a observer notified about changes of any property
+    pub fn subscribe( &mut self, observer: Observer<Key>, clone_key: fn( &Key ) -> Key ) -> ObserverId {
+        self.clone_key = Some( clone_key );
+        let id = self.create_id();
+        self.observers.borrow_mut().push( ( id, observer ));
+        id
+    }
+
+    /// adds a observer only notified about changes of the property `key`
+    pub fn subscribe_key( &mut self, key: Key, observer: Observer<Key>, clone_key: fn( &Key ) -> Key ) -> ObserverId {
+        self.clone_key = Some( clone_key );
+        let id = self.create_id();
+        self.key_observers.borrow_mut().push( ( key, id, observer ));
+        id
+    }
+
+    /// removes the observer with the given id, returns false if there is none
+    pub fn unsubscribe( &mut self, id: ObserverId ) -> bool {
+        let mut observers = self.observers.borrow_mut();
+        match observers.iter().position( |&( other, _ )| other == id ) {
+            Some( idx ) => {
+                observers.remove( idx );
+                return true;
+            },
+            None => {}
+        }
+        let mut key_observers = self.key_observers.borrow_mut();
+        match key_observers.iter().position( |&( _, other, _ )| other == id ) {
+            Some( idx ) => {
+                key_observers.remove( idx );
+                true
+            },
+            None => false
+        }
+    }
+
+    fn create_id( &mut self ) -> ObserverId {
+        self.next_id += 1;
+        ObserverId( self.next_id )
+    }
+
+    /// returns a clone of `key` if there is a observer to notify about it
+    pub fn observed_key( &self, key: &Key ) -> Option<Key> {
+        let observed = !self.observers.borrow().is_empty()
+                    || self.key_observers.borrow().iter().any( |&( ref other, _, _ )| other == key );
+        match self.clone_key {
+            Some( clone_key ) if observed => Some( clone_key( key )),
+            _ => None
+        }
+    }
+
+    /// notifies the observers of the key and then the observers of the object
+    pub fn notify( &self, key: &Key, change: Change ) {
+        for &mut ( ref other, _, ref mut observer ) in self.key_observers.borrow_mut().iter_mut() {
+            if other == key {
+                observer( key, change );
+            }
+        }
+        for &mut ( _, ref mut observer ) in self.observers.borrow_mut().iter_mut() {
+            observer( key, change );
+        }
+    }
+
+    /// adds a guard called for operations on any property
+    pub fn add_guard( &mut self, guard: Guard<Key> ) {
+        self.guards.push( guard );
+    }
+
+    /// adds a guard only called for operations on the property `key`
+    pub fn add_key_guard( &mut self, key: Key, guard: Guard<Key> ) {
+        self.key_guards.push( ( key, guard ));
+    }
+
+    /// returns true if all guards allow the operation, the guards of
+    /// the key are called before the guards of the object
+    pub fn allows( &self, key: &Key, operation: Operation ) -> bool {
+        self.key_guards.iter().filter( |&&( ref other, _ )| other == key ).all( |&( _, ref guard )| guard( key, operation ))
+            && self.guards.iter().all( |guard| guard( key, operation ))
+    }
+}
+
+/// returns true if `hooks` allow the operation, no hooks allow everything
+#[inline]
+pub fn allows<Key>( hooks: &Option<Box<Hooks<Key>>>, key: &Key, operation: Operation ) -> bool
+    where Key: Eq
+{
+    match *hooks {
+        Some( ref hooks ) => hooks.allows( key, operation ),
+        None => true
+    }
+}
+
+/// notifies the observers of `hooks`, if there are any
+#[inline]
+pub fn notify<Key>( hooks: &Option<Box<Hooks<Key>>>, key: &Key, change: Change )
+    where Key: Eq
+{
+    match *hooks {
+        Some( ref hooks ) => hooks.notify( key, change ),
+        None => {}
+    }
+}
+
+/// returns a clone of `key` if `hooks` have a observer to notify about it
+///
+/// Used to notify the observers after the key was moved into the object.
+pub fn observed_key<Key>( hooks: &Option<Box<Hooks<Key>>>, key: &Key ) -> Option<Key>
+    where Key: Eq
+{
+    match *hooks {
+        Some( ref hooks ) => hooks.observed_key( key ),
+        None => None
+    }
+}
+
+/// a mutable reference to the value of a property, returned by `InnerDynObject::as_mut`
+///
+/// Also yielded by the mutable iterators and returned by the entries.
+/// The observers of the object are notified with `Change::Write` when
+/// the reference is droped, i.e. after the value was written.
+pub struct PropertyWrite<'a, Key: 'a + Eq, T: 'a + ?Sized> {
+    value: &'a mut T,
+    key: WriteKey<'a, Key>,
+    hooks: &'a Option<Box<Hooks<Key>>>
+}
+
+//the entries own their key, the other writes borrow it from the caller or the storage
+enum WriteKey<'a, Key: 'a> {
+    Borrowed( &'a Key ),
+    Owned( Key )
+}
+
+/// creates a `PropertyWrite`
+pub fn property_write<'a, Key, T: ?Sized>( value: &'a mut T, key: &'a Key, hooks: &'a Option<Box<Hooks<Key>>> )
+    -> PropertyWrite<'a, Key, T>
+    where Key: Eq
+{
+    PropertyWrite {
+        value: value,
+        key: WriteKey::Borrowed( key ),
+        hooks: hooks
+    }
+}
+
+/// creates a `PropertyWrite` owning the key
+pub fn property_write_owned<'a, Key, T: ?Sized>( value: &'a mut T, key: Key, hooks: &'a Option<Box<Hooks<Key>>> )
+    -> PropertyWrite<'a, Key, T>
+    where Key: Eq
+{
+    PropertyWrite {
+        value: value,
+        key: WriteKey::Owned( key ),
+        hooks: hooks
+    }
+}
+
+impl<'a, Key, T: ?Sized> Deref for PropertyWrite<'a, Key, T> where Key: Eq {
+    type Target = T;
+
+    fn deref( &self ) -> &T {
+        self.value
+    }
+}
+
+impl<'a, Key, T: ?Sized> DerefMut for PropertyWrite<'a, Key, T> where Key: Eq {
+    fn deref_mut( &mut self ) -> &mut T {
+        self.value
+    }
+}
+
+#[unsafe_destructor]
+impl<'a, Key, T: ?Sized> Drop for PropertyWrite<'a, Key, T> where Key: Eq {
+    fn drop( &mut self ) {
+        let key = match self.key {
+            WriteKey::Borrowed( key ) => key,
+            WriteKey::Owned( ref key ) => key
+        };
+        notify( self.hooks, key, Change::Write );
+    }
+}
@@ -0,0 +1,105 @@
+use std::any::Any;
+use std::mem;
+use std::ptr;
+
+
+/// the buffer a small value is stored in, 3 words like a `Vec`
+pub type Buffer = [usize; 3];
+
+/// returns true if a value of type `T` can be stored inline
+///
+/// The value has to fit into the buffer and must not need a
+/// bigger alignment than `usize`.
+pub fn fits<T>() -> bool {
+    mem::size_of::<T>() <= mem::size_of::<Buffer>()
+        && mem::align_of::<T>() <= mem::align_of::<Buffer>()
+}
+
+/// returns the value in the buffer as `Any`, `T` has to be the stored type
+pub type AsAnyFn = fn(*mut Buffer) -> *mut Any;
+
+/// moves the value out of the buffer into a box
+pub type IntoBoxFn = unsafe fn(*mut Buffer) -> Box<Any+'static>;
+
+/// drops the value in the buffer
+pub type DropFn = unsafe fn(*mut Buffer);
+
+/// a small value of a `DynProperty` stored without a heap allocation
+///
+/// The type of the value is only known to the functions in the
+/// vtable of the property, like `Box<Any>` does it with its vtable.
+/// Therefore the value is not droped on its own, the owner has to
+/// call `drop_value` or move it out with `into_box`.
+pub struct InlineValue {
+    data: Buffer
+}
+
+pub fn as_any<T: Any>(data: *mut Buffer) -> *mut Any {
+    data as *mut T as *mut Any
+}
+
+pub unsafe fn into_box<T: Any>(data: *mut Buffer) -> Box<Any+'static> {
+    Box::new(ptr::read(data as *const T))
+}
+
+pub unsafe fn drop_value<T>(data: *mut Buffer) {
+    drop(ptr::read(data as *const T));
+}
+
+impl InlineValue {
+
+    /// stores `value` inline, `fits::<T>()` has to be true
+    pub fn new<T: Any>(value: T) -> InlineValue {
+        assert!(fits::<T>());
+        let mut data: Buffer = [0; 3];
+        unsafe { ptr::write(&mut data as *mut Buffer as *mut T, value) };
+        InlineValue { data: data }
+    }
+
+    /// `as_any` has to be the one of the stored type, as for all the methods
+    pub unsafe fn get(&self, as_any: AsAnyFn) -> &Any {
+        &*as_any(&self.data as *const Buffer as *mut Buffer)
+    }
+
+    pub unsafe fn get_mut(&mut self, as_any: AsAnyFn) -> &mut Any {
+        &mut *as_any(&mut self.data)
+    }
+
+    /// moves the value onto the heap
+    pub unsafe fn into_box(mut self, into_box: IntoBoxFn) -> Box<Any+'static> {
+        into_box(&mut self.data)
+    }
+
+    /// drops the value, the buffer must not be used afterwards
+    pub unsafe fn drop_value(&mut self, drop_value: DropFn) {
+        drop_value(&mut self.data)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::rc::{self, Rc};
+    use super::{InlineValue, fits, into_box, drop_value};
+
+    #[test]
+    fn fits_should_only_accept_small_values() {
+        assert!(fits::<u32>());
+        assert!(fits::<Vec<u8>>());
+        assert!(!fits::<[u64; 4]>());
+    }
+
+    #[test]
+    fn inline_values_should_be_droped_exactly_once() {
+        let load = Rc::new(5i32);
+        let value = InlineValue::new(load.clone());
+        assert_eq!(rc::strong_count(&load), 2);
+        let boxed = unsafe { value.into_box(into_box::<Rc<i32>>) };
+        assert_eq!(rc::strong_count(&load), 2);
+        drop(boxed);
+        assert_eq!(rc::strong_count(&load), 1);
+        let mut value = InlineValue::new(load.clone());
+        unsafe { value.drop_value(drop_value::<Rc<i32>>) };
+        assert_eq!(rc::strong_count(&load), 1);
+    }
+}
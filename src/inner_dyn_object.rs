@@ -1,14 +1,30 @@
 use std::result::Result;
 use std::ops::{Index, IndexMut};
-use std::collections::HashMap;
+use std::collections::Bound;
 use std::hash::Hash;
-use std::any::Any;
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::mem;
 
 //import and reexport dyn_property
-use super::dyn_property::DynProperty;
+use super::dyn_property::{self, DynProperty};
+use super::property_error::PropertyError;
+use super::prop_guard::{PropRef, PropMut};
+use super::typed_key::TypedKey;
+use super::deep_clone::{ClonePolicy, DeepCloneError};
+use super::hooks::{self, Hooks, Operation, Change, ObserverId, PropertyWrite};
+use super::iter::{self, Keys, Iter, IterMut, Range, IterOf, IterMutOf, IterDyn, IterDynMut, Drain};
+use super::entry::{self, Entry};
+use super::transaction::{self, Transaction};
+use super::history::{History, Edit};
+use super::storage::{PropertyStorage, HashMapStorage, BTreeMapStorage};
+use super::conversion::{self, ConversionRegistry};
+use super::dyn_cast::CastRegistry;
+use super::DynObject;
 
 
 /// zero sized type used as "is undefined" marker
+#[derive(Debug)]
 pub struct UndefinedProperty;
 
 pub fn undefined_property() -> DynProperty {
@@ -22,11 +38,48 @@ pub fn undefined_property() -> DynProperty {
 /// This Trait provids a way to create, add, remove and
 /// modify typed propertys defined by a given Key
 ///
-pub struct InnerDynObject<Key> {
+/// The `Debug` output lists each key with the type of its property,
+/// see `DynProperty::new_debug` for including the values.
+///
+/// An object can have a prototype, a `DynObject` witch is searched
+/// by `with_property` and `has_property` if a key does not exist
+/// locally. Properties created on the object shadow the properties
+/// of the prototype, the prototype itself is never written.
+///
+/// Guards can be added to the object or to single keys with `add_guard`
+/// and `add_key_guard`. They are called before a property is created,
+/// removed or accessed and can reject the operation, which results in
+/// a `PropertyError::Rejected`. Indexing a property which can not be
+/// accessed returns the undefined property, like indexing a missing one.
+///
+/// Observers can be subscribed to the object or to single keys with
+/// `subscribe` and `subscribe_key`. They are notified after a property
+/// was created, set, removed or written over a `PropertyWrite`, witch is
+/// returned by `as_mut`, the entries and the mutable iterators. Writes over the
+/// plain references returned by indexing and `access_mut` are not observed, neither
+/// are writes over the guards of `borrow_prop_mut`, as they do not refer to the object.
+///
+/// The properties are stored in a `PropertyStorage`, by default a `HashMap`.
+/// Other backends can be used with `with_storage`, e.g. `VecStorage`
+/// for objects with only a few keys. The keys only need to implement
+/// the traits the backend requires, e.g. `Ord` for `BTreeMapStorage`.
+///
+/// With `enable_history` the object records creating, setting and removing
+/// properties and can `undo` and `redo` these changes.
+///
+pub struct InnerDynObject<Key, S = HashMapStorage<Key>> {
     //initialise this allways with DynProperty::undefined();
     //FIXME move this as assoziated Konstant ( with unsave ) or static
     undefined_property: DynProperty,
-    data: HashMap<Key, DynProperty>
+    //handed out by index_mut and reset every time, so changes done
+    //over it do not leak into undefined_property or later lookups
+    undefined_property_mut: DynProperty,
+    data: S,
+    prototype: Option<DynObject<Key, S>>,
+    //None as long as no hook is added, so that plain objects stay fast
+    hooks: Option<Box<Hooks<Key>>>,
+    //None as long as the history is not enabled
+    history: Option<Box<History<Key>>>
 }
 
 impl<Key> InnerDynObject<Key> where Key: Eq + Hash {
@@ -34,18 +87,345 @@ impl<Key> InnerDynObject<Key> where Key: Eq + Hash {
     /// Creates a new empty InnerDynObject
     ///
     pub fn new() -> InnerDynObject<Key> {
+        InnerDynObject::with_storage( HashMapStorage::new() )
+    }
+}
+
+impl<Key, S> InnerDynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
+
+    /// Creates a new InnerDynObject using the given storage
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{InnerDynObject, PropertyStorage, VecStorage};
+    /// let mut obj = InnerDynObject::with_storage( VecStorage::new() );
+    /// obj.create_value( "hp", 100u32 ).unwrap();
+    /// assert_eq!( obj["hp"].as_ref::<u32>(), Some( &100u32 ));
+    /// ```
+    ///
+    pub fn with_storage( storage: S ) -> InnerDynObject<Key, S> {
         InnerDynObject {
             undefined_property: undefined_property(),
-            data: HashMap::<Key, DynProperty>::new()
+            undefined_property_mut: undefined_property(),
+            data: storage,
+            prototype: None,
+            hooks: None,
+            history: None
+        }
+    }
+
+    /// Creates a new empty InnerDynObject with the given prototype
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{DynObject, InnerDynObject};
+    /// let template = DynObject::<&'static str>::new();
+    /// template.aquire().create_property( "hp", Box::new( 100u32 )).unwrap();
+    /// let mut obj = InnerDynObject::with_prototype( template );
+    /// assert_eq!( obj.with_property( &"hp", |prop| prop.as_ref::<u32>().cloned() ), Some( 100u32 ));
+    /// obj.create_property( "hp", Box::new( 50u32 )).unwrap();
+    /// assert_eq!( obj.with_property( &"hp", |prop| prop.as_ref::<u32>().cloned() ), Some( 50u32 ));
+    /// ```
+    ///
+    pub fn with_prototype( prototype: DynObject<Key, S> ) -> InnerDynObject<Key, S> {
+        InnerDynObject {
+            prototype: Some( prototype ),
+            ..InnerDynObject::with_storage( S::new() )
+        }
+    }
+
+    /// returns the prototype of this object
+    ///
+    /// Use `DynObject::set_prototype` to change it.
+    ///
+    pub fn prototype( &self ) -> Option<&DynObject<Key, S>> {
+        self.prototype.as_ref()
+    }
+
+    /// adds a guard witch is called for operations on any property
+    ///
+    /// The guard receives the key and the kind of operation and returns
+    /// false to reject it. Guards of the key are called before the guards
+    /// of the object, all of them have to allow the operation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{InnerDynObject, Operation, PropertyError};
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.add_guard( |_: &&'static str, op| op != Operation::Remove );
+    /// obj.create_property( "hp", Box::new( 100u32 )).unwrap();
+    /// assert_eq!( obj.remove_property::<u32>( &"hp" ), Err( PropertyError::Rejected( () )) );
+    /// ```
+    ///
+    pub fn add_guard<F>( &mut self, guard: F )
+        where F: Fn( &Key, Operation ) -> bool + 'static
+    {
+        self.hooks_mut().add_guard( Box::new( guard ));
+    }
+
+    /// adds a guard witch is only called for operations on the property `key`
+    ///
+    /// See `add_guard`. The property does not need to exist, so e.g.
+    /// its creation can be rejected.
+    ///
+    pub fn add_key_guard<F>( &mut self, key: Key, guard: F )
+        where F: Fn( &Key, Operation ) -> bool + 'static
+    {
+        self.hooks_mut().add_key_guard( key, Box::new( guard ));
+    }
+
+    /// removes all guards of this object
+    ///
+    pub fn clear_guards( &mut self ) {
+        match self.hooks {
+            Some( ref mut hooks ) => hooks.clear_guards(),
+            None => {}
+        }
+    }
+
+    /// removes the observer with the given id
+    ///
+    /// Returns false if no such observer exists.
+    ///
+    pub fn unsubscribe( &mut self, id: ObserverId ) -> bool {
+        match self.hooks {
+            Some( ref mut hooks ) => hooks.unsubscribe( id ),
+            None => false
+        }
+    }
+
+    /// stops recording changes, dropping the undo and redo steps
+    ///
+    pub fn disable_history( &mut self ) {
+        self.history = None;
+    }
+
+    /// returns true if there is a change witch can be undone
+    ///
+    pub fn can_undo( &self ) -> bool {
+        self.history.as_ref().map_or( false, |history| history.undo_len() > 0 )
+    }
+
+    /// returns true if there is a undone change witch can be redone
+    ///
+    pub fn can_redo( &self ) -> bool {
+        self.history.as_ref().map_or( false, |history| history.redo_len() > 0 )
+    }
+
+    /// undoes the latest change (or group of changes)
+    ///
+    /// The guards are not called, but the observers are notified.
+    /// Returns false if there is nothing to undo, the history is not
+    /// enabled or this is called inside of `history_group`. False is
+    /// also returned, without changing anything, if the step does not
+    /// fit the properties anymore, e.g. because a property was replaced
+    /// over indexing. The step is kept in this case.
+    ///
+    pub fn undo( &mut self ) -> bool {
+        let ( step, clone_key ) = match self.history.as_mut() {
+            Some( history ) => match history.take_undo() {
+                Some( step ) => ( step, history.key_cloner() ),
+                None => return false
+            },
+            None => return false
+        };
+        let ( step, applied ) = self.apply_edits( step, clone_key );
+        match self.history.as_mut() {
+            Some( history ) if applied => history.push_redo( step ),
+            Some( history ) => history.push_undo( step ),
+            None => {}
+        }
+        applied
+    }
+
+    /// redoes the latest undone change (or group of changes)
+    ///
+    /// Works like `undo`. Recording a new change drops all redo steps.
+    ///
+    pub fn redo( &mut self ) -> bool {
+        let ( step, clone_key ) = match self.history.as_mut() {
+            Some( history ) => match history.take_redo() {
+                Some( step ) => ( step, history.key_cloner() ),
+                None => return false
+            },
+            None => return false
+        };
+        let ( step, applied ) = self.apply_edits( step, clone_key );
+        match self.history.as_mut() {
+            Some( history ) if applied => history.push_undo( step ),
+            Some( history ) => history.push_redo( step ),
+            None => {}
+        }
+        applied
+    }
+
+    /// calls `func` recording all changes done by it as one undo step
+    ///
+    /// Groups can be nested, only the outermost group creates a step.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::InnerDynObject;
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.enable_history( 10 );
+    /// obj.history_group( |obj| {
+    ///     obj.create_value( "x", 1i32 ).unwrap();
+    ///     obj.create_value( "y", 2i32 ).unwrap();
+    /// });
+    /// assert!( obj.undo() );
+    /// assert!( obj.is_empty() );
+    /// assert!( obj.redo() );
+    /// assert_eq!( obj.len(), 2 );
+    /// ```
+    ///
+    pub fn history_group<R, F>( &mut self, func: F ) -> R
+        where F: FnOnce( &mut InnerDynObject<Key, S> ) -> R
+    {
+        let mark = begin_history_group( self );
+        let res = func( self );
+        end_history_group( self, mark, true );
+        res
+    }
+
+    /// applies the edits of a step in reverse order returning the step undoing them
+    ///
+    /// If the step does not fit the properties it is returned unchanged with false.
+    fn apply_edits( &mut self, step: Vec<Edit<Key>>, clone_key: fn( &Key ) -> Key ) -> ( Vec<Edit<Key>>, bool ) {
+        if !self.fits( &step ) {
+            return ( step, false );
+        }
+        let mut inverse = Vec::with_capacity( step.len() );
+        //the edits can not fail anymore, `fits` checked them
+        for edit in step.into_iter().rev() {
+            inverse.push( match edit {
+                Edit::Insert( key, prop ) => {
+                    let undo = Edit::Remove( clone_key( &key ));
+                    insert_and_notify( self, key, prop );
+                    undo
+                },
+                Edit::Remove( key ) => {
+                    let prop = match self.data.remove( &key ) {
+                        Some( prop ) => prop,
+                        None => continue
+                    };
+                    hooks::notify( &self.hooks, &key, Change::Remove );
+                    Edit::Insert( key, prop )
+                },
+                Edit::Replace( key, prop ) => {
+                    let old = match self.data.get_mut( &key ) {
+                        Some( current ) => mem::replace( current, prop ),
+                        None => continue
+                    };
+                    hooks::notify( &self.hooks, &key, Change::Set );
+                    Edit::Replace( key, old )
+                },
+                Edit::Restore( key, _, value ) => {
+                    let old = match self.data.get_mut( &key ) {
+                        Some( current ) => {
+                            let prop = dyn_property::with_value( current, value );
+                            mem::replace( current, prop )
+                        },
+                        None => continue
+                    };
+                    hooks::notify( &self.hooks, &key, Change::Set );
+                    Edit::Replace( key, old )
+                }
+            });
         }
+        ( inverse, true )
+    }
+
+    /// returns true if the edits of `step` can be applied in reverse order
+    ///
+    /// Inserted properties must not exist, removed and replaced ones must
+    /// exist and restored values must have the type of the property.
+    fn fits( &self, step: &[Edit<Key>] ) -> bool {
+        //the type of the properties changed by the edits checked so far, None if removed
+        let mut changed: Vec<( &Key, Option<TypeId> )> = Vec::new();
+        for edit in step.iter().rev() {
+            let key = match *edit {
+                Edit::Insert( ref key, _ ) | Edit::Remove( ref key )
+                | Edit::Replace( ref key, _ ) | Edit::Restore( ref key, _, _ ) => key
+            };
+            let current = match changed.iter().rev().find( |&&( other, _ )| other == key ) {
+                Some( &( _, type_id )) => type_id,
+                None => self.data.get( key ).map( dyn_property::inner_type_id )
+            };
+            let next = match ( edit, current ) {
+                ( &Edit::Insert( _, ref prop ), None ) => Some( dyn_property::inner_type_id( prop )),
+                ( &Edit::Remove( _ ), Some( _ )) => None,
+                ( &Edit::Replace( _, ref prop ), Some( _ )) => Some( dyn_property::inner_type_id( prop )),
+                ( &Edit::Restore( _, type_id, _ ), Some( current )) if type_id == current => Some( current ),
+                _ => return false
+            };
+            changed.push( ( key, next ));
+        }
+        true
+    }
+
+    /// clones the property `key` before it is changed, if the history is enabled
+    ///
+    /// `Ok( None )` means that the history is not enabled or that the
+    /// property was created without clone support.
+    fn snapshot( &self, key: &Key ) -> Result<Option<DynProperty>, PropertyError<()>> {
+        if self.history.is_none() {
+            return Ok( None );
+        }
+        match self.data.get( key ) {
+            Some( prop ) if prop.is_cloneable() => prop.try_clone().map( Some ).ok_or( PropertyError::Borrowed( () )),
+            _ => Ok( None )
+        }
+    }
+
+    /// records that the property `key` was replaced or removed, `old` is
+    /// the property before the change as returned by `snapshot`
+    ///
+    /// Without `old` the change can not be undone, so the history is cleared.
+    /// Returns false in this case.
+    fn record_displaced( &mut self, key: &Key, old: Option<DynProperty>,
+                         edit: fn( Key, DynProperty ) -> Edit<Key> ) -> bool
+    {
+        match ( self.history.as_mut(), old ) {
+            ( Some( history ), Some( prop )) => {
+                let key = ( history.key_cloner() )( key );
+                history.record( edit( key, prop ));
+                true
+            },
+            ( Some( history ), None ) => {
+                history.clear();
+                false
+            },
+            ( None, _ ) => true
+        }
+    }
+
+    fn hooks_mut( &mut self ) -> &mut Hooks<Key> {
+        if self.hooks.is_none() {
+            self.hooks = Some( Box::new( Hooks::new() ));
+        }
+        self.hooks.as_mut().unwrap()
+    }
+
+    /// returns true if the guards allow the operation on `key`
+    #[inline]
+    fn allows( &self, key: &Key, operation: Operation ) -> bool {
+        hooks::allows( &self.hooks, key, operation )
     }
 
     /// sets the property defined by key
     ///
     /// If the property identified by key exists and the property has the type given by
     /// `T` this methode will set the value as new value and will return the old value
-    /// as Ok( Box( T )). If the property does not exists or the type is wrong the passed
-    /// value will be returned as Err( Box( T ))
+    /// as Ok( Box( T )). If the property does not exists, the type is wrong, the property
+    /// is borrowed or a guard rejects the write a `PropertyError` containing the passed
+    /// value will be returned.
+    ///
+    /// Only own properties are set. A property inherited from the prototype
+    /// chain is not found, use `assign_property` to shadow it with a own one.
     ///
     /// This is mostly equivalent to using  `inner_dyn_object[key].set( value )`
     ///
@@ -53,63 +433,1099 @@ impl<Key> InnerDynObject<Key> where Key: Eq + Hash {
     /// 
     #[unstable( reason="redundant, might be removed" )]
     #[inline]
-    pub fn set_property<T>( &mut self, key: &Key, value: Box<T> ) -> Result<Box<T>,Box<T>> 
+    pub fn set_property<T>( &mut self, key: &Key, value: Box<T> ) -> Result<Box<T>,PropertyError<Box<T>>> 
         where T: Any + 'static 
     {
-        self.index_mut( key ).set( value )
+        let snapshot = match self.data.get( key ) {
+            Some( _ ) if !self.allows( key, Operation::AccessMut ) =>
+                return Err( PropertyError::Rejected( value )),
+            Some( prop ) if prop.is_inner_type::<T>() => match self.snapshot( key ) {
+                Ok( snapshot ) => snapshot,
+                Err( err ) => return Err( err.map_value( |_| value ))
+            },
+            _ => None
+        };
+        let res = match self.data.get_mut( key ) {
+            Some( prop ) => prop.set( value ),
+            None => Err( PropertyError::NotFound( value ))
+        };
+        if res.is_ok() {
+            self.record_displaced( key, snapshot, Edit::Replace );
+            hooks::notify( &self.hooks, key, Change::Set );
+        }
+        res
+    }
+    
+    /// sets the property defined by key, taken by value
+    ///
+    /// Works like `set_property` without boxing the values, so inherited
+    /// properties are not set either.
+    ///
+    pub fn set_value<T>( &mut self, key: &Key, value: T ) -> Result<T,PropertyError<T>>
+        where T: Any + 'static
+    {
+        let snapshot = match self.data.get( key ) {
+            Some( _ ) if !self.allows( key, Operation::AccessMut ) =>
+                return Err( PropertyError::Rejected( value )),
+            Some( prop ) if prop.is_inner_type::<T>() => match self.snapshot( key ) {
+                Ok( snapshot ) => snapshot,
+                Err( err ) => return Err( err.map_value( |_| value ))
+            },
+            _ => None
+        };
+        let res = match self.data.get_mut( key ) {
+            Some( prop ) => prop.set_value( value ),
+            None => Err( PropertyError::NotFound( value ))
+        };
+        if res.is_ok() {
+            self.record_displaced( key, snapshot, Edit::Replace );
+            hooks::notify( &self.hooks, key, Change::Set );
+        }
+        res
+    }
+
+    /// create a new property with a initial value
+    ///
+    /// Creaates a new property with given key and `initial_value setting` the type of
+    /// the property to the type of `initial_value`. If the property already exists
+    /// the given initialvalue will be returned as `Err( PropertyError::AlreadyExists( .. ))`
+    /// else `Ok( () )` will be returned. Only the own properties are checked,
+    /// so a property of the prototype is shadowed by the new property.
+    ///
+    pub fn create_property<T>( &mut self, key: Key, init_value: Box<T> ) -> Result<(),PropertyError<Box<T>>> 
+        where T: Any + 'static  
+    {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( init_value ))
+        } else if !self.allows( &key, Operation::Create ) {
+            Err( PropertyError::Rejected( init_value ))
+        } else {
+            self.insert_unchecked( key, DynProperty::new( init_value ));
+            Ok( () )
+        }
+    }
+    
+    /// create a new property with a initial value, taken by value
+    ///
+    /// Works like `create_property` but small values are stored without
+    /// any heap allocation, see `DynProperty::from_value`.
+    ///
+    pub fn create_value<T>( &mut self, key: Key, init_value: T ) -> Result<(),PropertyError<T>>
+        where T: Any + 'static
+    {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( init_value ))
+        } else if !self.allows( &key, Operation::Create ) {
+            Err( PropertyError::Rejected( init_value ))
+        } else {
+            self.insert_unchecked( key, DynProperty::from_value( init_value ));
+            Ok( () )
+        }
+    }
+
+    /// create a new property witch can be cloned
+    ///
+    /// Works like `create_property` but the property captures the clone
+    /// support of `T`, see `DynProperty::new_cloneable`. With the history
+    /// enabled, setting or removing such a property can be undone.
+    ///
+    pub fn create_cloneable<T>( &mut self, key: Key, init_value: Box<T> ) -> Result<(),PropertyError<Box<T>>>
+        where T: Any + Clone + 'static
+    {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( init_value ))
+        } else if !self.allows( &key, Operation::Create ) {
+            Err( PropertyError::Rejected( init_value ))
+        } else {
+            self.insert_unchecked( key, DynProperty::new_cloneable( init_value ));
+            Ok( () )
+        }
+    }
+
+    /// create a new property witch can be cloned, taken by value
+    ///
+    /// Works like `create_cloneable` without boxing the value. This is used
+    /// by `create_path` and the `dynobject!` macro.
+    ///
+    pub fn create_cloneable_value<T>( &mut self, key: Key, init_value: T ) -> Result<(),PropertyError<T>>
+        where T: Any + Clone + 'static
+    {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( init_value ))
+        } else if !self.allows( &key, Operation::Create ) {
+            Err( PropertyError::Rejected( init_value ))
+        } else {
+            self.insert_unchecked( key, DynProperty::from_cloneable_value( init_value ));
+            Ok( () )
+        }
+    }
+
+    /// adds a already created `DynProperty`
+    ///
+    /// This can be used to add properties created with special constructors
+    /// like `DynProperty::new_debug`. If the property already exists the
+    /// given property will be returned as `Err( PropertyError::AlreadyExists( .. ))`.
+    ///
+    pub fn insert_property( &mut self, key: Key, property: DynProperty ) -> Result<(),PropertyError<DynProperty>> {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( property ))
+        } else if !self.allows( &key, Operation::Create ) {
+            Err( PropertyError::Rejected( property ))
+        } else {
+            self.insert_unchecked( key, property );
+            Ok( () )
+        }
+    }
+
+    /// inserts a property witch does not exist yet notifying the observers
+    fn insert_unchecked( &mut self, key: Key, property: DynProperty ) {
+        match self.history {
+            Some( ref mut history ) => {
+                let copy = ( history.key_cloner() )( &key );
+                history.record( Edit::Remove( copy ));
+            },
+            None => {}
+        }
+        insert_and_notify( self, key, property );
+    }
+
+    /// removes a given property returning the old value of it
+    ///
+    /// If the property exists and the type match the old
+    /// value will eb returned wraped in a Ok-Result and the
+    /// property is removed. Else the property won't be changed
+    /// and a `PropertyError` is returned. If you e.g. try to remove a given property
+    /// not the using the right type the property will NOT be removed.
+    /// The same is true for a property witch is currently borrowed.
+    ///
+    pub fn remove_property<T>( &mut self, key: &Key ) -> Result<Box<T>, PropertyError<()>> 
+        where T: Any + 'static
+    {
+        match self.data.get( key ) {
+            Some( prop ) => {
+                if !self.allows( key, Operation::Remove ) {
+                    return Err( PropertyError::Rejected( () ));
+                }
+                if !prop.is_inner_type::<T>() {
+                    return Err( dyn_property::type_mismatch::<T, ()>( prop, () ));
+                }
+                if prop.is_borrowed() {
+                    return Err( PropertyError::Borrowed( () ));
+                }
+            },
+            None => return Err( PropertyError::NotFound( () ))
+        }
+        let snapshot = try!( self.snapshot( key ));
+        let value = match self.data.remove( key ).map( |prop| prop.destruct::<T>() ) {
+            Some( Some( value )) => value,
+            _ => return Err( PropertyError::NotFound( () ))
+        };
+        self.record_displaced( key, snapshot, Edit::Insert );
+        hooks::notify( &self.hooks, key, Change::Remove );
+        Ok( value )
+    }
+
+    /// assigns a value to a property like it is done in JavaScript
+    ///
+    /// If the property exists locally this works like `set_property` and the
+    /// old value is returned as `Ok( Some( .. ))`. If the property is only
+    /// inherited from the prototype chain a local property shadowing it is
+    /// created and `Ok( None )` is returned. The type of the inherited property
+    /// has to match, if no property exists `PropertyError::NotFound` is returned.
+    /// Shadowing a property is guarded as `Operation::Create`. If a prototype
+    /// which has to be searched is aquired mutable `PropertyError::Borrowed`
+    /// is returned.
+    ///
+    pub fn assign_property<T>( &mut self, key: Key, value: Box<T> ) -> Result<Option<Box<T>>,PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        if self.data.contains_key( &key ) {
+            return self.set_property( &key, value ).map( Some );
+        }
+        let found = match self.prototype {
+            Some( ref proto ) => match proto.try_aquire_ref() {
+                Ok( proto ) => proto.inherited_type( &key ),
+                Err( _ ) => Err( () )
+            },
+            None => Ok( None )
+        };
+        match found {
+            Ok( Some( ( type_id, _ ))) if type_id == TypeId::of::<T>() =>
+                self.create_property( key, value ).map( |_| None ),
+            Ok( Some( ( _, type_name ))) => Err( PropertyError::TypeMismatch {
+                expected: dyn_property::type_name::<T>(),
+                found: type_name,
+                value: value
+            }),
+            Ok( None ) => Err( PropertyError::NotFound( value )),
+            Err( _ ) => Err( PropertyError::Borrowed( value ))
+        }
+    }
+
+    /// removes a given property without knowing its type
+    ///
+    /// Returns the removed `DynProperty`, use `DynProperty::into_any` or
+    /// `DynProperty::destruct` to get the value. None is returned if the
+    /// property does not exist, is borrowed or a guard rejects the removal.
+    ///
+    pub fn remove_any( &mut self, key: &Key ) -> Option<DynProperty> {
+        take_property( self, key ).ok()
+    }
+
+    fn is_removable( &self, key: &Key, prop: &DynProperty ) -> bool {
+        !prop.is_borrowed() && self.allows( key, Operation::Remove )
+    }
+
+    /// returns true if a given property exists
+    ///
+    /// Only the own properties are checked, see `has_property`.
+    ///
+    pub fn exists_property( &self, key: &Key ) -> bool {
+        self.data.contains_key( key )
+    }
+
+    /// returns the number of own properties
+    pub fn len( &self ) -> usize {
+        self.data.len()
+    }
+
+    /// returns true if the object has no own properties
+    pub fn is_empty( &self ) -> bool {
+        self.data.is_empty()
+    }
+
+    /// returns a iterator over the keys of the own properties
+    ///
+    pub fn keys( &self ) -> Keys<Key, S> {
+        iter::keys( &self.data )
+    }
+
+    /// returns a iterator over the own properties
+    ///
+    /// The order is unspecified. Properties a guard does not
+    /// allow to access are skipped.
+    ///
+    pub fn iter( &self ) -> Iter<Key, S> {
+        iter::iter( &self.data, &self.hooks )
+    }
+
+    /// returns a iterator over the own properties allowing to modify them
+    ///
+    /// Properties a guard does not allow to access mutable are skipped.
+    /// The properties are yielded as `PropertyWrite`, so the observers are
+    /// notified with `Change::Write` for every property the iterator yielded.
+    /// This is also true for `iter_mut_of` and `iter_dyn_mut`.
+    ///
+    pub fn iter_mut( &mut self ) -> IterMut<Key, S> {
+        iter::iter_mut( &mut self.data, &self.hooks )
+    }
+
+    /// returns a iterator over the values of all own properties with the inner type `T`
+    ///
+    /// Properties with a different type or a value witch is currently
+    /// borrowed mutable are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::InnerDynObject;
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.create_property( "hp", Box::new( 100u32 )).unwrap();
+    /// obj.create_property( "name", Box::new( "orc" )).unwrap();
+    /// let values: Vec<( &&'static str, &u32 )> = obj.iter_of::<u32>().collect();
+    /// assert_eq!( values, vec![ ( &"hp", &100u32 ) ] );
+    /// ```
+    ///
+    pub fn iter_of<T>( &self ) -> IterOf<Key, T, S> where T: Any + 'static {
+        iter::iter_of( self.iter() )
+    }
+
+    /// returns a iterator over the values of all own properties with the inner type `T`
+    /// allowing to modify them
+    ///
+    /// See `iter_of` and `iter_mut`.
+    ///
+    pub fn iter_mut_of<T>( &mut self ) -> IterMutOf<Key, T, S> where T: Any + 'static {
+        iter::iter_mut_of( self.iter_mut() )
+    }
+
+    /// returns a iterator over the values of all own properties witch can be viewed as `Trait`
+    ///
+    /// Properties witch did not capture a cast to `Trait`, see
+    /// `DynProperty::as_dyn`, are skipped like the ones a guard
+    /// does not allow to access.
+    ///
+    pub fn iter_dyn<Trait: ?Sized>( &self ) -> IterDyn<Key, Trait, S> where Trait: 'static {
+        iter::iter_dyn( self.iter(), None )
+    }
+
+    /// returns a iterator over the values of all own properties witch can be viewed as `Trait`,
+    /// using the casts registered in `registry` for properties witch did not capture one
+    ///
+    /// Unlike `CastRegistry::attach` this also covers properties created
+    /// after registering the casts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dynobject;
+    /// # use std::fmt::Display;
+    /// # use dynobject::{InnerDynObject, CastRegistry};
+    /// # fn main() {
+    /// let mut registry = CastRegistry::new();
+    /// registry.register( dyn_cast!( u32 => Display ));
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.create_value( "count", 3u32 ).unwrap();
+    /// obj.create_value( "flags", vec![ true ] ).unwrap();
+    /// let shown: Vec<String> = obj.iter_dyn_with::<Display>( &registry )
+    ///                             .map( |( key, value )| format!( "{}={}", key, value )).collect();
+    /// assert_eq!( shown, vec![ "count=3".to_string() ] );
+    /// # }
+    /// ```
+    ///
+    pub fn iter_dyn_with<'a, Trait: ?Sized>( &'a self, registry: &'a CastRegistry ) -> IterDyn<'a, Key, Trait, S>
+        where Trait: 'static
+    {
+        iter::iter_dyn( self.iter(), Some( registry ))
+    }
+
+    /// returns a iterator over the values of all own properties witch can be viewed as `Trait`
+    /// allowing to modify them
+    ///
+    /// See `iter_dyn` and `iter_mut`.
+    ///
+    pub fn iter_dyn_mut<Trait: ?Sized>( &mut self ) -> IterDynMut<Key, Trait, S> where Trait: 'static {
+        iter::iter_dyn_mut( self.iter_mut(), None )
+    }
+
+    /// returns a iterator over the values of all own properties witch can be viewed as `Trait`
+    /// allowing to modify them, using the casts registered in `registry`
+    ///
+    /// See `iter_dyn_with` and `iter_mut`.
+    ///
+    pub fn iter_dyn_mut_with<'a, Trait: ?Sized>( &'a mut self, registry: &'a CastRegistry )
+        -> IterDynMut<'a, Key, Trait, S>
+        where Trait: 'static
+    {
+        iter::iter_dyn_mut( self.iter_mut(), Some( registry ))
+    }
+
+    /// returns true if a given property exists locally or in the prototype chain
+    ///
+    /// False is returned if a prototype which has to be searched is aquired mutable.
+    ///
+    pub fn has_property( &self, key: &Key ) -> bool {
+        match self.inherited_type( key ) {
+            Ok( found ) => found.is_some(),
+            Err( _ ) => false
+        }
+    }
+
+    /// returns the own property defined by key, skipping the prototype chain
+    ///
+    /// None is returned if a guard rejects the access.
+    ///
+    pub fn own_property( &self, key: &Key ) -> Option<&DynProperty> {
+        self.access( key ).ok()
+    }
+
+    /// returns the property defined by key
+    ///
+    /// Works like indexing but returns `PropertyError::NotFound` or
+    /// `PropertyError::Rejected` instead of the undefined property.
+    ///
+    pub fn access( &self, key: &Key ) -> Result<&DynProperty, PropertyError<()>> {
+        match self.data.get( key ) {
+            Some( _ ) if !self.allows( key, Operation::Access ) => Err( PropertyError::Rejected( () )),
+            Some( prop ) => Ok( prop ),
+            None => Err( PropertyError::NotFound( () ))
+        }
+    }
+
+    /// returns a mutable reference to the value of the property defined by key
+    ///
+    /// Works like `inner_dyn_object[key].as_mut::<T>()` but the observers
+    /// are notified with `Change::Write` when the returned `PropertyWrite`
+    /// is droped. None is returned if the property does not exist, has
+    /// a different type, is borrowed or a guard rejects the access.
+    ///
+    pub fn as_mut<'a, T>( &'a mut self, key: &'a Key ) -> Option<PropertyWrite<'a, Key, T>>
+        where T: Any + 'static
+    {
+        if !self.allows( key, Operation::AccessMut ) {
+            return None;
+        }
+        match self.data.get_mut( key ) {
+            Some( prop ) => match prop.as_mut::<T>() {
+                Some( value ) => Some( hooks::property_write( value, key, &self.hooks )),
+                None => None
+            },
+            None => None
+        }
+    }
+
+    /// returns the property defined by key mutable
+    ///
+    /// Works like `access` but the guards are asked for `Operation::AccessMut`.
+    /// Changes done over the returned property are not observed, use `as_mut`
+    /// or `set_property` for this.
+    ///
+    pub fn access_mut( &mut self, key: &Key ) -> Result<&mut DynProperty, PropertyError<()>> {
+        match self.data.get_mut( key ) {
+            Some( _ ) if !hooks::allows( &self.hooks, key, Operation::AccessMut ) =>
+                Err( PropertyError::Rejected( () )),
+            Some( prop ) => Ok( prop ),
+            None => Err( PropertyError::NotFound( () ))
+        }
+    }
+
+    /// calls `func` with the property defined by key, searching the prototype chain
+    ///
+    /// If the key does not exist locally the prototype is searched, then its
+    /// prototype and so on. If the key is not found at all `func` is called with
+    /// a property with the inner type `UndefinedProperty`, like it is done by
+    /// indexing or if a guard rejects the access. The property is passed to a
+    /// closure because the prototype is only aquired while `func` runs. A
+    /// prototype which is aquired mutable is treated like a missing one.
+    ///
+    pub fn with_property<R, F>( &self, key: &Key, func: F ) -> R
+        where F: FnOnce( &DynProperty ) -> R
+    {
+        match self.data.get( key ) {
+            Some( prop ) => if self.allows( key, Operation::Access ) {
+                func( prop )
+            } else {
+                func( &self.undefined_property )
+            },
+            None => match self.prototype {
+                Some( ref proto ) => match proto.try_aquire_ref() {
+                    Ok( proto ) => proto.with_property( key, func ),
+                    Err( _ ) => func( &self.undefined_property )
+                },
+                None => func( &self.undefined_property )
+            }
+        }
+    }
+
+    /// returns the type of the property found by `with_property`
+    ///
+    /// `Err( () )` means that a prototype which has to be searched is aquired mutable.
+    fn inherited_type( &self, key: &Key ) -> Result<Option<( TypeId, &'static str )>, ()> {
+        match self.data.get( key ) {
+            Some( prop ) => Ok( Some( ( dyn_property::inner_type_id( prop ), prop.type_name() ))),
+            None => match self.prototype {
+                Some( ref proto ) => match proto.try_aquire_ref() {
+                    Ok( proto ) => proto.inherited_type( key ),
+                    Err( _ ) => Err( () )
+                },
+                None => Ok( None )
+            }
+        }
+    }
+
+    /// returns true if a given property exists and has the given type
+    ///
+    /// Only the own properties are checked, the prototype chain is not searched.
+    /// Like indexing false is returned if a guard rejects the access.
+    ///
+    pub fn exists_property_with_type<T>( &self, key: &Key ) -> bool 
+        where T: Any + 'static 
+    {
+        self.index( key ).is_inner_type::<T>()
+    }
+
+    /// borrows the value of a single property
+    ///
+    /// Returns a `PropRef` guard witch does not borrow this object, see
+    /// `DynProperty::borrow_ref`. If the property does not exist, has
+    /// a different type, is borrowed mutable or a guard rejects the access
+    /// a `PropertyError` is returned.
+    ///
+    pub fn borrow_prop<T>( &mut self, key: &Key ) -> Result<PropRef<T>, PropertyError<()>>
+        where T: Any + 'static
+    {
+        match self.data.get_mut( key ) {
+            Some( prop ) => {
+                if !hooks::allows( &self.hooks, key, Operation::Access ) {
+                    return Err( PropertyError::Rejected( () ));
+                }
+                if !prop.is_inner_type::<T>() {
+                    return Err( dyn_property::type_mismatch::<T, ()>( prop, () ));
+                }
+                prop.borrow_ref::<T>().ok_or( PropertyError::Borrowed( () ))
+            },
+            None => Err( PropertyError::NotFound( () ))
+        }
+    }
+
+    /// mutable borrows the value of a single property
+    ///
+    /// Returns a `PropMut` guard witch does not borrow this object, see
+    /// `DynProperty::borrow_mut`. If the property does not exist, has
+    /// a different type, is borrowed or a guard rejects the access
+    /// a `PropertyError` is returned. As the guard does not refer to this
+    /// object, changes done over it are not observed.
+    ///
+    pub fn borrow_prop_mut<T>( &mut self, key: &Key ) -> Result<PropMut<T>, PropertyError<()>>
+        where T: Any + 'static
+    {
+        match self.data.get_mut( key ) {
+            Some( prop ) => {
+                if !hooks::allows( &self.hooks, key, Operation::AccessMut ) {
+                    return Err( PropertyError::Rejected( () ));
+                }
+                if !prop.is_inner_type::<T>() {
+                    return Err( dyn_property::type_mismatch::<T, ()>( prop, () ));
+                }
+                prop.borrow_mut::<T>().ok_or( PropertyError::Borrowed( () ))
+            },
+            None => Err( PropertyError::NotFound( () ))
+        }
+    }
+
+    /// returns a reference to the value of the property defined by a typed key
+    ///
+    /// This is equivalent to `inner_dyn_object[key].as_ref::<T>()` but
+    /// the type is taken from the `TypedKey`. Like indexing it only returns
+    /// own properties, use `with_property` to search the prototype chain.
+    ///
+    pub fn get<T>( &self, key: &TypedKey<Key, T> ) -> Option<&T>
+        where T: Any + 'static
+    {
+        self.index( key.key() ).as_ref::<T>()
+    }
+
+    /// returns a mutable reference to the value of the property defined by a typed key
+    ///
+    /// This is equivalent to `as_mut::<T>( key )` but the type is taken
+    /// from the `TypedKey`. The observers are notified with `Change::Write`
+    /// when the returned `PropertyWrite` is droped.
+    ///
+    pub fn get_mut<'a, T>( &'a mut self, key: &'a TypedKey<Key, T> ) -> Option<PropertyWrite<'a, Key, T>>
+        where T: Any + 'static
+    {
+        self.as_mut::<T>( key.key() )
+    }
+
+    /// returns a copy of the value of the property defined by key converted to `T`
+    ///
+    /// Works like `inner_dyn_object[key].get_as::<T>( registry )`,
+    /// see `DynProperty::get_as`.
+    ///
+    pub fn get_as<T>( &self, key: &Key, registry: &ConversionRegistry ) -> Option<T>
+        where T: Any + Clone + 'static
+    {
+        self.index( key ).get_as::<T>( registry )
+    }
+
+    /// converts the value of the property defined by key to `T`
+    ///
+    /// See `DynProperty::convert_in_place`. If the property does not
+    /// exist or a guard rejects the write a `PropertyError` is returned.
+    /// The observers are notified with `Change::Set` if the type changed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{InnerDynObject, ConversionRegistry};
+    /// let registry = ConversionRegistry::new();
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.create_value( "count", 3u8 ).unwrap();
+    /// assert_eq!( obj.get_as::<u32>( &"count", &registry ), Some( 3u32 ));
+    /// obj.convert_in_place::<u32>( &"count", &registry ).unwrap();
+    /// *obj.as_mut::<u32>( &"count" ).unwrap() += 1000;
+    /// assert_eq!( obj["count"].as_ref::<u32>(), Some( &1003u32 ));
+    /// ```
+    ///
+    pub fn convert_in_place<T>( &mut self, key: &Key, registry: &ConversionRegistry ) -> Result<(), PropertyError<()>>
+        where T: Any + 'static
+    {
+        match self.data.get( key ) {
+            Some( prop ) if prop.is_inner_type::<T>() => return Ok( () ),
+            Some( _ ) => {},
+            None => return Err( PropertyError::NotFound( () ))
+        }
+        if !self.allows( key, Operation::AccessMut ) {
+            return Err( PropertyError::Rejected( () ));
+        }
+        let converted = match self.data.get( key ) {
+            Some( prop ) if prop.is_borrowed() => return Err( PropertyError::Borrowed( () )),
+            Some( prop ) => match conversion::convert::<T>( registry, prop ) {
+                Some( converted ) => converted,
+                None => return Err( dyn_property::type_mismatch::<T, ()>( prop, () ))
+            },
+            None => return Err( PropertyError::NotFound( () ))
+        };
+        let old = match self.data.get_mut( key ) {
+            Some( prop ) => mem::replace( prop, converted ),
+            None => return Err( PropertyError::NotFound( () ))
+        };
+        //the old property is moved into the history, so it does not need to be cloneable
+        self.record_displaced( key, Some( old ), Edit::Replace );
+        hooks::notify( &self.hooks, key, Change::Set );
+        Ok( () )
+    }
+
+    /// sets the property defined by a typed key
+    ///
+    /// See `set_property`, inherited properties are not set.
+    ///
+    pub fn set<T>( &mut self, key: &TypedKey<Key, T>, value: Box<T> ) -> Result<Box<T>,PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        self.set_property( key.key(), value )
+    }
+}
+
+impl<Key> InnerDynObject<Key, BTreeMapStorage<Key>> where Key: Ord {
+
+    /// returns a iterator over the own properties with keys in the given range
+    ///
+    /// The properties are ordered by their keys. Like with `iter`
+    /// properties a guard does not allow to access are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::Bound;
+    /// # use dynobject::{InnerDynObject, PropertyStorage, BTreeMapStorage};
+    /// let mut obj = InnerDynObject::with_storage( BTreeMapStorage::new() );
+    /// for idx in 0..5u32 {
+    ///     obj.create_value( idx, idx * 10 ).unwrap();
+    /// }
+    /// let keys: Vec<u32> = obj.range( Bound::Included( &1 ), Bound::Excluded( &3 ))
+    ///                         .map( |( key, _ )| *key ).collect();
+    /// assert_eq!( keys, vec![ 1, 2 ] );
+    /// ```
+    ///
+    pub fn range( &self, min: Bound<&Key>, max: Bound<&Key> ) -> Range<Key> {
+        iter::range( self.data.range( min, max ), &self.hooks )
+    }
+}
+
+impl<Key, S> InnerDynObject<Key, S> where Key: Eq + Clone, S: PropertyStorage<Key> {
+
+    /// creates the property defined by a typed key
+    ///
+    /// See `create_property`
+    ///
+    pub fn create<T>( &mut self, key: &TypedKey<Key, T>, init_value: Box<T> ) -> Result<(),PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        self.create_property( key.key().clone(), init_value )
+    }
+
+    /// subscribes a observer witch is notified about changes of any property
+    ///
+    /// The observer receives the key and the kind of change after the
+    /// property was changed. Observers of the key are notified before the
+    /// observers of the object. The object is still borrowed while the
+    /// observers are called, so they can not access it. A observer of a
+    /// created property receives a clone of the key, as the key itself
+    /// is moved into the object.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::rc::Rc;
+    /// # use std::cell::Cell;
+    /// # use dynobject::InnerDynObject;
+    /// let changes = Rc::new( Cell::new( 0u32 ));
+    /// let counter = changes.clone();
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.subscribe( move |_: &&'static str, _| counter.set( counter.get() + 1 ));
+    /// obj.create_property( "hp", Box::new( 100u32 )).unwrap();
+    /// *obj.as_mut::<u32>( &"hp" ).unwrap() -= 10;
+    /// assert_eq!( changes.get(), 2 );
+    /// ```
+    ///
+    pub fn subscribe<F>( &mut self, observer: F ) -> ObserverId
+        where F: FnMut( &Key, Change ) + 'static
+    {
+        self.hooks_mut().subscribe( Box::new( observer ), clone_key::<Key> )
+    }
+
+    /// subscribes a observer witch is only notified about changes of the property `key`
+    ///
+    /// See `subscribe`.
+    ///
+    pub fn subscribe_key<F>( &mut self, key: Key, observer: F ) -> ObserverId
+        where F: FnMut( &Key, Change ) + 'static
+    {
+        self.hooks_mut().subscribe_key( key, Box::new( observer ), clone_key::<Key> )
+    }
+
+    /// returns the entry of the own property defined by key
+    ///
+    /// See `Entry`.
+    ///
+    pub fn entry( &mut self, key: Key ) -> Entry<Key, S> {
+        entry::entry( self, key )
+    }
+
+    /// removes all properties for witch `func` returns false
+    ///
+    /// Properties witch are borrowed or can not be removed because of a
+    /// guard are kept regardless of the result of `func`. The keys of the
+    /// properties to remove are collected before the first one is removed,
+    /// so the object stays intact if `func` or a guard panics.
+    ///
+    pub fn retain<F>( &mut self, mut func: F )
+        where F: FnMut( &Key, &DynProperty ) -> bool
+    {
+        let keys: Vec<Key> = self.data.iter()
+                                 .filter( |&( key, prop )| !func( key, prop ) && self.is_removable( key, prop ))
+                                 .map( |( key, _ )| key.clone() ).collect();
+        let mark = begin_history_group( self );
+        for key in keys.into_iter() {
+            let prop = match self.data.remove( &key ) {
+                Some( prop ) => prop,
+                None => continue
+            };
+            match self.history {
+                //the property is not needed anymore, so it can be moved into the history
+                Some( ref mut history ) => history.record( Edit::Insert( key.clone(), prop )),
+                None => {}
+            }
+            hooks::notify( &self.hooks, &key, Change::Remove );
+        }
+        end_history_group( self, mark, true );
+    }
+
+    /// removes all properties
+    ///
+    /// Like `retain` borrowed or guarded properties are kept.
+    ///
+    pub fn clear( &mut self ) {
+        self.retain( |_, _| false );
+    }
+
+    /// removes all properties returning them as iterator
+    ///
+    /// Like `retain` borrowed or guarded properties are kept. The properties
+    /// are removed immediately, not when the iterator is consumed. If the
+    /// history is enabled, removing a property witch can not be cloned can
+    /// not be undone and clears the history, these properties are listed by
+    /// `Drain::unrecorded`.
+    ///
+    pub fn drain( &mut self ) -> Drain<Key> {
+        let keys: Vec<Key> = self.data.iter()
+                                 .filter( |&( key, prop )| self.is_removable( key, prop ))
+                                 .map( |( key, _ )| key.clone() ).collect();
+        let mark = begin_history_group( self );
+        let mut drained = Vec::with_capacity( keys.len() );
+        let mut unrecorded = Vec::new();
+        for key in keys.into_iter() {
+            //is_removable ruled out borrowed properties, so cloning can not fail
+            let snapshot = self.snapshot( &key ).ok().and_then( |snapshot| snapshot );
+            let prop = match self.data.remove( &key ) {
+                Some( prop ) => prop,
+                None => continue
+            };
+            if !self.record_displaced( &key, snapshot, Edit::Insert ) {
+                unrecorded.push( key.clone() );
+            }
+            hooks::notify( &self.hooks, &key, Change::Remove );
+            drained.push( ( key, prop ));
+        }
+        end_history_group( self, mark, true );
+        iter::drain( drained, unrecorded )
+    }
+
+    /// starts recording changes, keeping at most `depth` undo steps
+    ///
+    /// Creating, setting and removing properties (including `retain`, `clear`
+    /// and `drain`) is recorded. Writes over `as_mut`, indexing or
+    /// `DynProperty` guards are not. The history keeps the displaced
+    /// properties, but as the methods removing or setting a property return
+    /// the old value, a clone is recorded. This needs the clone support
+    /// captured by `create_cloneable`, `create_cloneable_value` (witch are
+    /// used by `create_path` and `dynobject!`) or `DynProperty::new_cloneable`.
+    /// Setting or removing a property without it still works, but can not be
+    /// undone, so the history is cleared. `retain`, `clear`, `convert_in_place`
+    /// and changes done over a `Transaction` move the old properties into the
+    /// history, so they do not need to be cloned. Calling this again changes the depth.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::InnerDynObject;
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.enable_history( 10 );
+    /// obj.create_cloneable_value( "name", "a".to_string() ).unwrap();
+    /// obj.set_value( &"name", "b".to_string() ).unwrap();
+    /// assert!( obj.undo() );
+    /// assert_eq!( obj["name"].as_ref::<String>(), Some( &"a".to_string() ));
+    /// assert!( obj.redo() );
+    /// assert_eq!( obj["name"].as_ref::<String>(), Some( &"b".to_string() ));
+    /// ```
+    ///
+    pub fn enable_history( &mut self, depth: usize ) {
+        match self.history {
+            Some( ref mut history ) => return history.set_depth( depth ),
+            None => {}
+        }
+        self.history = Some( Box::new( History::new( depth, clone_key::<Key> )));
+    }
+
+    /// starts a transaction, see `Transaction`
+    ///
+    pub fn begin_transaction( &mut self ) -> Transaction<Key, S> {
+        transaction::begin( self )
+    }
+}
+
+impl<Key, S> InnerDynObject<Key, S> where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static {
+
+    /// creates a independent copy of this object
+    ///
+    /// Every property is cloned with `DynProperty::try_clone`, properties
+    /// containing a `DynObject<Key>` are deep cloned too. If a property was
+    /// created without clone support it is either skipped or
+    /// `DeepCloneError::NotCloneable` is returned, depending on `policy`.
+    /// The same is done with properties a guard does not allow to access,
+    /// returning `DeepCloneError::Rejected`, as the clone has no guards.
+    /// A nested object witch is reachable over multiple properties is
+    /// cloned multiple times. The clone shares the prototype.
+    ///
+    pub fn deep_clone( &self, policy: ClonePolicy ) -> Result<InnerDynObject<Key, S>, DeepCloneError<Key>> {
+        deep_clone_nested( self, policy, &mut Vec::new() )
+    }
+}
+
+/// returns true if the guards of `obj` allow the operation on `key`
+pub fn allows<Key, S>( obj: &InnerDynObject<Key, S>, key: &Key, operation: Operation ) -> bool
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    obj.allows( key, operation )
+}
+
+/// replaces the prototype of `obj` without checking for cycles
+pub fn set_prototype<Key, S>( obj: &mut InnerDynObject<Key, S>, prototype: Option<DynObject<Key, S>> )
+    -> Option<DynObject<Key, S>>
+{
+    mem::replace( &mut obj.prototype, prototype )
+}
+
+fn clone_key<Key: Clone>( key: &Key ) -> Key {
+    key.clone()
+}
+
+/// takes the history out of `obj`, so that changes are not recorded
+pub fn take_history<Key, S>( obj: &mut InnerDynObject<Key, S> ) -> Option<Box<History<Key>>> {
+    obj.history.take()
+}
+
+/// puts a history taken with `take_history` back
+pub fn put_history<Key, S>( obj: &mut InnerDynObject<Key, S>, history: Option<Box<History<Key>>> ) {
+    obj.history = history;
+}
+
+/// opens a history group, returns None if the history is not enabled
+pub fn begin_history_group<Key, S>( obj: &mut InnerDynObject<Key, S> ) -> Option<usize> {
+    obj.history.as_mut().map( |history| history.begin_group() )
+}
+
+/// closes a history group, dropping the changes recorded in it if `keep` is false
+pub fn end_history_group<Key, S>( obj: &mut InnerDynObject<Key, S>, mark: Option<usize>, keep: bool ) {
+    match ( obj.history.as_mut(), mark ) {
+        ( Some( history ), Some( mark )) => history.end_group( mark, keep ),
+        _ => {}
+    }
+}
+
+/// removes all properties and the prototype without calling the guards or observers
+///
+/// The history is dropped too, as it can contain removed properties.
+pub fn take_contents<Key, S>( obj: &mut InnerDynObject<Key, S> )
+    -> ( Vec<( Key, DynProperty )>, Option<DynObject<Key, S>> )
+    where S: PropertyStorage<Key>
+{
+    obj.history = None;
+    ( obj.data.take_all(), obj.prototype.take() )
+}
+
+/// inserts `prop` without calling the guards, `key` must not exist
+pub fn restore_property<Key, S>( obj: &mut InnerDynObject<Key, S>, key: Key, prop: DynProperty )
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    insert_and_notify( obj, key, prop );
+}
+
+/// inserts `prop` and notifies the observers afterwards, `key` must not exist
+fn insert_and_notify<Key, S>( obj: &mut InnerDynObject<Key, S>, key: Key, prop: DynProperty )
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    let observed = hooks::observed_key( &obj.hooks, &key );
+    obj.data.insert( key, prop );
+    match observed {
+        Some( key ) => hooks::notify( &obj.hooks, &key, Change::Create ),
+        None => {}
+    }
+}
+
+/// removes the property `key` without calling the guards
+pub fn discard_property<Key, S>( obj: &mut InnerDynObject<Key, S>, key: &Key )
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    if obj.data.remove( key ).is_some() {
+        hooks::notify( &obj.hooks, key, Change::Remove );
+    }
+}
+
+/// removes the property `key` of any type like `remove_any`, but reports why it failed
+pub fn take_property<Key, S>( obj: &mut InnerDynObject<Key, S>, key: &Key ) -> Result<DynProperty, PropertyError<()>>
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    match obj.data.get( key ) {
+        Some( prop ) => {
+            if !obj.allows( key, Operation::Remove ) {
+                return Err( PropertyError::Rejected( () ));
+            }
+            if prop.is_borrowed() {
+                return Err( PropertyError::Borrowed( () ));
+            }
+        },
+        None => return Err( PropertyError::NotFound( () ))
     }
-    
-    /// create a new property with a initial value
-    ///
-    /// Creaates a new property with given key and `initial_value setting` the type of
-    /// the property to the type of `initial_value`. If the property already exists
-    /// the given initialvalue will be returned as `Err( Box( T ))` else `Ok( () )` will
-    /// be returned.
-    ///
-    pub fn create_property<T>( &mut self, key: Key, init_value: Box<T> ) -> Result<(),Box<T>> 
-        where T: Any + 'static  
-    {
-        if self.data.contains_key( &key ) {
-            Err( init_value )
+    let snapshot = try!( obj.snapshot( key ));
+    let prop = match obj.data.remove( key ) {
+        Some( prop ) => prop,
+        None => return Err( PropertyError::NotFound( () ))
+    };
+    obj.record_displaced( key, snapshot, Edit::Insert );
+    hooks::notify( &obj.hooks, key, Change::Remove );
+    Ok( prop )
+}
+
+/// replaces the value of the property `key` without calling the guards
+pub fn restore_value<Key, S>( obj: &mut InnerDynObject<Key, S>, key: &Key, value: Box<Any> )
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    match obj.data.get_mut( key ) {
+        Some( prop ) => dyn_property::restore_value( prop, value ),
+        None => return
+    }
+    hooks::notify( &obj.hooks, key, Change::Set );
+}
+
+/// deep clones `obj`, `stack` contains the nested objects
+/// witch are currently cloned to detect cycles
+pub fn deep_clone_nested<Key, S>( obj: &InnerDynObject<Key, S>, policy: ClonePolicy, stack: &mut Vec<*const ()> )
+    -> Result<InnerDynObject<Key, S>, DeepCloneError<Key>>
+    where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static
+{
+    let mut clone = InnerDynObject::with_storage( S::new() );
+    clone.prototype = obj.prototype.clone();
+    for ( key, prop ) in obj.data.iter() {
+        let cloned = if !obj.allows( key, Operation::Access ) {
+            Err( DeepCloneError::Rejected( key.clone() ))
+        } else if prop.is_inner_type::<DynObject<Key, S>>() {
+            match prop.as_ref::<DynObject<Key, S>>() {
+                Some( nested ) => nested_deep_clone( nested, key, policy, stack )
+                                    .map( |nested| DynProperty::new( Box::new( nested ))),
+                None => Err( DeepCloneError::Borrowed( key.clone() ))
+            }
+        } else if prop.is_borrowed_mutable() {
+            Err( DeepCloneError::Borrowed( key.clone() ))
         } else {
-            self.data.insert( key, DynProperty::new( init_value ));
-            Ok( () )
+            prop.try_clone().ok_or( DeepCloneError::NotCloneable( key.clone() ))
+        };
+        match cloned {
+            Ok( prop ) => {
+                clone.data.insert( key.clone(), prop );
+            },
+            Err( DeepCloneError::NotCloneable( _ )) |
+            Err( DeepCloneError::Rejected( _ )) if policy == ClonePolicy::Skip => {},
+            Err( err ) => return Err( err )
         }
     }
-    
-    /// removes a given property returning the old value of it
-    ///
-    /// If the property exists and the type match the old
-    /// value will eb returned wraped in a Ok-Result and the
-    /// property is removed. Else the property won't be changed
-    /// and `Err( () )`. If you e.g. try to remove a given property
-    /// not the using the right type the property will NOT be removed.
-    ///
-    pub fn remove_property<T>( &mut self, key: &Key ) -> Result<Box<T>, ()> 
-        where T: Any + 'static
-    {
-        if !self.index( key ).is_inner_type::<T>() {
-            return Err( () );
-        }
-        Ok( self.data.remove( key ).unwrap().destruct::<T>().unwrap() )
+    Ok( clone )
+}
+
+/// borrows the property `key` of `obj` like `borrow_prop` without changing `obj`
+///
+/// Returns None if the value has to be shared first, see `dyn_property::borrow_shared`.
+pub fn borrow_shared_prop<T, Key, S>( obj: &InnerDynObject<Key, S>, key: &Key )
+    -> Option<Result<PropRef<T>, PropertyError<()>>>
+    where T: Any + 'static, Key: Eq, S: PropertyStorage<Key>
+{
+    match obj.data.get( key ) {
+        Some( _ ) if !obj.allows( key, Operation::Access ) => Some( Err( PropertyError::Rejected( () ))),
+        Some( prop ) if !prop.is_inner_type::<T>() =>
+            Some( Err( dyn_property::type_mismatch::<T, ()>( prop, () ))),
+        Some( prop ) => dyn_property::borrow_shared::<T>( prop )
+                            .map( |guard| guard.ok_or( PropertyError::Borrowed( () ))),
+        None => Some( Err( PropertyError::NotFound( () )))
     }
+}
 
-    /// returns true if a given property exists
-    pub fn exists_property( &self, key: &Key ) -> bool {
-        self.data.contains_key( key )
+/// returns the properties of `obj`
+pub fn properties<Key, S>( obj: &InnerDynObject<Key, S> ) -> &S {
+    &obj.data
+}
+
+/// returns the properties of `obj` mutable
+pub fn properties_mut<Key, S>( obj: &mut InnerDynObject<Key, S> ) -> &mut S {
+    &mut obj.data
+}
+
+/// returns the value of the property `key` as a `PropertyWrite` owning the key
+///
+/// Used by the entries, the guards are not asked.
+pub fn write_owned<'a, Key, S, T>( obj: &'a mut InnerDynObject<Key, S>, key: Key ) -> Option<PropertyWrite<'a, Key, T>>
+    where Key: Eq, S: PropertyStorage<Key>, T: Any + 'static
+{
+    let InnerDynObject { ref mut data, ref hooks, .. } = *obj;
+    match data.get_mut( &key ).and_then( |prop| prop.as_mut::<T>() ) {
+        Some( value ) => Some( hooks::property_write_owned( value, key, hooks )),
+        None => None
+    }
+}
+
+/// deep clones a `DynObject` found under the property `key`
+fn nested_deep_clone<Key, S>( obj: &DynObject<Key, S>, key: &Key, policy: ClonePolicy, stack: &mut Vec<*const ()> )
+    -> Result<DynObject<Key, S>, DeepCloneError<Key>>
+    where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static
+{
+    let ptr = &*obj.inner as *const _ as *const ();
+    if stack.contains( &ptr ) {
+        return Err( DeepCloneError::Cycle( key.clone() ));
     }
+    let inner = match obj.try_aquire_ref() {
+        Ok( inner ) => inner,
+        Err( _ ) => return Err( DeepCloneError::Borrowed( key.clone() ))
+    };
+    stack.push( ptr );
+    let res = deep_clone_nested( &*inner, policy, stack );
+    stack.pop();
+    res.map( DynObject::from_inner )
+}
 
-    /// returns true if a given property exists and has the given type
-    pub fn exists_property_with_type<T>( &self, key: &Key ) -> bool 
-        where T: Any + 'static 
-    {
-        self.index( key ).is_inner_type::<T>()
+/// formats the properties of `obj` using `fmt_key` for the keys
+pub fn fmt_properties<Key, S, F>( obj: &InnerDynObject<Key, S>, fter: &mut fmt::Formatter, fmt_key: F )
+    -> fmt::Result
+    where S: PropertyStorage<Key>, F: Fn( &Key, &mut fmt::Formatter ) -> fmt::Result
+{
+    try!( fter.write_str( "{" ));
+    for ( idx, ( key, prop )) in obj.data.iter().enumerate() {
+        if idx > 0 {
+            try!( fter.write_str( ", " ));
+        }
+        try!( fmt_key( key, fter ));
+        try!( fter.write_str( ": " ));
+        try!( dyn_property::fmt_type_and_value( prop, fter ));
     }
+    fter.write_str( "}" )
+}
 
-    //TODO add remove_typeles to remove without knowing the type returning Box<Any>
+impl<Key, S> fmt::Debug for InnerDynObject<Key, S> where Key: Eq + fmt::Debug, S: PropertyStorage<Key> {
+    fn fmt( &self, fter: &mut fmt::Formatter ) -> fmt::Result {
+        fmt_properties( self, fter, |key, fter| write!( fter, "{:?}", key ))
+    }
 }
 
-impl<Key: Hash+Eq> Index<Key> for InnerDynObject<Key> {
+impl<Key, S> Index<Key> for InnerDynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
     type Output = DynProperty;
  
     /// return a reference to a `DynProperty` for a given key
@@ -117,16 +1533,19 @@ impl<Key: Hash+Eq> Index<Key> for InnerDynObject<Key> {
     /// If the key exists in this `InnerDynObject` a reference to
     /// the associated property will be returned. If not a reference to
     /// a property with the inner type `UndefinedProperty` will be returned.
+    /// The prototype chain is not searched, because the returned reference
+    /// can not keep the prototype aquired, use `with_property` for this.
+    /// If a guard rejects the access the undefined property is returned too.
     ///
     fn index<'a>( &'a self, index: &Key ) -> &'a DynProperty {
-        match self.data.get( index ) {
-            Some( data ) => data,
-            None => &self.undefined_property
+        match self.access( index ) {
+            Ok( data ) => data,
+            Err( _ ) => &self.undefined_property
         }
     }
 }
 
-impl<Key: Hash+Eq> IndexMut<Key> for InnerDynObject<Key> {
+impl<Key, S> IndexMut<Key> for InnerDynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
 
 
     /// return a mutable referenc to a `DynProperty` for a given key
@@ -134,14 +1553,20 @@ impl<Key: Hash+Eq> IndexMut<Key> for InnerDynObject<Key> {
     /// If the key exists in this `InnerDynObject` a reference to
     /// the associated property will be returned. If not a reference to
     /// a property with the inner type `UndefinedProperty` will be returned.
+    /// This is also the case if a guard rejects the access. Changes done over
+    /// the returned reference are not observed, use `as_mut` for this.
     ///
-    /// Note: that it is not a problem to return a mutable reference to a UndefinedProperty
-    /// because UndefinedProperty is a zero sized type and therfor has only one representation.
+    /// The undefined property is created again on every call, so assigning
+    /// to it or borrowing it does not affect other missing or rejected keys.
     ///
     fn index_mut<'a>( &'a mut self, index: &Key ) -> &'a mut DynProperty {
+        let allowed = hooks::allows( &self.hooks, index, Operation::AccessMut );
         match self.data.get_mut( index ) {
-            Some( data ) => data,
-            None => &mut self.undefined_property
+            Some( data ) if allowed => data,
+            _ => {
+                self.undefined_property_mut = undefined_property();
+                &mut self.undefined_property_mut
+            }
         }
     }
 }
@@ -150,7 +1575,17 @@ impl<Key: Hash+Eq> IndexMut<Key> for InnerDynObject<Key> {
 mod test {
     use super::InnerDynObject;
     use super::UndefinedProperty;
+    use super::super::dyn_property::DynProperty;
+    use super::super::deep_clone::{ClonePolicy, DeepCloneError};
+    use super::super::typed_key::TypedKey;
+    use super::super::property_error::PropertyError;
     use super::undefined_property;
+    use super::super::DynObject;
+    use super::super::hooks::{Operation, Change};
+    use super::super::entry::Entry;
+    use super::super::storage::{PropertyStorage, VecStorage, BTreeMapStorage};
+    use super::super::conversion::ConversionRegistry;
+    use std::collections::Bound;
 
     fn create_dummy() -> InnerDynObject<&'static str> {
         InnerDynObject::<&'static str>::new()
@@ -204,7 +1639,8 @@ mod test {
     fn create_property_should_return_false_if_key_already_exists() {
         let mut obj = create_dummy();
         assert!(  obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
-        assert!( !obj.create_property( "hallo", Box::new( 20i32 )).is_ok() );
+        let res = obj.create_property( "hallo", Box::new( 20i32 ));
+        assert_eq!( res, Err( PropertyError::AlreadyExists( Box::new( 20i32 ))) );
 
     }
 
@@ -213,7 +1649,7 @@ mod test {
         let mut obj = create_dummy();
         assert!( !obj.exists_property( &"hallo" ) );
         let res = obj.set_property( &"hallo", Box::new( 44i32 ));
-        assert_eq!( res, Err( Box::new( 44i32 )) );
+        assert_eq!( res, Err( PropertyError::NotFound( Box::new( 44i32 ))) );
     }
 
     #[test]
@@ -221,7 +1657,14 @@ mod test {
         let mut obj = create_dummy();
         assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
         let res = obj.set_property( &"hallo", Box::new( "oh falsch" ));
-        assert_eq!( res, Err( Box::new( "oh falsch" )) );
+        match res {
+            Err( PropertyError::TypeMismatch { expected, found, value } ) => {
+                assert_eq!( expected, "&str" );
+                assert_eq!( found, "i32" );
+                assert_eq!( value, Box::new( "oh falsch" ));
+            },
+            _ => panic!( "expected a type mismatch" )
+        }
     }
     
     #[test]
@@ -238,7 +1681,7 @@ mod test {
     fn remove_property_should_fail_if_property_does_not_exists() {
         let mut obj = create_dummy();
         let res = obj.remove_property::<i32>( &"hallo" );
-        assert_eq!( res, Err( () ) );
+        assert_eq!( res, Err( PropertyError::NotFound( () )) );
     }
 
     #[test]
@@ -246,7 +1689,7 @@ mod test {
         let mut obj = create_dummy();
         assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
         let res = obj.remove_property::<u16>( &"hallo" );
-        assert_eq!( res, Err( () ) );
+        assert_eq!( res, Err( PropertyError::TypeMismatch { expected: "u16", found: "i32", value: () }) );
         assert!( obj.exists_property( &"hallo" ));
     }
 
@@ -258,6 +1701,450 @@ mod test {
         assert_eq!( res, Ok( Box::new( 23i32 )) );
     }
 
+    #[test]
+    fn remove_property_should_fail_if_the_property_is_borrowed() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let guard = obj.borrow_prop::<i32>( &"hallo" ).unwrap();
+        assert_eq!( obj.remove_property::<i32>( &"hallo" ), Err( PropertyError::Borrowed( () )) );
+        assert_eq!( *guard, 23i32 );
+    }
+
+    #[test]
+    fn writes_over_a_rejected_index_should_not_leak_into_other_keys() {
+        let mut obj = create_dummy();
+        obj.create_value( "secret", 1u32 ).unwrap();
+        obj.add_guard( |key: &&'static str, op| !( *key == "secret" && op == Operation::AccessMut ));
+        obj["secret"] = DynProperty::from_value( 5u32 );
+        assert!( obj["other"].is_inner_type::<UndefinedProperty>() );
+        assert!( obj["other"].as_mut::<UndefinedProperty>().is_some() );
+        assert_eq!( obj["secret"].as_ref::<u32>(), Some( &1u32 ));
+        let guard = obj["secret"].borrow_mut::<UndefinedProperty>().unwrap();
+        assert!( !obj["other"].is_borrowed() );
+        assert!( obj["other"].borrow_mut::<UndefinedProperty>().is_some() );
+        drop( guard );
+    }
+
+    #[test]
+    fn borrow_prop_should_fail_if_property_does_not_exist() {
+        let mut obj = create_dummy();
+        assert_eq!( obj.borrow_prop::<i32>( &"hallo" ).err(), Some( PropertyError::NotFound( () )) );
+        assert!( obj.borrow_prop_mut::<UndefinedProperty>( &"hallo" ).is_err() );
+    }
+
+    #[test]
+    fn borrow_prop_mut_should_fail_if_the_property_is_already_borrowed() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let guard = obj.borrow_prop_mut::<i32>( &"hallo" ).unwrap();
+        assert_eq!( obj.borrow_prop_mut::<i32>( &"hallo" ).err(), Some( PropertyError::Borrowed( () )) );
+        assert_eq!( obj.borrow_prop::<i32>( &"hallo" ).err(), Some( PropertyError::Borrowed( () )) );
+    }
+
+    #[test]
+    fn typed_key_accessors_should_use_the_type_of_the_key() {
+        let key = TypedKey::<&'static str, i32>::new( "hallo" );
+        let mut obj = create_dummy();
+        assert_eq!( obj.get( &key ), None );
+        assert!( obj.create( &key, Box::new( 23i32 )).is_ok() );
+        assert!( obj.create( &key, Box::new( 24i32 )).is_err() );
+        *obj.get_mut( &key ).unwrap() += 1;
+        assert_eq!( obj.set( &key, Box::new( 3i32 )), Ok( Box::new( 24i32 )) );
+        assert_eq!( obj.get( &key ), Some( &3i32 ) );
+    }
+
+    #[test]
+    fn typed_key_accessors_should_fail_if_property_has_a_different_type() {
+        let key = TypedKey::<&'static str, i32>::new( "hallo" );
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23u8 )).is_ok() );
+        assert_eq!( obj.get( &key ), None );
+        assert!( obj.get_mut( &key ).is_none() );
+        assert!( obj.set( &key, Box::new( 3i32 )).is_err() );
+    }
+
+    #[test]
+    fn insert_property_should_fail_if_key_already_exists() {
+        let mut obj = create_dummy();
+        assert!( obj.insert_property( "hallo", DynProperty::new( Box::new( 1u8 ))).is_ok() );
+        let res = obj.insert_property( "hallo", DynProperty::new( Box::new( 2u8 )));
+        match res {
+            Err( PropertyError::AlreadyExists( prop )) => assert_eq!( prop.as_ref::<u8>(), Some( &2u8 )),
+            _ => panic!( "expected AlreadyExists" )
+        }
+        assert_eq!( obj["hallo"].as_ref::<u8>(), Some( &1u8 ));
+    }
+
+    #[test]
+    fn debug_should_list_keys_with_types() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.insert_property( "welt", DynProperty::new_debug( Box::new( 1u8 ))).is_ok() );
+        let output = format!( "{:?}", obj );
+        assert!( output == r#"{"hallo": i32, "welt": u8 = 1}"#
+              || output == r#"{"welt": u8 = 1, "hallo": i32}"# );
+    }
+
+    #[test]
+    fn deep_clone_should_fail_if_a_property_is_not_cloneable() {
+        let mut obj = create_dummy();
+        assert!( obj.insert_property( "hallo", DynProperty::new_cloneable( Box::new( 1u8 ))).is_ok() );
+        assert!( obj.create_property( "welt", Box::new( 2u8 )).is_ok() );
+        let res = obj.deep_clone( ClonePolicy::Fail );
+        assert_eq!( res.err(), Some( DeepCloneError::NotCloneable( "welt" )) );
+    }
+
+    #[test]
+    fn deep_clone_should_respect_guards() {
+        let mut obj = create_dummy();
+        assert!( obj.insert_property( "hallo", DynProperty::new_cloneable( Box::new( 1u8 ))).is_ok() );
+        assert!( obj.insert_property( "secret", DynProperty::new_cloneable( Box::new( 2u8 ))).is_ok() );
+        obj.add_key_guard( "secret", |_: &&'static str, op| op != Operation::Access );
+        assert_eq!( obj.deep_clone( ClonePolicy::Fail ).err(), Some( DeepCloneError::Rejected( "secret" )) );
+        let clone = obj.deep_clone( ClonePolicy::Skip ).ok().unwrap();
+        assert!( clone.exists_property( &"hallo" ));
+        assert!( !clone.exists_property( &"secret" ));
+    }
+
+    #[test]
+    fn deep_clone_should_skip_not_cloneable_properties_if_requested() {
+        let mut obj = create_dummy();
+        assert!( obj.insert_property( "hallo", DynProperty::new_cloneable( Box::new( 1u8 ))).is_ok() );
+        assert!( obj.create_property( "welt", Box::new( 2u8 )).is_ok() );
+        let mut clone = obj.deep_clone( ClonePolicy::Skip ).ok().unwrap();
+        assert!( !clone.exists_property( &"welt" ));
+        *clone["hallo"].as_mut::<u8>().unwrap() = 4;
+        assert_eq!( obj["hallo"].as_ref::<u8>(), Some( &1u8 ));
+    }
+
+    fn create_prototype() -> DynObject<&'static str> {
+        let proto = DynObject::<&'static str>::new();
+        assert!( proto.aquire().create_property( "hp", Box::new( 100u32 )).is_ok() );
+        proto
+    }
+
+    #[test]
+    fn with_property_should_search_the_prototype_chain() {
+        let base = create_prototype();
+        let proto = DynObject::with_prototype( base );
+        assert!( proto.aquire().create_property( "name", Box::new( "orc" )).is_ok() );
+        let obj = InnerDynObject::with_prototype( proto );
+        assert_eq!( obj.with_property( &"hp", |prop| prop.as_ref::<u32>().cloned() ), Some( 100u32 ));
+        assert_eq!( obj.with_property( &"name", |prop| prop.as_ref::<&'static str>().cloned() ), Some( "orc" ));
+        assert!( obj.with_property( &"mp", |prop| prop.is_inner_type::<UndefinedProperty>() ));
+        assert!( obj.has_property( &"hp" ));
+        assert!( !obj.exists_property( &"hp" ));
+        assert!( obj.own_property( &"hp" ).is_none() );
+        assert!( obj["hp"].is_inner_type::<UndefinedProperty>() );
+    }
+
+    #[test]
+    fn assign_property_should_shadow_the_prototype() {
+        let proto = create_prototype();
+        let mut obj = InnerDynObject::with_prototype( proto.clone() );
+        assert_eq!( obj.assign_property( "hp", Box::new( 50u32 )), Ok( None ));
+        assert_eq!( obj.assign_property( "hp", Box::new( 40u32 )), Ok( Some( Box::new( 50u32 ))));
+        assert_eq!( obj.own_property( &"hp" ).unwrap().as_ref::<u32>(), Some( &40u32 ));
+        assert_eq!( proto.aquire()["hp"].as_ref::<u32>(), Some( &100u32 ));
+    }
+
+    #[test]
+    fn assign_property_should_fail_if_the_inherited_type_mismatches() {
+        let mut obj = InnerDynObject::with_prototype( create_prototype() );
+        assert_eq!( obj.assign_property( "hp", Box::new( 50i8 )),
+                    Err( PropertyError::TypeMismatch { expected: "i8", found: "u32", value: Box::new( 50i8 ) }) );
+        assert_eq!( obj.assign_property( "mp", Box::new( 50u32 )), Err( PropertyError::NotFound( Box::new( 50u32 ))));
+    }
+
+    #[test]
+    fn lookups_should_not_panic_if_the_prototype_is_aquired() {
+        let proto = create_prototype();
+        let mut obj = InnerDynObject::with_prototype( proto.clone() );
+        let data = proto.aquire();
+        assert!( !obj.has_property( &"hp" ));
+        assert!( obj.with_property( &"hp", |prop| prop.is_inner_type::<UndefinedProperty>() ));
+        assert_eq!( obj.assign_property( "hp", Box::new( 50u32 )), Err( PropertyError::Borrowed( Box::new( 50u32 ))));
+        drop( data );
+        assert!( obj.has_property( &"hp" ));
+    }
+
+    #[test]
+    fn guards_should_reject_operations_with_rejected() {
+        let mut obj = create_dummy();
+        obj.add_guard( |_: &&'static str, op| op != Operation::Remove );
+        obj.add_key_guard( "secret", |_: &&'static str, op| op == Operation::Create );
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.create_property( "secret", Box::new( 42i32 )).is_ok() );
+        assert_eq!( obj.remove_property::<i32>( &"hallo" ), Err( PropertyError::Rejected( () )) );
+        assert_eq!( obj.set_property( &"secret", Box::new( 1i32 )), Err( PropertyError::Rejected( Box::new( 1i32 ))) );
+        assert_eq!( obj.borrow_prop::<i32>( &"secret" ).err(), Some( PropertyError::Rejected( () )) );
+        assert_eq!( obj.access( &"secret" ).err(), Some( PropertyError::Rejected( () )) );
+        assert!( obj["secret"].is_inner_type::<UndefinedProperty>() );
+        assert!( obj["secret"].as_mut::<UndefinedProperty>().is_some() );
+        assert_eq!( obj["hallo"].as_ref::<i32>(), Some( &23i32 ));
+        assert!( !obj.exists_property_with_type::<i32>( &"secret" ));
+        assert_eq!( obj.borrow_prop::<u8>( &"secret" ).err(), Some( PropertyError::Rejected( () )) );
+        assert_eq!( obj.remove_property::<u8>( &"hallo" ), Err( PropertyError::Rejected( () )) );
+    }
+
+    #[test]
+    fn guards_should_receive_the_key_and_operation() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let mut obj = create_dummy();
+        let guard_log = log.clone();
+        obj.add_guard( move |key: &&'static str, op| {
+            guard_log.borrow_mut().push( ( *key, op ));
+            true
+        });
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.get( &TypedKey::<&'static str, i32>::new( "hallo" )).is_some() );
+        assert!( obj.remove_property::<i32>( &"hallo" ).is_ok() );
+        assert_eq!( *log.borrow(), vec![ ( "hallo", Operation::Create ),
+                                         ( "hallo", Operation::Access ),
+                                         ( "hallo", Operation::Remove ) ] );
+        obj.clear_guards();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert_eq!( log.borrow().len(), 3 );
+    }
+
+    #[test]
+    fn observers_should_be_notified_after_each_change() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let key_log = Rc::new( RefCell::new( Vec::new() ));
+        let mut obj = create_dummy();
+        let observer_log = log.clone();
+        obj.subscribe( move |key: &&'static str, change| observer_log.borrow_mut().push( ( *key, change )));
+        let observer_log = key_log.clone();
+        obj.subscribe_key( "welt", move |_: &&'static str, change| observer_log.borrow_mut().push( change ));
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.set_property( &"hallo", Box::new( 24i32 )).is_ok() );
+        *obj.as_mut::<i32>( &"hallo" ).unwrap() += 1;
+        assert!( obj.as_mut::<u8>( &"hallo" ).is_none() );
+        assert!( obj.set_property( &"hallo", Box::new( 24u8 )).is_err() );
+        assert_eq!( obj.remove_property::<i32>( &"hallo" ), Ok( Box::new( 25i32 )) );
+        assert_eq!( *log.borrow(), vec![ ( "hallo", Change::Create ), ( "hallo", Change::Set ),
+                                         ( "hallo", Change::Write ), ( "hallo", Change::Remove ) ] );
+        assert!( obj.insert_property( "welt", DynProperty::new( Box::new( 1u8 ))).is_ok() );
+        assert_eq!( *key_log.borrow(), vec![ Change::Create ] );
+    }
+
+    #[test]
+    fn typed_writes_and_undo_should_be_observed() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let key = TypedKey::<&'static str, i32>::new( "hallo" );
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        let observer_log = log.clone();
+        obj.subscribe_key( "hallo", move |key: &&'static str, change| observer_log.borrow_mut().push( ( *key, change )));
+        assert!( obj.create( &key, Box::new( 1i32 )).is_ok() );
+        *obj.get_mut( &key ).unwrap() += 1;
+        assert!( obj.undo() );
+        assert!( obj.redo() );
+        assert_eq!( obj.get( &key ), Some( &2i32 ));
+        assert_eq!( *log.borrow(), vec![ ( "hallo", Change::Create ), ( "hallo", Change::Write ),
+                                         ( "hallo", Change::Remove ), ( "hallo", Change::Create ) ] );
+    }
+
+    #[test]
+    fn unsubscribe_should_remove_the_observer() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+        let count = Rc::new( Cell::new( 0u32 ));
+        let mut obj = create_dummy();
+        let observer_count = count.clone();
+        let id = obj.subscribe_key( "hallo", move |_: &&'static str, _| observer_count.set( observer_count.get() + 1 ));
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.unsubscribe( id ));
+        assert!( !obj.unsubscribe( id ));
+        assert!( obj.set_property( &"hallo", Box::new( 24i32 )).is_ok() );
+        assert_eq!( count.get(), 1 );
+    }
+
+    #[test]
+    fn iterators_should_yield_all_own_properties() {
+        let mut obj = InnerDynObject::with_prototype( create_prototype() );
+        assert!( obj.is_empty() );
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.create_property( "welt", Box::new( 2u8 )).is_ok() );
+        assert!( obj.create_property( "foo", Box::new( 5i32 )).is_ok() );
+        assert_eq!( obj.len(), 3 );
+        let mut keys: Vec<&'static str> = obj.keys().map( |key| *key ).collect();
+        keys.sort();
+        assert_eq!( keys, vec![ "foo", "hallo", "welt" ] );
+        assert_eq!( obj.iter().filter( |&( _, prop )| prop.is_inner_type::<u8>() ).count(), 1 );
+        for ( _, mut value ) in obj.iter_mut_of::<i32>() {
+            *value += 1;
+        }
+        let mut values: Vec<i32> = obj.iter_of::<i32>().map( |( _, value )| *value ).collect();
+        values.sort();
+        assert_eq!( values, vec![ 6, 24 ] );
+        for ( _, prop ) in obj.iter_mut() {
+            assert!( !prop.is_inner_type::<UndefinedProperty>() );
+        }
+    }
+
+    #[test]
+    fn iterators_should_skip_guarded_properties() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.create_property( "secret", Box::new( 42i32 )).is_ok() );
+        obj.add_key_guard( "secret", |_: &&'static str, op| op != Operation::AccessMut );
+        assert_eq!( obj.keys().count(), 2 );
+        assert_eq!( obj.iter_of::<i32>().count(), 2 );
+        assert_eq!( obj.iter_mut().map( |( key, _ )| *key ).collect::<Vec<_>>(), vec![ "hallo" ] );
+    }
+
+    #[test]
+    fn mutable_iterators_and_entries_should_notify_writes() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let observer_log = log.clone();
+        obj.subscribe( move |key: &&'static str, change| observer_log.borrow_mut().push( ( *key, change )));
+        for ( _, mut value ) in obj.iter_mut_of::<i32>() {
+            *value += 1;
+        }
+        for ( _, mut prop ) in obj.iter_mut() {
+            *prop.as_mut::<i32>().unwrap() += 1;
+        }
+        *obj.entry( "hallo" ).or_insert( Box::new( 0i32 )).unwrap() += 1;
+        match obj.entry( "hallo" ) {
+            Entry::Occupied( entry ) => *entry.into_mut::<i32>().unwrap() += 1,
+            Entry::Vacant( _ ) => panic!( "expected a occupied entry" )
+        }
+        assert_eq!( obj["hallo"].as_ref::<i32>(), Some( &27i32 ));
+        assert_eq!( *log.borrow(), vec![ ( "hallo", Change::Write ); 4 ] );
+    }
+
+    #[test]
+    fn entry_should_insert_missing_properties() {
+        let mut obj = create_dummy();
+        assert_eq!( obj.entry( "hallo" ).or_insert( Box::new( 23i32 )).map( |value| *value ), Ok( 23i32 ));
+        assert_eq!( obj.entry( "hallo" ).or_insert_with( || -> Box<i32> { panic!( "not called" ) }).map( |value| *value ),
+                    Ok( 23i32 ));
+        assert_eq!( *obj.entry( "welt" ).key(), "welt" );
+        match obj.entry( "welt" ) {
+            Entry::Vacant( entry ) => assert!( entry.insert( Box::new( 1u8 )).is_ok() ),
+            Entry::Occupied( _ ) => panic!( "expected a vacant entry" )
+        }
+        assert_eq!( obj["welt"].as_ref::<u8>(), Some( &1u8 ));
+    }
+
+    #[test]
+    fn entry_should_report_type_mismatches() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert_eq!( obj.entry( "hallo" ).or_insert( Box::new( 1u8 )).map( |value| *value ),
+                    Err( PropertyError::TypeMismatch { expected: "u8", found: "i32", value: Box::new( 1u8 ) }) );
+        match obj.entry( "hallo" ).and_modify( |value: &mut u8| *value += 1 ) {
+            Err( PropertyError::TypeMismatch { .. } ) => {},
+            _ => panic!( "expected a type mismatch" )
+        }
+    }
+
+    #[test]
+    fn and_modify_should_only_modify_existing_properties() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let mut obj = create_dummy();
+        let observer_log = log.clone();
+        obj.subscribe( move |_: &&'static str, change| observer_log.borrow_mut().push( change ));
+        for _ in 0..2 {
+            let entry = obj.entry( "hallo" ).and_modify( |value: &mut i32| *value += 1 ).ok().unwrap();
+            assert!( entry.or_insert( Box::new( 0i32 )).is_ok() );
+        }
+        assert_eq!( obj["hallo"].as_ref::<i32>(), Some( &1i32 ));
+        assert_eq!( *log.borrow(), vec![ Change::Create, Change::Write, Change::Write, Change::Write ] );
+    }
+
+    #[test]
+    fn entry_should_respect_guards() {
+        let mut obj = create_dummy();
+        obj.add_key_guard( "hallo", |_: &&'static str, op| op != Operation::AccessMut );
+        assert_eq!( obj.entry( "hallo" ).or_insert( Box::new( 23i32 )).map( |value| *value ),
+                    Err( PropertyError::Rejected( Box::new( 23i32 ))) );
+        assert!( !obj.exists_property( &"hallo" ));
+    }
+
+    #[test]
+    fn vacant_insert_should_ask_the_guards_only_once() {
+        use std::cell::Cell;
+        let mut obj = create_dummy();
+        let calls = Cell::new( 0u32 );
+        obj.add_key_guard( "hallo", move |_: &&'static str, op| {
+            if op == Operation::AccessMut {
+                calls.set( calls.get() + 1 );
+            }
+            calls.get() <= 1
+        });
+        assert_eq!( obj.entry( "hallo" ).or_insert( Box::new( 23i32 )).map( |value| *value ), Ok( 23i32 ));
+        assert!( obj.exists_property( &"hallo" ));
+    }
+
+    #[test]
+    fn remove_any_should_remove_without_knowing_the_type() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.remove_any( &"welt" ).is_none() );
+        let prop = obj.remove_any( &"hallo" ).unwrap();
+        assert!( !obj.exists_property( &"hallo" ));
+        assert_eq!( prop.into_any().ok().unwrap().downcast_ref::<i32>(), Some( &23i32 ));
+    }
+
+    #[test]
+    fn remove_any_should_keep_borrowed_properties() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let guard = obj.borrow_prop::<i32>( &"hallo" ).unwrap();
+        assert!( obj.remove_any( &"hallo" ).is_none() );
+        obj.clear();
+        assert_eq!( obj.len(), 1 );
+        assert_eq!( *guard, 23i32 );
+    }
+
+    #[test]
+    fn retain_should_remove_the_rejected_properties() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.create_property( "welt", Box::new( 2u8 )).is_ok() );
+        assert!( obj.create_property( "secret", Box::new( 3u8 )).is_ok() );
+        obj.add_key_guard( "secret", |_: &&'static str, op| op != Operation::Remove );
+        obj.retain( |_, prop| prop.is_inner_type::<i32>() );
+        let mut keys: Vec<&'static str> = obj.keys().map( |key| *key ).collect();
+        keys.sort();
+        assert_eq!( keys, vec![ "hallo", "secret" ] );
+    }
+
+    #[test]
+    fn drain_should_return_all_removable_properties() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.create_property( "welt", Box::new( 2u8 )).is_ok() );
+        let mut keys: Vec<&'static str> = obj.drain().map( |( key, _ )| key ).collect();
+        keys.sort();
+        assert_eq!( keys, vec![ "hallo", "welt" ] );
+        assert!( obj.is_empty() );
+    }
+
+    #[test]
+    fn create_value_and_set_value_should_work_without_boxes() {
+        let mut obj = create_dummy();
+        assert!( obj.create_value( "hallo", 23i32 ).is_ok() );
+        assert_eq!( obj.create_value( "hallo", 2i32 ), Err( PropertyError::AlreadyExists( 2i32 )) );
+        assert_eq!( obj.set_value( &"hallo", 24i32 ), Ok( 23i32 ));
+        assert_eq!( obj.set_value( &"welt", 24i32 ), Err( PropertyError::NotFound( 24i32 )) );
+        assert_eq!( obj.remove_property::<i32>( &"hallo" ), Ok( Box::new( 24i32 )) );
+    }
+
     //TODO test index
     #[test]
     fn index_should_return_the_property_if_existing() {
@@ -290,6 +2177,216 @@ mod test {
         }   
     }
     
+    #[test]
+    fn other_storages_should_support_the_same_operations() {
+        let mut obj = InnerDynObject::with_storage( VecStorage::new() );
+        assert!( obj.create_value( "a", 1u8 ).is_ok() );
+        assert!( obj.create_value( "b", 2u8 ).is_ok() );
+        assert!( obj.create_value( "a", 3u8 ).is_err() );
+        assert_eq!( obj.set_value( &"b", 4u8 ).ok(), Some( 2u8 ));
+        assert_eq!( obj.remove_property::<u8>( &"a" ).ok(), Some( Box::new( 1u8 )));
+        assert_eq!( obj.len(), 1 );
+        assert_eq!( obj["b"].as_ref::<u8>(), Some( &4u8 ));
+        *obj.entry( "c" ).or_insert( Box::new( 0u8 )).unwrap() += 5;
+        assert_eq!( obj["c"].as_ref::<u8>(), Some( &5u8 ));
+    }
+
+    #[test]
+    fn btree_storage_should_iterate_in_key_order() {
+        let mut obj = InnerDynObject::with_storage( BTreeMapStorage::new() );
+        for key in vec![ 3u32, 1, 4, 0, 2 ] {
+            obj.create_value( key, key ).unwrap();
+        }
+        let keys: Vec<u32> = obj.keys().cloned().collect();
+        assert_eq!( keys, vec![ 0, 1, 2, 3, 4 ] );
+        obj.add_key_guard( 2, |_: &u32, _| false );
+        let keys: Vec<u32> = obj.range( Bound::Included( &1 ), Bound::Unbounded )
+                                .map( |( key, _ )| *key ).collect();
+        assert_eq!( keys, vec![ 1, 3, 4 ] );
+    }
+
+    #[test]
+    fn keys_should_not_need_hash_with_other_storages() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        #[derive(PartialEq, Eq, Clone, Debug)]
+        struct Name( &'static str );
+
+        let log = Rc::new( RefCell::new( Vec::new() ));
+        let observer_log = log.clone();
+        let mut obj = InnerDynObject::with_storage( VecStorage::new() );
+        obj.add_key_guard( Name( "secret" ), |_: &Name, op| op != Operation::Access );
+        obj.subscribe_key( Name( "hp" ), move |key: &Name, change| observer_log.borrow_mut().push( ( key.clone(), change )));
+        obj.create_value( Name( "hp" ), 10u32 ).unwrap();
+        obj.create_value( Name( "secret" ), 1u8 ).unwrap();
+        assert!( obj.borrow_prop::<u8>( &Name( "secret" )).is_err() );
+        assert_eq!( obj.iter().map( |( key, _ )| key.clone() ).collect::<Vec<_>>(), vec![ Name( "hp" ) ] );
+        assert_eq!( *log.borrow(), vec![ ( Name( "hp" ), Change::Create ) ] );
+    }
+
+    #[test]
+    fn prototypes_should_work_with_other_storages() {
+        let proto = DynObject::with_storage( VecStorage::new() );
+        proto.aquire().create_value( "hp", 10u32 ).unwrap();
+        let obj = InnerDynObject::with_prototype( proto );
+        assert!( obj.has_property( &"hp" ));
+        assert!( !obj.exists_property( &"hp" ));
+    }
+
+    #[test]
+    fn undo_and_redo_should_revert_and_repeat_changes() {
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.insert_property( "hp", DynProperty::new_cloneable( Box::new( 10u32 ))).unwrap();
+        obj.set_value( &"hp", 5u32 ).unwrap();
+        assert_eq!( obj.remove_property::<u32>( &"hp" ), Ok( Box::new( 5u32 )));
+        assert!( obj.undo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &5u32 ));
+        assert!( obj.undo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &10u32 ));
+        assert!( obj["hp"].is_cloneable() );
+        assert!( obj.undo() );
+        assert!( !obj.exists_property( &"hp" ));
+        assert!( !obj.undo() );
+        assert!( obj.redo() );
+        assert!( obj.redo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &5u32 ));
+        obj.set_value( &"hp", 1u32 ).unwrap();
+        assert!( !obj.can_redo() );
+        assert!( !obj.redo() );
+    }
+
+    #[test]
+    fn history_should_be_bounded_by_its_depth() {
+        let mut obj = create_dummy();
+        obj.enable_history( 2 );
+        for key in vec![ "a", "b", "c" ] {
+            obj.create_value( key, 1u8 ).unwrap();
+        }
+        assert!( obj.undo() );
+        assert!( obj.undo() );
+        assert!( !obj.undo() );
+        assert_eq!( obj.keys().cloned().collect::<Vec<&'static str>>(), vec![ "a" ] );
+    }
+
+    #[test]
+    fn changing_a_not_cloneable_property_should_work_and_clear_the_history() {
+        let registry = ConversionRegistry::new();
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.create_value( "hp", 10u32 ).unwrap();
+        obj.create_value( "mp", 3u8 ).unwrap();
+        //converting and clearing move the old properties into the history
+        obj.convert_in_place::<u32>( &"mp", &registry ).unwrap();
+        assert!( obj.undo() );
+        assert!( obj.redo() );
+        assert_eq!( obj.set_value( &"hp", 5u32 ), Ok( 10u32 ));
+        assert!( !obj.can_undo() );
+        obj.create_value( "sp", 1u8 ).unwrap();
+        assert_eq!( obj.remove_property::<u32>( &"hp" ), Ok( Box::new( 5u32 )));
+        assert!( !obj.can_undo() );
+        obj.create_value( "hp", 10u32 ).unwrap();
+        assert!( obj.remove_any( &"hp" ).is_some() );
+        assert!( !obj.can_undo() );
+        obj.clear();
+        assert!( obj.is_empty() );
+        assert!( obj.undo() );
+        assert_eq!( obj.len(), 2 );
+        assert!( !obj.undo() );
+    }
+
+    #[test]
+    fn drain_should_report_the_properties_it_could_not_record() {
+        let mut obj = InnerDynObject::with_storage( VecStorage::new() );
+        obj.enable_history( 10 );
+        obj.create_value( "mp", 3u8 ).unwrap();
+        obj.create_cloneable_value( "hp", 10u32 ).unwrap();
+        let drained = obj.drain();
+        assert_eq!( drained.unrecorded(), &[ "mp" ][..] );
+        assert_eq!( drained.count(), 2 );
+        assert!( obj.is_empty() );
+        //removing "mp" cleared the history, so only removing "hp" is undone
+        assert!( obj.undo() );
+        assert!( obj.exists_property( &"hp" ));
+        assert!( !obj.exists_property( &"mp" ));
+        assert!( !obj.undo() );
+    }
+
+    #[test]
+    fn cloneable_properties_should_be_undoable() {
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.create_cloneable( "name", Box::new( "a".to_string() )).unwrap();
+        obj.create_cloneable_value( "hp", 10u32 ).unwrap();
+        assert_eq!( obj.create_cloneable_value( "hp", 1u32 ), Err( PropertyError::AlreadyExists( 1u32 )));
+        assert_eq!( obj.set_value( &"hp", 5u32 ), Ok( 10u32 ));
+        assert_eq!( obj.remove_property::<String>( &"name" ), Ok( Box::new( "a".to_string() )));
+        assert!( obj.undo() );
+        assert!( obj.undo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &10u32 ));
+        assert_eq!( obj["name"].as_ref::<String>(), Some( &"a".to_string() ));
+    }
+
+    #[test]
+    fn undo_should_fail_if_the_step_does_not_fit() {
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.create_value( "hp", 10u32 ).unwrap();
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.set_value( &"hp", 5u32 ).unwrap();
+            transaction.commit();
+        }
+        obj["hp"] = DynProperty::new( Box::new( "full".to_string() ));
+        assert!( !obj.undo() );
+        assert!( obj.can_undo() );
+        assert_eq!( obj["hp"].as_ref::<String>(), Some( &"full".to_string() ));
+        obj["hp"] = DynProperty::new( Box::new( 1u32 ));
+        assert!( obj.undo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &10u32 ));
+        //redo restores the property replaced over indexing
+        assert!( obj.redo() );
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &1u32 ));
+    }
+
+    #[test]
+    fn history_groups_should_be_undone_at_once() {
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.create_value( "a", 1u8 ).unwrap();
+        obj.create_value( "b", 2u8 ).unwrap();
+        obj.history_group( |obj| {
+            obj.create_value( "c", 3u8 ).unwrap();
+            assert!( !obj.undo() );
+            obj.history_group( |obj| obj.clear() );
+        });
+        assert!( obj.is_empty() );
+        assert!( obj.undo() );
+        assert_eq!( obj.len(), 2 );
+        assert!( !obj.exists_property( &"c" ));
+        assert!( obj.redo() );
+        assert!( obj.is_empty() );
+        obj.disable_history();
+        assert!( !obj.can_undo() );
+    }
+
+    #[test]
+    fn convert_in_place_should_be_guarded_and_undoable() {
+        let registry = ConversionRegistry::new();
+        let mut obj = create_dummy();
+        obj.enable_history( 10 );
+        obj.insert_property( "hp", DynProperty::new_cloneable( Box::new( 5u16 ))).unwrap();
+        assert_eq!( obj.convert_in_place::<u32>( &"mp", &registry ), Err( PropertyError::NotFound( () )));
+        obj.add_key_guard( "hp", |_: &&'static str, op| op != Operation::AccessMut );
+        assert_eq!( obj.convert_in_place::<u32>( &"hp", &registry ), Err( PropertyError::Rejected( () )));
+        assert_eq!( obj.get_as::<f32>( &"hp", &registry ), Some( 5f32 ));
+        obj.clear_guards();
+        obj.convert_in_place::<u32>( &"hp", &registry ).unwrap();
+        assert_eq!( obj["hp"].as_ref::<u32>(), Some( &5u32 ));
+        assert!( obj.undo() );
+        assert_eq!( obj["hp"].as_ref::<u16>(), Some( &5u16 ));
+    }
+
     #[test]
     fn undefined_property_should_return_a_property_of_the_undefined_property_type() {
         let x = undefined_property();
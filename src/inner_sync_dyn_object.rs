@@ -0,0 +1,179 @@
+use std::result::Result;
+use std::ops::{Index, IndexMut};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::any::Any;
+
+use super::sync_dyn_property::{self, SyncDynProperty};
+use super::inner_dyn_object::UndefinedProperty;
+use super::property_error::PropertyError;
+
+
+pub fn undefined_sync_property() -> SyncDynProperty {
+    SyncDynProperty::new( Box::new( UndefinedProperty ))
+}
+
+/// The inner part of SyncDynObject witch contains the data
+///
+/// It only accepts values witch are `Send` and `Sync`, so that it can be
+/// shared between threads. It provides the basic methodes of `InnerDynObject`
+/// (`create_property`, `set_property`, `remove_property`, `exists_property`,
+/// `exists_property_with_type` and indexing) and nothing else, see
+/// `SyncDynObject` for what is missing.
+///
+pub struct InnerSyncDynObject<Key> {
+    //initialise this allways with undefined_sync_property();
+    undefined_property: SyncDynProperty,
+    data: HashMap<Key, SyncDynProperty>
+}
+
+impl<Key> InnerSyncDynObject<Key> where Key: Eq + Hash {
+
+    /// Creates a new empty InnerSyncDynObject
+    ///
+    pub fn new() -> InnerSyncDynObject<Key> {
+        InnerSyncDynObject {
+            undefined_property: undefined_sync_property(),
+            data: HashMap::<Key, SyncDynProperty>::new()
+        }
+    }
+
+    /// sets the property defined by key
+    ///
+    /// See `InnerDynObject::set_property`
+    ///
+    #[inline]
+    pub fn set_property<T>( &mut self, key: &Key, value: Box<T> ) -> Result<Box<T>,PropertyError<Box<T>>>
+        where T: Any + Send + Sync + 'static
+    {
+        match self.data.get_mut( key ) {
+            Some( prop ) => prop.set( value ),
+            None => Err( PropertyError::NotFound( value ))
+        }
+    }
+
+    /// create a new property with a initial value
+    ///
+    /// See `InnerDynObject::create_property`
+    ///
+    pub fn create_property<T>( &mut self, key: Key, init_value: Box<T> ) -> Result<(),PropertyError<Box<T>>>
+        where T: Any + Send + Sync + 'static
+    {
+        if self.data.contains_key( &key ) {
+            Err( PropertyError::AlreadyExists( init_value ))
+        } else {
+            self.data.insert( key, SyncDynProperty::new( init_value ));
+            Ok( () )
+        }
+    }
+
+    /// removes a given property returning the old value of it
+    ///
+    /// See `InnerDynObject::remove_property`
+    ///
+    pub fn remove_property<T>( &mut self, key: &Key ) -> Result<Box<T>, PropertyError<()>>
+        where T: Any + Send + Sync + 'static
+    {
+        match self.data.get( key ) {
+            Some( prop ) => if !prop.is_inner_type::<T>() {
+                return Err( sync_dyn_property::type_mismatch::<T, ()>( prop, () ));
+            },
+            None => return Err( PropertyError::NotFound( () ))
+        }
+        Ok( self.data.remove( key ).unwrap().destruct::<T>().unwrap() )
+    }
+
+    /// returns true if a given property exists
+    pub fn exists_property( &self, key: &Key ) -> bool {
+        self.data.contains_key( key )
+    }
+
+    /// returns true if a given property exists and has the given type
+    pub fn exists_property_with_type<T>( &self, key: &Key ) -> bool
+        where T: Any + 'static
+    {
+        self.index( key ).is_inner_type::<T>()
+    }
+}
+
+impl<Key: Hash+Eq> Index<Key> for InnerSyncDynObject<Key> {
+    type Output = SyncDynProperty;
+
+    /// return a reference to a `SyncDynProperty` for a given key
+    ///
+    /// If the key does not exist a reference to a property with
+    /// the inner type `UndefinedProperty` will be returned.
+    ///
+    fn index<'a>( &'a self, index: &Key ) -> &'a SyncDynProperty {
+        match self.data.get( index ) {
+            Some( data ) => data,
+            None => &self.undefined_property
+        }
+    }
+}
+
+impl<Key: Hash+Eq> IndexMut<Key> for InnerSyncDynObject<Key> {
+
+    /// return a mutable referenc to a `SyncDynProperty` for a given key
+    ///
+    /// If the key does not exist a reference to a property with
+    /// the inner type `UndefinedProperty` will be returned.
+    ///
+    fn index_mut<'a>( &'a mut self, index: &Key ) -> &'a mut SyncDynProperty {
+        match self.data.get_mut( index ) {
+            Some( data ) => data,
+            None => &mut self.undefined_property
+        }
+    }
+}
+
+#[cfg( test )]
+mod test {
+    use super::InnerSyncDynObject;
+    use super::super::property_error::PropertyError;
+    use super::super::inner_dyn_object::UndefinedProperty;
+
+    fn create_dummy() -> InnerSyncDynObject<&'static str> {
+        InnerSyncDynObject::<&'static str>::new()
+    }
+
+    #[test]
+    fn after_creating_a_property_should_exist() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert!( obj.exists_property( &"hallo" ));
+        assert!( obj.exists_property_with_type::<i32>( &"hallo" ));
+    }
+
+    #[test]
+    fn create_property_should_return_false_if_key_already_exists() {
+        let mut obj = create_dummy();
+        assert!(  obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let res = obj.create_property( "hallo", Box::new( 20i32 ));
+        assert_eq!( res, Err( PropertyError::AlreadyExists( Box::new( 20i32 ))) );
+    }
+
+    #[test]
+    fn set_property_should_return_the_old_value_if_property_exists_and_type_matches() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        let res = obj.set_property( &"hallo", Box::new( 44i32 ));
+        assert_eq!( res, Ok( Box::new( 23i32 )) );
+        assert_eq!( obj["hallo"].as_ref::<i32>().unwrap(), &44i32 );
+    }
+
+    #[test]
+    fn remove_property_should_fail_if_the_type_mismatches() {
+        let mut obj = create_dummy();
+        assert!( obj.create_property( "hallo", Box::new( 23i32 )).is_ok() );
+        assert_eq!( obj.remove_property::<u16>( &"hallo" ),
+                    Err( PropertyError::TypeMismatch { expected: "u16", found: "i32", value: () }) );
+        assert_eq!( obj.remove_property::<i32>( &"hallo" ), Ok( Box::new( 23i32 )) );
+    }
+
+    #[test]
+    fn index_should_return_a_property_of_type_undefined_if_inexisting() {
+        let obj = create_dummy();
+        assert!( obj["hallo"].is_inner_type::<UndefinedProperty>() );
+    }
+}
@@ -0,0 +1,260 @@
+use std::vec;
+use std::any::Any;
+use std::collections::btree_map;
+use std::marker::PhantomData;
+
+use super::dyn_property::{self, DynProperty};
+use super::dyn_cast::CastRegistry;
+use super::hooks::{self, Hooks, Operation, PropertyWrite};
+use super::storage::{StorageIterators, HashMapStorage};
+
+
+/// iterator over the keys of a `InnerDynObject`, returned by `keys`
+pub struct Keys<'a, Key: 'a, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: <S as StorageIterators<'a, Key>>::Iter
+}
+
+/// iterator over the properties of a `InnerDynObject`, returned by `iter`
+pub struct Iter<'a, Key: 'a, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: <S as StorageIterators<'a, Key>>::Iter,
+    hooks: &'a Option<Box<Hooks<Key>>>
+}
+
+/// mutable iterator over the properties of a `InnerDynObject`, returned by `iter_mut`
+pub struct IterMut<'a, Key: 'a, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: <S as StorageIterators<'a, Key>>::IterMut,
+    hooks: &'a Option<Box<Hooks<Key>>>
+}
+
+/// iterator over the properties with keys in a range, returned by `InnerDynObject::range`
+pub struct Range<'a, Key: 'a> {
+    inner: btree_map::Range<'a, Key, DynProperty>,
+    hooks: &'a Option<Box<Hooks<Key>>>
+}
+
+/// iterator over the values of type `T` of a `InnerDynObject`, returned by `iter_of`
+pub struct IterOf<'a, Key: 'a, T, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: Iter<'a, Key, S>,
+    marker: PhantomData<fn() -> T>
+}
+
+/// mutable iterator over the values of type `T` of a `InnerDynObject`, returned by `iter_mut_of`
+pub struct IterMutOf<'a, Key: 'a, T, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: IterMut<'a, Key, S>,
+    marker: PhantomData<fn() -> T>
+}
+
+/// iterator over the values viewed as `Trait` of a `InnerDynObject`, returned by `iter_dyn`
+pub struct IterDyn<'a, Key: 'a, Trait: ?Sized, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: Iter<'a, Key, S>,
+    registry: Option<&'a CastRegistry>,
+    marker: PhantomData<fn( &Trait )>
+}
+
+/// mutable iterator over the values viewed as `Trait` of a `InnerDynObject`, returned by `iter_dyn_mut`
+pub struct IterDynMut<'a, Key: 'a, Trait: ?Sized, S: 'a = HashMapStorage<Key>> where S: StorageIterators<'a, Key> {
+    inner: IterMut<'a, Key, S>,
+    registry: Option<&'a CastRegistry>,
+    marker: PhantomData<fn( &Trait )>
+}
+
+/// iterator over the removed properties of a `InnerDynObject`, returned by `drain`
+pub struct Drain<Key> {
+    inner: vec::IntoIter<( Key, DynProperty )>,
+    unrecorded: Vec<Key>
+}
+
+pub fn keys<'a, Key, S>( data: &'a S ) -> Keys<'a, Key, S>
+    where S: StorageIterators<'a, Key>
+{
+    Keys { inner: data.iter() }
+}
+
+pub fn iter<'a, Key, S>( data: &'a S, hooks: &'a Option<Box<Hooks<Key>>> ) -> Iter<'a, Key, S>
+    where Key: Eq, S: StorageIterators<'a, Key>
+{
+    Iter { inner: data.iter(), hooks: hooks }
+}
+
+pub fn iter_mut<'a, Key, S>( data: &'a mut S, hooks: &'a Option<Box<Hooks<Key>>> ) -> IterMut<'a, Key, S>
+    where Key: Eq, S: StorageIterators<'a, Key>
+{
+    IterMut { inner: data.iter_mut(), hooks: hooks }
+}
+
+pub fn range<'a, Key>( data: btree_map::Range<'a, Key, DynProperty>, hooks: &'a Option<Box<Hooks<Key>>> )
+    -> Range<'a, Key>
+    where Key: Eq
+{
+    Range { inner: data, hooks: hooks }
+}
+
+pub fn iter_of<'a, Key, T, S>( iter: Iter<'a, Key, S> ) -> IterOf<'a, Key, T, S>
+    where S: StorageIterators<'a, Key>
+{
+    IterOf { inner: iter, marker: PhantomData }
+}
+
+pub fn iter_mut_of<'a, Key, T, S>( iter: IterMut<'a, Key, S> ) -> IterMutOf<'a, Key, T, S>
+    where S: StorageIterators<'a, Key>
+{
+    IterMutOf { inner: iter, marker: PhantomData }
+}
+
+pub fn iter_dyn<'a, Key, Trait: ?Sized, S>( iter: Iter<'a, Key, S>, registry: Option<&'a CastRegistry> )
+    -> IterDyn<'a, Key, Trait, S>
+    where S: StorageIterators<'a, Key>
+{
+    IterDyn { inner: iter, registry: registry, marker: PhantomData }
+}
+
+pub fn iter_dyn_mut<'a, Key, Trait: ?Sized, S>( iter: IterMut<'a, Key, S>, registry: Option<&'a CastRegistry> )
+    -> IterDynMut<'a, Key, Trait, S>
+    where S: StorageIterators<'a, Key>
+{
+    IterDynMut { inner: iter, registry: registry, marker: PhantomData }
+}
+
+/// returns the next property of `inner` the hooks allow `operation` on
+fn next_allowed<'a, Key, V, I>( inner: &mut I, hooks: &Option<Box<Hooks<Key>>>, operation: Operation )
+    -> Option<( &'a Key, V )>
+    where Key: Eq + 'a, I: Iterator<Item=( &'a Key, V )>
+{
+    while let Some( ( key, prop )) = inner.next() {
+        if hooks::allows( hooks, key, operation ) {
+            return Some( ( key, prop ));
+        }
+    }
+    None
+}
+
+pub fn drain<Key>( drained: Vec<( Key, DynProperty )>, unrecorded: Vec<Key> ) -> Drain<Key> {
+    Drain { inner: drained.into_iter(), unrecorded: unrecorded }
+}
+
+impl<Key> Drain<Key> {
+
+    /// returns the keys of the removed properties the history could not record
+    ///
+    /// They were created without clone support, so removing them cleared
+    /// the history. Empty if the history is not enabled.
+    pub fn unrecorded( &self ) -> &[Key] {
+        &self.unrecorded
+    }
+}
+
+impl<'a, Key, S> Iterator for Keys<'a, Key, S> where S: StorageIterators<'a, Key> {
+    type Item = &'a Key;
+
+    fn next( &mut self ) -> Option<&'a Key> {
+        self.inner.next().map( |( key, _ )| key )
+    }
+
+    fn size_hint( &self ) -> ( usize, Option<usize> ) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, Key, S> Iterator for Iter<'a, Key, S> where Key: Eq, S: StorageIterators<'a, Key> {
+    type Item = ( &'a Key, &'a DynProperty );
+
+    fn next( &mut self ) -> Option<( &'a Key, &'a DynProperty )> {
+        next_allowed( &mut self.inner, self.hooks, Operation::Access )
+    }
+}
+
+impl<'a, Key, S> IterMut<'a, Key, S> where Key: Eq, S: StorageIterators<'a, Key> {
+
+    //the plain properties, wrapped by the typed iterators
+    fn next_prop( &mut self ) -> Option<( &'a Key, &'a mut DynProperty )> {
+        next_allowed( &mut self.inner, self.hooks, Operation::AccessMut )
+    }
+}
+
+impl<'a, Key, S> Iterator for IterMut<'a, Key, S> where Key: Eq, S: StorageIterators<'a, Key> {
+    type Item = ( &'a Key, PropertyWrite<'a, Key, DynProperty> );
+
+    fn next( &mut self ) -> Option<( &'a Key, PropertyWrite<'a, Key, DynProperty> )> {
+        self.next_prop().map( |( key, prop )| ( key, hooks::property_write( prop, key, self.hooks )))
+    }
+}
+
+impl<'a, Key> Iterator for Range<'a, Key> where Key: Eq {
+    type Item = ( &'a Key, &'a DynProperty );
+
+    fn next( &mut self ) -> Option<( &'a Key, &'a DynProperty )> {
+        next_allowed( &mut self.inner, self.hooks, Operation::Access )
+    }
+}
+
+impl<'a, Key, T, S> Iterator for IterOf<'a, Key, T, S> where Key: Eq, T: Any, S: StorageIterators<'a, Key> {
+    type Item = ( &'a Key, &'a T );
+
+    fn next( &mut self ) -> Option<( &'a Key, &'a T )> {
+        while let Some( ( key, prop )) = self.inner.next() {
+            match prop.as_ref::<T>() {
+                Some( value ) => return Some( ( key, value )),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl<'a, Key, T, S> Iterator for IterMutOf<'a, Key, T, S> where Key: Eq, T: Any, S: StorageIterators<'a, Key> {
+    type Item = ( &'a Key, PropertyWrite<'a, Key, T> );
+
+    fn next( &mut self ) -> Option<( &'a Key, PropertyWrite<'a, Key, T> )> {
+        while let Some( ( key, prop )) = self.inner.next_prop() {
+            match prop.as_mut::<T>() {
+                Some( value ) => return Some( ( key, hooks::property_write( value, key, self.inner.hooks ))),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl<'a, Key, Trait: ?Sized, S> Iterator for IterDyn<'a, Key, Trait, S>
+    where Key: Eq, Trait: 'static, S: StorageIterators<'a, Key>
+{
+    type Item = ( &'a Key, &'a Trait );
+
+    fn next( &mut self ) -> Option<( &'a Key, &'a Trait )> {
+        while let Some( ( key, prop )) = self.inner.next() {
+            match dyn_property::cast_ref::<Trait>( prop, self.registry ) {
+                Some( value ) => return Some( ( key, value )),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl<'a, Key, Trait: ?Sized, S> Iterator for IterDynMut<'a, Key, Trait, S>
+    where Key: Eq, Trait: 'static, S: StorageIterators<'a, Key>
+{
+    type Item = ( &'a Key, PropertyWrite<'a, Key, Trait> );
+
+    fn next( &mut self ) -> Option<( &'a Key, PropertyWrite<'a, Key, Trait> )> {
+        while let Some( ( key, prop )) = self.inner.next_prop() {
+            match dyn_property::cast_mut::<Trait>( prop, self.registry ) {
+                Some( value ) => return Some( ( key, hooks::property_write( value, key, self.inner.hooks ))),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl<Key> Iterator for Drain<Key> {
+    type Item = ( Key, DynProperty );
+
+    fn next( &mut self ) -> Option<( Key, DynProperty )> {
+        self.inner.next()
+    }
+
+    fn size_hint( &self ) -> ( usize, Option<usize> ) {
+        self.inner.size_hint()
+    }
+}
@@ -30,26 +30,128 @@
 #![feature(alloc)]
 //this is needed 'cause Associated Types in combination with e.g. Index are not yet complety stable
 #![feature(core)]
+//PropertyWrite contains references and needs a destructor
+#![feature(unsafe_destructor)]
 //for now it is unstable
 #![unstable(feature="alloc,core")]
 
-use std::rc::Rc;
+#[cfg(feature = "serialize")]
+extern crate "rustc-serialize" as rustc_serialize;
+
+use std::rc::{self, Rc};
 use std::cell::RefCell;
-use std::cell::RefMut;
+use std::cell::{Ref, RefMut, BorrowState};
 use std::hash::Hash;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
 
 //import and reexport dyn_property
 pub use dyn_property::DynProperty;
+pub use prop_guard::{PropRef, PropMut};
+pub use property_error::PropertyError;
+pub use deep_clone::{ClonePolicy, DeepCloneError};
+pub use hooks::{Operation, Change, ObserverId, PropertyWrite};
 pub use inner_dyn_object::UndefinedProperty;
 pub use inner_dyn_object::InnerDynObject;
+pub use typed_key::TypedKey;
+pub use iter::{Keys, Iter, IterMut, Range, IterOf, IterMutOf, IterDyn, IterDynMut, Drain};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use storage::{PropertyStorage, StorageIterators, HashMapStorage, BTreeMapStorage, VecStorage, OrderedStorage};
+pub use symbol::{Symbol, Interner, Names};
+pub use path::PathError;
+pub use transaction::Transaction;
+pub use weak_dyn_object::WeakDynObject;
+pub use cycle_collector::{CycleCollector, CycleLink};
+pub use conversion::ConversionRegistry;
+pub use dyn_cast::{DynCast, CastRegistry};
+pub use sync_dyn_property::SyncDynProperty;
+pub use inner_sync_dyn_object::InnerSyncDynObject;
+pub use sync_dyn_object::SyncDynObject;
+#[cfg(feature = "serialize")]
+pub use serialize::{TypeRegistry, EncodeError};
+#[doc(hidden)]
+pub use macros::new_nested_object as __new_nested_object;
 
 
+#[macro_use]
+mod macros;
 mod dyn_property;
+mod prop_guard;
+mod inline_value;
+mod property_error;
+mod deep_clone;
+mod hooks;
 mod inner_dyn_object;
+mod typed_key;
+mod iter;
+mod entry;
+mod storage;
+mod symbol;
+mod path;
+mod transaction;
+mod history;
+mod weak_dyn_object;
+mod cycle_collector;
+mod conversion;
+mod dyn_cast;
+mod sync_dyn_property;
+mod inner_sync_dyn_object;
+mod sync_dyn_object;
+#[cfg(feature = "serialize")]
+mod serialize;
 
 
-pub struct DynObject<Key> {
-    inner: Rc<RefCell<InnerDynObject<Key>>>
+pub struct DynObject<Key, S = HashMapStorage<Key>> {
+    inner: Rc<RefCell<InnerDynObject<Key, S>>>
+}
+
+/// error returned if a `DynObject` can not be aquired
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AquireError {
+    /// the object is currently aquired for reading (`aquire_ref`)
+    Borrowed,
+    /// the object is currently aquired mutable (`aquire`)
+    BorrowedMutable
+}
+
+impl fmt::Display for AquireError {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.write_str(self.description())
+    }
+}
+
+impl Error for AquireError {
+    fn description(&self) -> &str {
+        match *self {
+            AquireError::Borrowed => "DynObject is already aquired for reading",
+            AquireError::BorrowedMutable => "DynObject is already aquired mutable"
+        }
+    }
+}
+
+/// error returned if the prototype of a `DynObject` can not be set
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PrototypeError {
+    /// the object would become part of its own prototype chain
+    Cycle,
+    /// the object or a object of the new prototype chain is currently aquired mutable
+    Aquired
+}
+
+impl fmt::Display for PrototypeError {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.write_str(self.description())
+    }
+}
+
+impl Error for PrototypeError {
+    fn description(&self) -> &str {
+        match *self {
+            PrototypeError::Cycle => "prototype chain would contain a cycle",
+            PrototypeError::Aquired => "DynObject is aquired mutable"
+        }
+    }
 }
 
 
@@ -62,8 +164,32 @@ impl<Key> DynObject<Key> where Key: Eq+Hash {
     /// `DynObject::<KeyType>::new()
     ///
     pub fn new() -> DynObject<Key> {
-        let x =  InnerDynObject::<Key>::new();
-        let cell = RefCell::new(x);
+        DynObject::from_inner(InnerDynObject::<Key>::new())
+    }
+}
+
+impl<Key, S> DynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
+
+    /// create a new empty DynObject using the given storage
+    ///
+    /// See `InnerDynObject::with_storage`.
+    ///
+    pub fn with_storage(storage: S) -> DynObject<Key, S> {
+        DynObject::from_inner(InnerDynObject::with_storage(storage))
+    }
+
+    /// create a new empty DynObject with the given prototype
+    ///
+    /// See `InnerDynObject::with_prototype`.
+    ///
+    pub fn with_prototype(prototype: DynObject<Key, S>) -> DynObject<Key, S> {
+        DynObject::from_inner(InnerDynObject::with_prototype(prototype))
+    }
+
+    /// create a new DynObject containing the given `InnerDynObject`
+    ///
+    pub fn from_inner(inner: InnerDynObject<Key, S>) -> DynObject<Key, S> {
+        let cell = RefCell::new(inner);
         let rc = Rc::new(cell);
         DynObject {
             inner: rc
@@ -90,12 +216,260 @@ impl<Key> DynObject<Key> where Key: Eq+Hash {
     /// let v2 = obj.aquire();
     /// ```
     ///
-    pub fn aquire(&self) -> RefMut<InnerDynObject<Key>> {
+    pub fn aquire(&self) -> RefMut<InnerDynObject<Key, S>> {
         self.inner.borrow_mut()
     }
+
+    /// aquire the DynObject like `aquire` but without panicing
+    ///
+    /// If someone else aquired the DynObject (mutable or not)
+    /// a `AquireError` is returned instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{DynObject, AquireError};
+    /// let obj = DynObject::<i32>::new();
+    /// let v1 = obj.aquire();
+    /// assert_eq!(obj.try_aquire().err(), Some(AquireError::BorrowedMutable));
+    /// ```
+    ///
+    pub fn try_aquire(&self) -> Result<RefMut<InnerDynObject<Key, S>>, AquireError> {
+        match self.inner.borrow_state() {
+            BorrowState::Unused => Ok(self.inner.borrow_mut()),
+            BorrowState::Reading => Err(AquireError::Borrowed),
+            BorrowState::Writing => Err(AquireError::BorrowedMutable)
+        }
+    }
+
+    /// aquire the DynObject for read only operations
+    ///
+    /// In difference to `aquire` the DynObject can be aquired
+    /// with `aquire_ref` any number of times at the same time.
+    ///
+    /// # Panics
+    /// panics if someone aquired it mutable with `aquire` and
+    /// didn't relase it jet
+    ///
+    /// ```
+    /// # use dynobject::DynObject;
+    /// let obj = DynObject::<i32>::new();
+    /// let v1 = obj.aquire_ref();
+    /// let v2 = obj.aquire_ref();
+    /// ```
+    ///
+    pub fn aquire_ref(&self) -> Ref<InnerDynObject<Key, S>> {
+        self.inner.borrow()
+    }
+
+    /// aquire the DynObject like `aquire_ref` but without panicing
+    ///
+    /// If someone aquired the DynObject mutable
+    /// `AquireError::BorrowedMutable` is returned instead.
+    ///
+    pub fn try_aquire_ref(&self) -> Result<Ref<InnerDynObject<Key, S>>, AquireError> {
+        match self.inner.borrow_state() {
+            BorrowState::Writing => Err(AquireError::BorrowedMutable),
+            _ => Ok(self.inner.borrow())
+        }
+    }
+
+    /// borrows the value of a single property
+    ///
+    /// The object is only aquired while the property is looked up.
+    /// The returned `PropRef` does not hold it, so other properties
+    /// can still be read and written while the guard exists, e.g.
+    /// over a clone of this DynObject. A `PropertyError` is returned if the
+    /// property does not exist, has a different type or is borrowed mutable.
+    /// If the object is currently aquired mutable `PropertyError::Borrowed` is
+    /// returned. Readers holding `aquire_ref` do not block the borrow, except
+    /// when the value is borrowed the first time, because it is then moved into
+    /// storage shared with the guard, witch needs the object mutable.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::DynObject;
+    /// let obj = DynObject::<&'static str>::new();
+    /// obj.aquire().create_property("counter1", Box::new(0u32)).unwrap();
+    /// obj.aquire().create_property("counter2", Box::new(0u32)).unwrap();
+    /// let mut counter1 = obj.borrow_prop_mut::<u32>(&"counter1").unwrap();
+    /// let mut counter2 = obj.borrow_prop_mut::<u32>(&"counter2").unwrap();
+    /// *counter1 += 1;
+    /// *counter2 += 2;
+    /// assert!(obj.borrow_prop::<u32>(&"counter1").is_err());
+    /// ```
+    ///
+    pub fn borrow_prop<T: Any>(&self, key: &Key) -> Result<PropRef<T>, PropertyError<()>> {
+        match self.try_aquire_ref() {
+            Ok(inner) => match inner_dyn_object::borrow_shared_prop::<T, Key, S>(&*inner, key) {
+                Some(res) => return res,
+                None => {}
+            },
+            Err(_) => return Err(PropertyError::Borrowed(()))
+        }
+        match self.try_aquire() {
+            Ok(mut inner) => inner.borrow_prop::<T>(key),
+            Err(_) => Err(PropertyError::Borrowed(()))
+        }
+    }
+
+    /// mutable borrows the value of a single property
+    ///
+    /// Works like `borrow_prop` but returns a `PropMut` guard
+    /// and fails if the property is borrowed in any way.
+    ///
+    pub fn borrow_prop_mut<T: Any>(&self, key: &Key) -> Result<PropMut<T>, PropertyError<()>> {
+        match self.try_aquire() {
+            Ok(mut inner) => inner.borrow_prop_mut::<T>(key),
+            Err(_) => Err(PropertyError::Borrowed(()))
+        }
+    }
+
+    /// returns the prototype of this DynObject
+    ///
+    /// # Panics
+    /// panics if the DynObject is aquired mutable
+    ///
+    pub fn prototype(&self) -> Option<DynObject<Key, S>> {
+        self.aquire_ref().prototype().cloned()
+    }
+
+    /// replaces the prototype of this DynObject returning the old one
+    ///
+    /// Lookups with `InnerDynObject::with_property` search the prototype
+    /// if a key does not exist locally. A prototype chain containing
+    /// this object is rejected with `PrototypeError::Cycle`. If this
+    /// object or a object of the new chain is aquired mutable
+    /// `PrototypeError::Aquired` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{DynObject, PrototypeError};
+    /// let template = DynObject::<&'static str>::new();
+    /// let obj = DynObject::<&'static str>::new();
+    /// assert!(obj.set_prototype(Some(template.clone())).is_ok());
+    /// assert_eq!(template.set_prototype(Some(obj.clone())).err(), Some(PrototypeError::Cycle));
+    /// ```
+    ///
+    pub fn set_prototype(&self, prototype: Option<DynObject<Key, S>>)
+        -> Result<Option<DynObject<Key, S>>, PrototypeError>
+    {
+        let mut current = prototype.clone();
+        while let Some(obj) = current {
+            if same_object(self, &obj) {
+                return Err(PrototypeError::Cycle);
+            }
+            current = match obj.try_aquire_ref() {
+                Ok(inner) => inner.prototype().cloned(),
+                Err(_) => return Err(PrototypeError::Aquired)
+            };
+        }
+        match self.try_aquire() {
+            Ok(mut inner) => Ok(inner_dyn_object::set_prototype(&mut *inner, prototype)),
+            Err(_) => Err(PrototypeError::Aquired)
+        }
+    }
+
+    /// returns true if the DynObject is currently aquired (mutable or not)
+    ///
+    pub fn is_borrowed(&self) -> bool {
+        match self.inner.borrow_state() {
+            BorrowState::Unused => false,
+            _ => true
+        }
+    }
+
+    /// returns true if the DynObject is currently aquired mutable
+    ///
+    pub fn is_borrowed_mutable(&self) -> bool {
+        match self.inner.borrow_state() {
+            BorrowState::Writing => true,
+            _ => false
+        }
+    }
+
+    /// creates a `WeakDynObject` witch does not keep this object alive
+    ///
+    pub fn downgrade(&self) -> WeakDynObject<Key, S> {
+        weak_dyn_object::downgrade(self)
+    }
+
+    /// returns the number of DynObjects sharing the inner object
+    ///
+    pub fn strong_count(&self) -> usize {
+        rc::strong_count(&self.inner)
+    }
+
+    /// returns the number of `WeakDynObject`s refering to the inner object
+    ///
+    pub fn weak_count(&self) -> usize {
+        rc::weak_count(&self.inner)
+    }
+
+    /// returns true if both DynObjects share the same inner object
+    ///
+    pub fn ptr_eq(&self, other: &DynObject<Key, S>) -> bool {
+        same_object(self, other)
+    }
+}
+
+impl<Key, S> DynObject<Key, S> where Key: Eq+Clone+'static, S: PropertyStorage<Key>+'static {
+
+    /// creates a independent copy of this DynObject
+    ///
+    /// In difference to `clone` the returned object does not share
+    /// anything with this object. This requires that all properties
+    /// were created with clone support, e.g. with `create_cloneable_value`
+    /// or `DynProperty::new_cloneable`, or contain a nested `DynObject<Key>`,
+    /// see `InnerDynObject::deep_clone`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{DynObject, DynProperty};
+    /// let obj = DynObject::<&'static str>::new();
+    /// obj.aquire().insert_property("counter", DynProperty::new_cloneable(Box::new(1u32))).unwrap();
+    /// let copy = obj.deep_clone().unwrap();
+    /// *copy.aquire()["counter"].as_mut::<u32>().unwrap() += 1;
+    /// assert_eq!(obj.aquire()["counter"].as_ref::<u32>(), Some(&1u32));
+    /// ```
+    ///
+    pub fn deep_clone(&self) -> Result<DynObject<Key, S>, DeepCloneError<Key>> {
+        self.deep_clone_with(ClonePolicy::Fail)
+    }
+
+    /// creates a independent copy of this DynObject using the given `ClonePolicy`
+    ///
+    pub fn deep_clone_with(&self, policy: ClonePolicy) -> Result<DynObject<Key, S>, DeepCloneError<Key>> {
+        let inner = match self.try_aquire_ref() {
+            Ok(inner) => inner,
+            Err(_) => return Err(DeepCloneError::Aquired)
+        };
+        let mut stack = vec![&*self.inner as *const _ as *const ()];
+        let res = inner_dyn_object::deep_clone_nested(&*inner, policy, &mut stack);
+        res.map(DynObject::from_inner)
+    }
+}
+
+/// returns true if both DynObjects share the same inner object
+fn same_object<Key, S>(first: &DynObject<Key, S>, second: &DynObject<Key, S>) -> bool {
+    &*first.inner as *const _ == &*second.inner as *const _
+}
+
+impl<Key, S> fmt::Debug for DynObject<Key, S> where Key: Eq+fmt::Debug, S: PropertyStorage<Key> {
+
+    /// formats the inner object, or `DynObject(<aquired>)` if it is currently aquired mutable
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_aquire_ref() {
+            Ok(inner) => write!(fter, "DynObject({:?})", &*inner),
+            Err(_) => fter.write_str("DynObject(<aquired>)")
+        }
+    }
 }
 
-impl<T> Clone for DynObject<T> where T: Eq+Hash {
+impl<T, S> Clone for DynObject<T, S> where T: Eq {
 
     /// shalow clons `DynObject` saftily sharing the inner `InnerDynbject`
     ///
@@ -114,7 +488,7 @@ impl<T> Clone for DynObject<T> where T: Eq+Hash {
 mod test_dyn_object {
     #![allow(unused_variables)]
 
-    use super::DynObject;
+    use super::{DynObject, DynProperty, AquireError, DeepCloneError, PrototypeError};
 
     fn create_dummy() -> DynObject<&'static str> {
         DynObject::<&'static str>::new()
@@ -144,6 +518,143 @@ mod test_dyn_object {
         let data2 = x.aquire();
     }
     
+    #[test]
+    fn try_aquire_should_fail_if_aquired_mutable() {
+        let x = create_dummy();
+        let data = x.aquire();
+        assert!(x.is_borrowed_mutable());
+        assert_eq!(x.try_aquire().err(), Some(AquireError::BorrowedMutable));
+        assert_eq!(x.try_aquire_ref().err(), Some(AquireError::BorrowedMutable));
+    }
+
+    #[test]
+    fn try_aquire_should_fail_if_aquired_for_reading() {
+        let x = create_dummy();
+        let data = x.aquire_ref();
+        assert!(x.is_borrowed());
+        assert!(!x.is_borrowed_mutable());
+        assert_eq!(x.try_aquire().err(), Some(AquireError::Borrowed));
+    }
+
+    #[test]
+    fn aquire_ref_multiple_times_should_not_panic() {
+        let x = create_dummy();
+        let y = x.clone();
+        let data = x.aquire_ref();
+        let data2 = y.aquire_ref();
+        assert!(x.try_aquire_ref().is_ok());
+    }
+
+    #[test]
+    fn try_aquire_should_succeed_if_not_borrowed() {
+        let x = create_dummy();
+        assert!(!x.is_borrowed());
+        assert!(x.try_aquire().is_ok());
+    }
+
+    #[test]
+    fn borrowed_properties_should_not_block_other_properties() {
+        let x = create_dummy();
+        let y = x.clone();
+        assert!(x.aquire().create_property("counter1", Box::new(1u32)).is_ok());
+        assert!(x.aquire().create_property("counter2", Box::new(2u32)).is_ok());
+        let mut counter1 = x.borrow_prop_mut::<u32>(&"counter1").unwrap();
+        *counter1 += 10;
+        *y.aquire()["counter2"].as_mut::<u32>().unwrap() += 10;
+        assert!(y.aquire()["counter1"].as_ref::<u32>().is_none());
+        assert!(y.borrow_prop::<u32>(&"counter1").is_err());
+        assert_eq!(*counter1, 11);
+        assert_eq!(*y.borrow_prop::<u32>(&"counter2").unwrap(), 12);
+    }
+
+    #[test]
+    fn borrow_prop_should_fail_if_the_object_is_aquired() {
+        let x = create_dummy();
+        assert!(x.aquire().create_property("counter", Box::new(1u32)).is_ok());
+        let data = x.aquire();
+        assert!(x.borrow_prop::<u32>(&"counter").is_err());
+    }
+
+    #[test]
+    fn borrow_prop_should_not_need_the_object_mutable_once_shared() {
+        let x = create_dummy();
+        assert!(x.aquire().create_property("counter", Box::new(1u32)).is_ok());
+        let first = x.borrow_prop::<u32>(&"counter").unwrap();
+        let data = x.aquire_ref();
+        assert_eq!(*x.borrow_prop::<u32>(&"counter").unwrap(), 1);
+        assert!(x.borrow_prop::<u8>(&"counter").is_err());
+        assert!(x.borrow_prop_mut::<u32>(&"counter").is_err());
+        assert_eq!(*first, 1);
+    }
+
+    #[test]
+    fn debug_should_print_the_inner_object() {
+        let x = create_dummy();
+        assert!(x.aquire().create_property("hallo", Box::new(1u8)).is_ok());
+        assert_eq!(format!("{:?}", x), r#"DynObject({"hallo": u8})"#);
+        let data = x.aquire();
+        assert_eq!(format!("{:?}", x), "DynObject(<aquired>)");
+    }
+
+    #[test]
+    fn deep_clone_should_clone_nested_objects() {
+        let x = create_dummy();
+        let nested = create_dummy();
+        assert!(nested.aquire().insert_property("hallo", DynProperty::new_cloneable(Box::new(1u8))).is_ok());
+        assert!(x.aquire().create_property("nested", Box::new(nested.clone())).is_ok());
+        let copy = x.deep_clone().ok().unwrap();
+        {
+            let copy_inner = copy.aquire();
+            let copy_nested = copy_inner["nested"].as_ref::<DynObject<&'static str>>().unwrap();
+            *copy_nested.aquire()["hallo"].as_mut::<u8>().unwrap() = 3;
+        }
+        assert_eq!(nested.aquire()["hallo"].as_ref::<u8>(), Some(&1u8));
+    }
+
+    #[test]
+    fn deep_clone_should_detect_cycles() {
+        let x = create_dummy();
+        assert!(x.aquire().create_property("self", Box::new(x.clone())).is_ok());
+        assert_eq!(x.deep_clone().err(), Some(DeepCloneError::Cycle("self")));
+        //break the cycle so that the test does not leak
+        assert!(x.aquire().remove_property::<DynObject<&'static str>>(&"self").is_ok());
+    }
+
+    #[test]
+    fn deep_clone_should_fail_if_aquired() {
+        let x = create_dummy();
+        let data = x.aquire();
+        assert_eq!(x.deep_clone().err(), Some(DeepCloneError::Aquired));
+    }
+
+    #[test]
+    fn set_prototype_should_reject_cycles() {
+        let x = create_dummy();
+        let y = DynObject::with_prototype(x.clone());
+        assert_eq!(x.set_prototype(Some(y.clone())).err(), Some(PrototypeError::Cycle));
+        assert_eq!(x.set_prototype(Some(x.clone())).err(), Some(PrototypeError::Cycle));
+        assert!(x.prototype().is_none());
+    }
+
+    #[test]
+    fn set_prototype_should_return_the_old_prototype() {
+        let x = create_dummy();
+        let proto = create_dummy();
+        assert!(proto.aquire().create_property("hallo", Box::new(1u8)).is_ok());
+        assert!(x.set_prototype(Some(proto.clone())).ok().unwrap().is_none());
+        assert!(x.aquire_ref().has_property(&"hallo"));
+        let old = x.set_prototype(None).ok().unwrap().unwrap();
+        assert!(super::same_object(&old, &proto));
+        assert!(!x.aquire_ref().has_property(&"hallo"));
+    }
+
+    #[test]
+    fn set_prototype_should_fail_if_aquired() {
+        let x = create_dummy();
+        let data = x.aquire();
+        assert_eq!(x.set_prototype(None).err(), Some(PrototypeError::Aquired));
+    }
+
     fn set_data(target: DynObject<&'static str>, value: i32) {
         assert!(target.aquire().create_property(&"hallo", Box::new(value)).is_ok());
     }
@@ -0,0 +1,205 @@
+
+use super::inner_dyn_object::InnerDynObject;
+use super::storage::PropertyStorage;
+use super::DynObject;
+
+
+/// creates a empty object with the key and storage type of `parent`, used by `dynobject!`
+pub fn new_nested_object<Key, S>(_parent: &InnerDynObject<Key, S>) -> DynObject<Key, S>
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    DynObject::with_storage(S::new())
+}
+
+/// creates a `DynCast` from a type to a trait object it implements
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use] extern crate dynobject;
+/// # use std::fmt::Display;
+/// # use dynobject::DynProperty;
+/// # fn main() {
+/// let mut prop = DynProperty::from_value(12u8);
+/// prop.add_cast(dyn_cast!(u8 => Display));
+/// assert_eq!(format!("{}", prop.as_dyn::<Display>().unwrap()), "12");
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! dyn_cast {
+    ($ty:ty => $trait_name:path) => {{
+        fn cast_ref(value: &$ty) -> &($trait_name + 'static) { value }
+        fn cast_mut(value: &mut $ty) -> &mut ($trait_name + 'static) { value }
+        $crate::DynCast::new(cast_ref, cast_mut)
+    }}
+}
+
+/// creates a `DynObject` with the given properties
+///
+/// Each property is written as `key => value`, properties are separated
+/// by commas. A value in braces is a nested object with the same key
+/// type, its properties are written the same way. The keys have to be
+/// literals, other keys, including identifiers, have to be put in
+/// brackets, e.g. `[name.clone()] => 3u8`. Using a literal key twice is
+/// a compile time error, using a bracketed key twice panics.
+///
+/// The values have to implement `Clone`, the properties are created with
+/// `create_cloneable_value` so that changing them can be undone, see
+/// `InnerDynObject::enable_history`.
+///
+/// The macro handles one property per recursion step, so a object with
+/// more than about 60 properties on one level exceeds the default
+/// `recursion_limit` of 64. Raise it in the crate using the macro, e.g.
+/// `#![recursion_limit = "128"]`, or add the remaining properties with
+/// `create_value`.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use] extern crate dynobject;
+/// # use dynobject::DynObject;
+/// # fn main() {
+/// let obj: DynObject<&'static str> = dynobject!{
+///     "counter" => 0u32,
+///     "limit" => 4u32,
+///     "nested" => {
+///         "name" => "inner".to_string()
+///     }
+/// };
+/// assert_eq!(obj.aquire()["limit"].as_ref::<u32>(), Some(&4u32));
+/// assert_eq!(obj.get_path::<String>(&["nested", "name"]), Ok("inner".to_string()));
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! dynobject {
+    (@check) => {};
+    (@check $first:tt $($key:tt)*) => {{
+        //a key used twice makes its second pattern unreachable
+        #[deny(unreachable_patterns)]
+        fn check_duplicate_keys() {
+            match $first {
+                $first => {},
+                $( $key => {}, )*
+                _ => {}
+            }
+        }
+        check_duplicate_keys();
+    }};
+    (@insert $obj:ident, $key:expr, $value:expr) => {{
+        //a nested object is created from the type of $obj, before it is borrowed mutable
+        let key = $key;
+        let value = $value;
+        if $obj.create_cloneable_value(key, value).is_err() {
+            panic!("dynobject!: the key {} is used twice", stringify!($key));
+        }
+    }};
+    (@nested $parent:ident, $($props:tt)*) => {{
+        let nested = $crate::__new_nested_object(&*$parent);
+        dynobject!(@fill nested, $($props)*);
+        nested
+    }};
+    (@fill $obj:ident, ) => {};
+    (@fill $obj:ident, $($props:tt)+) => {{
+        let mut inner = $obj.aquire();
+        dynobject!(@props inner, [] $($props)+);
+    }};
+    (@props $obj:ident, [$($checked:tt)*]) => {
+        dynobject!(@check $($checked)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] , $($rest:tt)*) => {
+        dynobject!(@props $obj, [$($checked)*] $($rest)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] [$key:expr] => { $($nested:tt)* } $($rest:tt)*) => {
+        dynobject!(@insert $obj, $key, dynobject!(@nested $obj, $($nested)*));
+        dynobject!(@props $obj, [$($checked)*] $($rest)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] [$key:expr] => $value:expr , $($rest:tt)*) => {
+        dynobject!(@insert $obj, $key, $value);
+        dynobject!(@props $obj, [$($checked)*] $($rest)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] [$key:expr] => $value:expr) => {
+        dynobject!(@insert $obj, $key, $value);
+        dynobject!(@check $($checked)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] $key:ident => $($rest:tt)*) => {
+        //a identifier would be a binding pattern in @check, so name the mistake instead
+        dynobject_keys_have_to_be_literals_put_other_keys_in_brackets!($key)
+    };
+    (@props $obj:ident, [$($checked:tt)*] $key:tt => { $($nested:tt)* } $($rest:tt)*) => {
+        dynobject!(@insert $obj, $key, dynobject!(@nested $obj, $($nested)*));
+        dynobject!(@props $obj, [$($checked)* $key] $($rest)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] $key:tt => $value:expr , $($rest:tt)*) => {
+        dynobject!(@insert $obj, $key, $value);
+        dynobject!(@props $obj, [$($checked)* $key] $($rest)*);
+    };
+    (@props $obj:ident, [$($checked:tt)*] $key:tt => $value:expr) => {
+        dynobject!(@insert $obj, $key, $value);
+        dynobject!(@check $($checked)* $key);
+    };
+    () => {
+        $crate::DynObject::new()
+    };
+    ($($props:tt)+) => {{
+        let obj = $crate::DynObject::new();
+        dynobject!(@fill obj, $($props)+);
+        obj
+    }};
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Display;
+    use super::super::DynObject;
+
+    #[test]
+    fn dyn_cast_should_cast_to_the_trait() {
+        let cast = dyn_cast!(u8 => Display);
+        let mut prop = super::super::DynProperty::from_value(3u8);
+        assert!(prop.add_cast(cast));
+        assert_eq!(prop.as_dyn::<Display>().unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn dynobject_should_create_nested_objects() {
+        let obj: DynObject<&'static str> = dynobject!{
+            "a" => 1u8,
+            "nested" => {
+                "b" => 2u16,
+                "empty" => {},
+            },
+            "c" => vec![3u32],
+        };
+        let inner = obj.aquire_ref();
+        assert_eq!(inner.len(), 3);
+        assert_eq!(inner["a"].as_ref::<u8>(), Some(&1u8));
+        assert_eq!(inner["c"].as_ref::<Vec<u32>>(), Some(&vec![3u32]));
+        let nested = inner["nested"].as_ref::<DynObject<&'static str>>().unwrap().aquire_ref();
+        assert_eq!(nested["b"].as_ref::<u16>(), Some(&2u16));
+        assert!(nested["empty"].as_ref::<DynObject<&'static str>>().unwrap().aquire_ref().is_empty());
+    }
+
+    #[test]
+    fn dynobject_should_accept_bracketed_keys() {
+        let name = "name".to_string();
+        let obj = dynobject!{
+            [name.clone()] => 1i32,
+            [format!("{}2", name)] => { ["x".to_string()] => 0u8 },
+            ["other".to_string()] => 2i32
+        };
+        assert_eq!(obj.aquire_ref().len(), 3);
+        assert_eq!(obj.aquire_ref()[&name].as_ref::<i32>(), Some(&1i32));
+        let obj: DynObject<u32> = dynobject!{ 1 => "one", 2 => "two" };
+        assert!(obj.aquire_ref().exists_property(&2));
+    }
+
+    #[test]
+    #[should_fail]
+    fn dynobject_should_panic_on_duplicate_bracketed_keys() {
+        let key = "a";
+        let _: DynObject<&'static str> = dynobject!{ "a" => 1u8, [key] => 2u8 };
+    }
+}
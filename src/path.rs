@@ -0,0 +1,454 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+use super::dyn_property::DynProperty;
+use super::property_error::PropertyError;
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::PropertyStorage;
+use super::DynObject;
+
+
+/// error returned by the path accessors like `get_path`
+///
+/// The key contained in the error is the path segment witch failed.
+///
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum PathError<Key> {
+    /// the path has no segments
+    Empty,
+    /// the segment at the given position of a dotted path is empty, e.g. in `"a..b"`
+    EmptySegment(usize),
+    /// there is no property with the given key
+    NotFound(Key),
+    /// the property with the given key is not a nested `DynObject`
+    NotAnObject(Key),
+    /// the property with the given key has a different type
+    TypeMismatch(Key),
+    /// the property with the given key already exists
+    AlreadyExists(Key),
+    /// the property (or nested object) with the given key is borrowed
+    Borrowed(Key),
+    /// a guard rejected the operation on the property with the given key
+    Rejected(Key),
+    /// the outermost object is aquired mutable
+    Aquired
+}
+
+impl<Key> PathError<Key> {
+
+    /// returns the path segment witch failed, if there is one
+    ///
+    pub fn segment(&self) -> Option<&Key> {
+        match *self {
+            PathError::NotFound(ref key)
+            | PathError::NotAnObject(ref key)
+            | PathError::TypeMismatch(ref key)
+            | PathError::AlreadyExists(ref key)
+            | PathError::Borrowed(ref key)
+            | PathError::Rejected(ref key) => Some(key),
+            PathError::Empty | PathError::EmptySegment(_) | PathError::Aquired => None
+        }
+    }
+}
+
+impl<Key> fmt::Display for PathError<Key> where Key: fmt::Debug {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match (self, self.segment()) {
+            (&PathError::EmptySegment(idx), _) => write!(fter, "{} at position {}", self.description(), idx),
+            (_, Some(key)) => write!(fter, "{} at path segment {:?}", self.description(), key),
+            (_, None) => fter.write_str(self.description())
+        }
+    }
+}
+
+impl<Key> Error for PathError<Key> where Key: fmt::Debug {
+    fn description(&self) -> &str {
+        match *self {
+            PathError::Empty => "path is empty",
+            PathError::EmptySegment(_) => "path segment is empty",
+            PathError::NotFound(_) => "property not found",
+            PathError::NotAnObject(_) => "property is not a DynObject",
+            PathError::TypeMismatch(_) => "property has a different type",
+            PathError::AlreadyExists(_) => "property already exists",
+            PathError::Borrowed(_) => "property is borrowed",
+            PathError::Rejected(_) => "operation was rejected by a guard",
+            PathError::Aquired => "DynObject is aquired mutable"
+        }
+    }
+}
+
+/// converts the error of a operation on the property `key`
+fn path_error<Key: Clone, V>(err: PropertyError<V>, key: &Key) -> PathError<Key> {
+    match err {
+        PropertyError::NotFound(_) => PathError::NotFound(key.clone()),
+        PropertyError::TypeMismatch { .. } => PathError::TypeMismatch(key.clone()),
+        PropertyError::AlreadyExists(_) => PathError::AlreadyExists(key.clone()),
+        PropertyError::Borrowed(_) => PathError::Borrowed(key.clone()),
+        PropertyError::Rejected(_) => PathError::Rejected(key.clone())
+    }
+}
+
+impl<Key, S> InnerDynObject<Key, S>
+    where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static
+{
+
+    /// calls `func` with the property at the end of `path`
+    ///
+    /// All segments but the last have to be nested `DynObject`s, they are
+    /// aquired for reading while `func` runs. Only own properties are
+    /// considered and the guards of every object on the path are called.
+    ///
+    pub fn with_path<R, F>(&self, path: &[Key], func: F) -> Result<R, PathError<Key>>
+        where F: FnOnce(&DynProperty) -> R
+    {
+        if path.is_empty() {
+            return Err(PathError::Empty);
+        }
+        let key = &path[0];
+        let prop = try!(self.access(key).map_err(|err| path_error(err, key)));
+        if path.len() == 1 {
+            return Ok(func(prop));
+        }
+        let nested = try!(nested_object::<Key, S>(prop, key));
+        let inner = try!(nested.try_aquire_ref().map_err(|_| PathError::Borrowed(key.clone())));
+        let res = inner.with_path(&path[1..], func);
+        res
+    }
+
+    /// returns a clone of the value at the end of `path`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::InnerDynObject;
+    /// let mut obj = InnerDynObject::<&'static str>::new();
+    /// obj.create_path(&["player", "stats", "hp"], 100u32).unwrap();
+    /// assert_eq!(obj.get_path::<u32>(&["player", "stats", "hp"]), Ok(100u32));
+    /// ```
+    ///
+    pub fn get_path<T>(&self, path: &[Key]) -> Result<T, PathError<Key>>
+        where T: Any + Clone + 'static
+    {
+        let res = self.with_path(path, |prop| match prop.as_ref::<T>() {
+            Some(value) => Ok(value.clone()),
+            None if prop.is_inner_type::<T>() => Err(PathError::Borrowed(path[path.len() - 1].clone())),
+            None => Err(PathError::TypeMismatch(path[path.len() - 1].clone()))
+        });
+        try!(res)
+    }
+
+    /// sets the value at the end of `path` returning the old value
+    ///
+    /// The property has to exist as own property of the last object,
+    /// inherited properties are not set, see `set_value`.
+    ///
+    pub fn set_path<T>(&mut self, path: &[Key], value: T) -> Result<T, PathError<Key>>
+        where T: Any + 'static
+    {
+        self.with_parent_mut(path, false, |obj, key| {
+            obj.set_value(key, value).map_err(|err| path_error(err, key))
+        })
+    }
+
+    /// creates the property at the end of `path`
+    ///
+    /// Missing objects on the way are created as empty `DynObject`s.
+    /// They are removed again if creating the property fails. The property
+    /// and the created objects can be cloned, see `create_cloneable_value`.
+    ///
+    pub fn create_path<T>(&mut self, path: &[Key], value: T) -> Result<(), PathError<Key>>
+        where T: Any + Clone + 'static
+    {
+        self.with_parent_mut(path, true, |obj, key| {
+            obj.create_cloneable_value(key.clone(), value).map_err(|err| path_error(err, key))
+        })
+    }
+
+    /// calls `func` with the object containing the last segment of `path`
+    fn with_parent_mut<R, F>(&mut self, path: &[Key], create: bool, func: F) -> Result<R, PathError<Key>>
+        where F: FnOnce(&mut InnerDynObject<Key, S>, &Key) -> Result<R, PathError<Key>>
+    {
+        if path.is_empty() {
+            return Err(PathError::Empty);
+        }
+        let key = &path[0];
+        if path.len() == 1 {
+            return func(self, key);
+        }
+        if create && !self.exists_property(key) {
+            //the created object and its history entry are removed again if the rest fails
+            let mark = inner_dyn_object::begin_history_group(self);
+            let nested = DynObject::<Key, S>::with_storage(S::new());
+            let res = match self.create_cloneable_value(key.clone(), nested) {
+                Ok(()) => self.with_nested_mut(path, create, func),
+                Err(err) => Err(path_error(err, key))
+            };
+            if res.is_err() {
+                inner_dyn_object::discard_property(self, key);
+            }
+            inner_dyn_object::end_history_group(self, mark, res.is_ok());
+            return res;
+        }
+        self.with_nested_mut(path, create, func)
+    }
+
+    /// calls `with_parent_mut` on the nested object named by the first segment of `path`
+    fn with_nested_mut<R, F>(&mut self, path: &[Key], create: bool, func: F) -> Result<R, PathError<Key>>
+        where F: FnOnce(&mut InnerDynObject<Key, S>, &Key) -> Result<R, PathError<Key>>
+    {
+        let key = &path[0];
+        let nested = {
+            let prop = try!(self.access(key).map_err(|err| path_error(err, key)));
+            try!(nested_object::<Key, S>(prop, key)).clone()
+        };
+        let mut inner = try!(nested.try_aquire().map_err(|_| PathError::Borrowed(key.clone())));
+        let res = inner.with_parent_mut(&path[1..], create, func);
+        res
+    }
+}
+
+/// returns the nested object stored in `prop`
+fn nested_object<'a, Key, S>(prop: &'a DynProperty, key: &Key) -> Result<&'a DynObject<Key, S>, PathError<Key>>
+    where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static
+{
+    match prop.as_ref::<DynObject<Key, S>>() {
+        Some(nested) => Ok(nested),
+        None if prop.is_inner_type::<DynObject<Key, S>>() => Err(PathError::Borrowed(key.clone())),
+        None => Err(PathError::NotAnObject(key.clone()))
+    }
+}
+
+impl<Key, S> DynObject<Key, S>
+    where Key: Eq + Clone + 'static, S: PropertyStorage<Key> + 'static
+{
+
+    /// returns a clone of the value at the end of `path`, see `InnerDynObject::get_path`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{DynObject, PathError};
+    /// let obj = DynObject::<&'static str>::new();
+    /// obj.create_path(&["player", "stats", "hp"], 100u32).unwrap();
+    /// assert_eq!(obj.set_path(&["player", "stats", "hp"], 90u32), Ok(100u32));
+    /// assert_eq!(obj.get_path::<u32>(&["player", "stats", "hp"]), Ok(90u32));
+    /// assert_eq!(obj.get_path::<u32>(&["player", "items", "hp"]), Err(PathError::NotFound("items")));
+    /// ```
+    ///
+    pub fn get_path<T>(&self, path: &[Key]) -> Result<T, PathError<Key>>
+        where T: Any + Clone + 'static
+    {
+        match self.try_aquire_ref() {
+            Ok(inner) => inner.get_path(path),
+            Err(_) => Err(PathError::Aquired)
+        }
+    }
+
+    /// sets the value at the end of `path`, see `InnerDynObject::set_path`
+    ///
+    pub fn set_path<T>(&self, path: &[Key], value: T) -> Result<T, PathError<Key>>
+        where T: Any + 'static
+    {
+        match self.try_aquire() {
+            Ok(mut inner) => inner.set_path(path, value),
+            Err(_) => Err(PathError::Aquired)
+        }
+    }
+
+    /// creates the property at the end of `path`, see `InnerDynObject::create_path`
+    ///
+    pub fn create_path<T>(&self, path: &[Key], value: T) -> Result<(), PathError<Key>>
+        where T: Any + Clone + 'static
+    {
+        match self.try_aquire() {
+            Ok(mut inner) => inner.create_path(path, value),
+            Err(_) => Err(PathError::Aquired)
+        }
+    }
+}
+
+/// splits a dotted path like `"player.stats.hp"` into its segments
+fn split_dotted(path: &str) -> Result<Vec<String>, PathError<String>> {
+    if path.is_empty() {
+        return Err(PathError::Empty);
+    }
+    let mut segments = Vec::new();
+    for (idx, segment) in path.split('.').enumerate() {
+        if segment.is_empty() {
+            return Err(PathError::EmptySegment(idx));
+        }
+        segments.push(segment.to_string());
+    }
+    Ok(segments)
+}
+
+impl<S> InnerDynObject<String, S> where S: PropertyStorage<String> + 'static {
+
+    /// like `get_path` with a dotted path like `"player.stats.hp"`
+    ///
+    /// A path with a empty segment, like `"player..hp"` or `"player."`,
+    /// fails with `PathError::EmptySegment`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{InnerDynObject, PathError};
+    /// let mut obj = InnerDynObject::<String>::new();
+    /// obj.create_dotted("player.stats.hp", 100u32).unwrap();
+    /// assert_eq!(obj.get_dotted::<u32>("player.stats.hp"), Ok(100u32));
+    /// assert_eq!(obj.get_dotted::<u32>("player..hp"), Err(PathError::EmptySegment(1)));
+    /// ```
+    ///
+    pub fn get_dotted<T>(&self, path: &str) -> Result<T, PathError<String>>
+        where T: Any + Clone + 'static
+    {
+        self.get_path(&try!(split_dotted(path)))
+    }
+
+    /// like `set_path` with a dotted path like `"player.stats.hp"`
+    ///
+    pub fn set_dotted<T>(&mut self, path: &str, value: T) -> Result<T, PathError<String>>
+        where T: Any + 'static
+    {
+        self.set_path(&try!(split_dotted(path)), value)
+    }
+
+    /// like `create_path` with a dotted path like `"player.stats.hp"`
+    ///
+    pub fn create_dotted<T>(&mut self, path: &str, value: T) -> Result<(), PathError<String>>
+        where T: Any + Clone + 'static
+    {
+        self.create_path(&try!(split_dotted(path)), value)
+    }
+}
+
+impl<S> DynObject<String, S> where S: PropertyStorage<String> + 'static {
+
+    /// like `get_path` with a dotted path, see `InnerDynObject::get_dotted`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::DynObject;
+    /// let obj = DynObject::<String>::new();
+    /// obj.create_dotted("player.stats.hp", 100u32).unwrap();
+    /// assert_eq!(obj.get_dotted::<u32>("player.stats.hp"), Ok(100u32));
+    /// ```
+    ///
+    pub fn get_dotted<T>(&self, path: &str) -> Result<T, PathError<String>>
+        where T: Any + Clone + 'static
+    {
+        self.get_path(&try!(split_dotted(path)))
+    }
+
+    /// like `set_path` with a dotted path, see `InnerDynObject::get_dotted`
+    ///
+    pub fn set_dotted<T>(&self, path: &str, value: T) -> Result<T, PathError<String>>
+        where T: Any + 'static
+    {
+        self.set_path(&try!(split_dotted(path)), value)
+    }
+
+    /// like `create_path` with a dotted path, see `InnerDynObject::get_dotted`
+    ///
+    pub fn create_dotted<T>(&self, path: &str, value: T) -> Result<(), PathError<String>>
+        where T: Any + Clone + 'static
+    {
+        self.create_path(&try!(split_dotted(path)), value)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::PathError;
+    use super::super::inner_dyn_object::InnerDynObject;
+    use super::super::hooks::Operation;
+    use super::super::DynObject;
+
+    #[test]
+    fn create_path_should_create_missing_objects() {
+        let mut obj = InnerDynObject::<&'static str>::new();
+        assert_eq!(obj.create_path(&["a", "b", "c"], 1u8), Ok(()));
+        assert_eq!(obj.create_path(&["a", "b", "d"], 2u8), Ok(()));
+        assert_eq!(obj.create_path(&["a", "b", "c"], 3u8), Err(PathError::AlreadyExists("c")));
+        assert!(obj["a"].is_inner_type::<DynObject<&'static str>>());
+        assert_eq!(obj.get_path::<u8>(&["a", "b", "d"]), Ok(2u8));
+    }
+
+    #[test]
+    fn errors_should_name_the_failed_segment() {
+        let mut obj = InnerDynObject::<&'static str>::new();
+        obj.create_path(&["a", "b"], 1u8).unwrap();
+        assert_eq!(obj.get_path::<u8>(&[]), Err(PathError::Empty));
+        assert_eq!(obj.get_path::<u8>(&["x", "b"]), Err(PathError::NotFound("x")));
+        assert_eq!(obj.get_path::<u8>(&["a", "b", "c"]), Err(PathError::NotAnObject("b")));
+        assert_eq!(obj.get_path::<u16>(&["a", "b"]), Err(PathError::TypeMismatch("b")));
+        assert_eq!(obj.set_path(&["a", "x"], 2u8), Err(PathError::NotFound("x")));
+        assert_eq!(obj.create_path(&["a", "b", "c"], 2u8), Err(PathError::NotAnObject("b")));
+    }
+
+    #[test]
+    fn path_accessors_should_call_the_guards() {
+        let obj = DynObject::<&'static str>::new();
+        obj.create_path(&["a", "b"], 1u8).unwrap();
+        let nested = obj.aquire()["a"].as_ref::<DynObject<&'static str>>().unwrap().clone();
+        nested.aquire().add_key_guard("b", |_: &&'static str, op| op == Operation::Access);
+        assert_eq!(obj.get_path::<u8>(&["a", "b"]), Ok(1u8));
+        assert_eq!(obj.set_path(&["a", "b"], 2u8), Err(PathError::Rejected("b")));
+    }
+
+    #[test]
+    fn aquired_objects_should_be_reported() {
+        let obj = DynObject::<&'static str>::new();
+        obj.create_path(&["a", "b"], 1u8).unwrap();
+        let nested = obj.aquire()["a"].as_ref::<DynObject<&'static str>>().unwrap().clone();
+        {
+            let _guard = nested.aquire();
+            assert_eq!(obj.get_path::<u8>(&["a", "b"]), Err(PathError::Borrowed("a")));
+        }
+        let _guard = obj.aquire();
+        assert_eq!(obj.get_path::<u8>(&["a", "b"]), Err(PathError::Aquired));
+    }
+
+    #[test]
+    fn dotted_paths_should_be_split_at_the_dots() {
+        let obj = DynObject::<String>::new();
+        obj.create_dotted("player.stats.hp", 10u32).unwrap();
+        assert_eq!(obj.set_dotted("player.stats.hp", 20u32), Ok(10u32));
+        assert_eq!(obj.get_path::<u32>(&["player".to_string(), "stats".to_string(), "hp".to_string()]), Ok(20u32));
+        assert_eq!(obj.get_dotted::<u32>("player.hp"), Err(PathError::NotFound("hp".to_string())));
+    }
+
+    #[test]
+    fn dotted_paths_should_reject_empty_segments() {
+        let mut obj = InnerDynObject::<String>::new();
+        assert_eq!(obj.create_dotted("a..b", 1u8), Err(PathError::EmptySegment(1)));
+        assert_eq!(obj.create_dotted("a.", 1u8), Err(PathError::EmptySegment(1)));
+        assert_eq!(obj.set_dotted(".a", 1u8), Err(PathError::EmptySegment(0)));
+        assert_eq!(obj.get_dotted::<u8>(""), Err(PathError::Empty));
+        assert!(obj.is_empty());
+        assert_eq!(PathError::EmptySegment::<String>(1).to_string(), "path segment is empty at position 1");
+        obj.create_dotted("a.b", 1u8).unwrap();
+        assert_eq!(obj.set_dotted("a.b", 2u8), Ok(1u8));
+        assert_eq!(obj.get_dotted::<u8>("a.b"), Ok(2u8));
+    }
+
+    #[test]
+    fn create_path_should_remove_the_created_objects_if_it_fails() {
+        let mut obj = InnerDynObject::<&'static str>::new();
+        obj.enable_history(10);
+        obj.create_path(&["n", "a"], 1u8).unwrap();
+        let nested = obj["n"].as_ref::<DynObject<&'static str>>().unwrap().clone();
+        nested.aquire().add_key_guard("m", |_: &&'static str, op| op == Operation::Create);
+        assert_eq!(obj.create_path(&["n", "m", "b"], 2u8), Err(PathError::Rejected("m")));
+        assert!(!nested.aquire_ref().exists_property(&"m"));
+        obj.add_guard(|key: &&'static str, op| *key != "x" || op == Operation::Create);
+        assert_eq!(obj.create_path(&["x", "y"], 3u8), Err(PathError::Rejected("x")));
+        assert!(!obj.exists_property(&"x"));
+        assert!(obj.undo());
+        assert!(!obj.exists_property(&"n"));
+        assert!(!obj.undo());
+    }
+}
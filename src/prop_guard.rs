@@ -0,0 +1,155 @@
+use std::any::Any;
+use std::cell::{Cell, UnsafeCell};
+use std::ops::{Deref, DerefMut};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+
+type BorrowFlag = isize;
+const UNUSED: BorrowFlag = 0;
+const WRITING: BorrowFlag = -1;
+
+/// the value of a `DynProperty` witch was borrowed at least once
+///
+/// It is shared between the property and all `PropRef`/`PropMut`
+/// guards. The borrow flag works like the one of `RefCell`.
+pub struct SharedValue {
+    borrow: Cell<BorrowFlag>,
+    value: UnsafeCell<Box<Any+'static>>
+}
+
+impl SharedValue {
+
+    pub fn new(value: Box<Any+'static>) -> SharedValue {
+        SharedValue {
+            borrow: Cell::new(UNUSED),
+            value: UnsafeCell::new(value)
+        }
+    }
+
+    pub fn is_borrowed(&self) -> bool {
+        self.borrow.get() != UNUSED
+    }
+
+    pub fn is_borrowed_mutable(&self) -> bool {
+        self.borrow.get() == WRITING
+    }
+
+    /// returns the inner value if it is not borrowed mutable
+    pub fn get(&self) -> Option<&Any> {
+        if self.is_borrowed_mutable() {
+            None
+        } else {
+            Some(unsafe { &**self.value.get() })
+        }
+    }
+
+    /// returns the inner value if it is not borrowed at all
+    ///
+    /// This is unsafe because the caller has to make sure that no
+    /// new guard is created while the returned reference exists,
+    /// e.g. by holding the owning `DynProperty` mutable.
+    pub unsafe fn get_mut(&self) -> Option<&mut Box<Any+'static>> {
+        if self.is_borrowed() {
+            None
+        } else {
+            Some(&mut *self.value.get())
+        }
+    }
+
+    pub fn into_inner(self) -> Box<Any+'static> {
+        unsafe { self.value.into_inner() }
+    }
+}
+
+/// creates a new `PropRef` if the value is not borrowed mutable and has type `T`
+pub fn borrow_ref<T: Any>(shared: &Rc<SharedValue>) -> Option<PropRef<T>> {
+    if shared.is_borrowed_mutable() {
+        return None;
+    }
+    let ptr = match unsafe { (*shared.value.get()).downcast_ref::<T>() } {
+        Some(value) => value as *const T,
+        None => return None
+    };
+    shared.borrow.set(shared.borrow.get() + 1);
+    Some(PropRef {
+        shared: shared.clone(),
+        value: ptr
+    })
+}
+
+/// creates a new `PropMut` if the value is not borrowed at all and has type `T`
+pub fn borrow_mut<T: Any>(shared: &Rc<SharedValue>) -> Option<PropMut<T>> {
+    if shared.is_borrowed() {
+        return None;
+    }
+    let ptr = match unsafe { (*shared.value.get()).downcast_mut::<T>() } {
+        Some(value) => value as *mut T,
+        None => return None
+    };
+    shared.borrow.set(WRITING);
+    Some(PropMut {
+        shared: shared.clone(),
+        value: ptr,
+        marker: PhantomData
+    })
+}
+
+
+/// a shared borrow of the value of a single `DynProperty`
+///
+/// The guard keeps the value alive on its own, so it does not
+/// hold the `DynObject` containing the property aquired. As long
+/// as it exists the property can not be borrowed mutable, set or removed.
+///
+pub struct PropRef<T> {
+    shared: Rc<SharedValue>,
+    value: *const T
+}
+
+impl<T> Deref for PropRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T> Drop for PropRef<T> {
+    fn drop(&mut self) {
+        let flag = self.shared.borrow.get();
+        self.shared.borrow.set(flag - 1);
+    }
+}
+
+/// a mutable borrow of the value of a single `DynProperty`
+///
+/// The guard keeps the value alive on its own, so it does not
+/// hold the `DynObject` containing the property aquired. As long
+/// as it exists the property can not be accessed by anyone else.
+///
+pub struct PropMut<T> {
+    shared: Rc<SharedValue>,
+    value: *mut T,
+    marker: PhantomData<T>
+}
+
+impl<T> Deref for PropMut<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T> DerefMut for PropMut<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<T> Drop for PropMut<T> {
+    fn drop(&mut self) {
+        self.shared.borrow.set(UNUSED);
+    }
+}
@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fmt;
+
+
+/// error returned by operations on a property
+///
+/// The `V` parameter is the value passed to the failed operation,
+/// e.g. `Box<T>` for `create_property` or `set_property`. It is
+/// handed back so that it will not be lost. Operations without
+/// a value like `remove_property` use `()`.
+///
+#[derive(PartialEq, Eq, Clone)]
+pub enum PropertyError<V> {
+    /// there is no property with the given key
+    NotFound(V),
+    /// the property exists but has a different type
+    TypeMismatch {
+        /// the name of the type requested by the operation
+        expected: &'static str,
+        /// the name of the type the property actually has
+        found: &'static str,
+        /// the value passed to the operation
+        value: V
+    },
+    /// there already is a property with the given key
+    AlreadyExists(V),
+    /// the property (or object) is currently borrowed
+    Borrowed(V),
+    /// a guard of the object rejected the operation
+    Rejected(V)
+}
+
+impl<V> PropertyError<V> {
+
+    /// consumes the error returning the rejected value
+    ///
+    pub fn into_value(self) -> V {
+        match self {
+            PropertyError::NotFound(value) => value,
+            PropertyError::TypeMismatch { value, .. } => value,
+            PropertyError::AlreadyExists(value) => value,
+            PropertyError::Borrowed(value) => value,
+            PropertyError::Rejected(value) => value
+        }
+    }
+
+    /// returns a reference to the rejected value
+    ///
+    pub fn value(&self) -> &V {
+        match *self {
+            PropertyError::NotFound(ref value) => value,
+            PropertyError::TypeMismatch { ref value, .. } => value,
+            PropertyError::AlreadyExists(ref value) => value,
+            PropertyError::Borrowed(ref value) => value,
+            PropertyError::Rejected(ref value) => value
+        }
+    }
+
+    /// replaces the rejected value keeping the kind of error
+    ///
+    pub fn map_value<U, F>(self, func: F) -> PropertyError<U> where F: FnOnce(V) -> U {
+        match self {
+            PropertyError::NotFound(value) => PropertyError::NotFound(func(value)),
+            PropertyError::TypeMismatch { expected, found, value } => PropertyError::TypeMismatch {
+                expected: expected,
+                found: found,
+                value: func(value)
+            },
+            PropertyError::AlreadyExists(value) => PropertyError::AlreadyExists(func(value)),
+            PropertyError::Borrowed(value) => PropertyError::Borrowed(func(value)),
+            PropertyError::Rejected(value) => PropertyError::Rejected(func(value))
+        }
+    }
+}
+
+impl<V> fmt::Debug for PropertyError<V> {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PropertyError::NotFound(_) => fter.write_str("NotFound"),
+            PropertyError::TypeMismatch { expected, found, .. } =>
+                write!(fter, "TypeMismatch {{ expected: {:?}, found: {:?} }}", expected, found),
+            PropertyError::AlreadyExists(_) => fter.write_str("AlreadyExists"),
+            PropertyError::Borrowed(_) => fter.write_str("Borrowed"),
+            PropertyError::Rejected(_) => fter.write_str("Rejected")
+        }
+    }
+}
+
+impl<V> fmt::Display for PropertyError<V> {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PropertyError::TypeMismatch { expected, found, .. } =>
+                write!(fter, "property has type {} but {} was expected", found, expected),
+            _ => fter.write_str(self.description())
+        }
+    }
+}
+
+impl<V> Error for PropertyError<V> {
+    fn description(&self) -> &str {
+        match *self {
+            PropertyError::NotFound(_) => "property does not exist",
+            PropertyError::TypeMismatch { .. } => "property has a different type",
+            PropertyError::AlreadyExists(_) => "property already exists",
+            PropertyError::Borrowed(_) => "property is currently borrowed",
+            PropertyError::Rejected(_) => "operation was rejected by a guard"
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::PropertyError;
+
+    #[test]
+    fn into_value_should_return_the_rejected_value() {
+        let err = PropertyError::TypeMismatch { expected: "i32", found: "u8", value: Box::new(3i32) };
+        assert_eq!(err.into_value(), Box::new(3i32));
+        assert_eq!(PropertyError::Borrowed(4u8).into_value(), 4u8);
+    }
+
+    #[test]
+    fn display_should_name_both_types_on_type_mismatch() {
+        let err = PropertyError::TypeMismatch { expected: "i32", found: "u8", value: () };
+        assert_eq!(format!("{}", err), "property has type u8 but i32 was expected");
+    }
+
+    #[test]
+    fn map_value_should_keep_the_kind_of_error() {
+        let err = PropertyError::AlreadyExists(4u8).map_value(|_| ());
+        assert_eq!(err, PropertyError::AlreadyExists(()));
+    }
+}
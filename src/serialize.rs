@@ -0,0 +1,431 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, BTreeMap};
+use std::hash::Hash;
+use std::error::Error;
+use std::fmt;
+
+use rustc_serialize::{Encodable, Decodable};
+use rustc_serialize::json::{self, Json, EncoderError, DecoderError, DecodeResult};
+
+use super::dyn_property::{self, DynProperty};
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::PropertyStorage;
+use super::DynObject;
+
+
+/// converts the inner value to `Json`
+pub type EncodeFn = fn(&Any) -> Result<Json, EncodeError>;
+
+/// decodes a value from `Json` and wraps it into a `DynProperty`
+type DecodeFn = fn(&TypeRegistry, Json) -> DecodeResult<DynProperty>;
+
+/// the encoding support captured by a `DynProperty`
+#[derive(Copy, Clone)]
+pub struct EncodeInfo {
+    pub tag: &'static str,
+    pub encode: EncodeFn
+}
+
+struct Entry {
+    info: EncodeInfo,
+    decode: DecodeFn
+}
+
+/// error returned if a `InnerDynObject` can not be encoded
+#[derive(PartialEq, Debug)]
+pub enum EncodeError {
+    /// a property did not capture the encoding support of a `TypeRegistry`,
+    /// contains the name of its type
+    NotEncodable(&'static str),
+    /// a property or nested object is borrowed mutable
+    Borrowed,
+    /// a key is not encoded as string or number
+    BadKey,
+    /// the json encoder failed
+    Json(EncoderError)
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::NotEncodable(type_name) => write!(fter, "{} of type {}", self.description(), type_name),
+            _ => fter.write_str(self.description())
+        }
+    }
+}
+
+impl Error for EncodeError {
+    fn description(&self) -> &str {
+        match *self {
+            EncodeError::NotEncodable(_) => "property is not encodable",
+            EncodeError::Borrowed => "property is borrowed mutable",
+            EncodeError::BadKey => "key is not encoded as string or number",
+            EncodeError::Json(_) => "json encoder failed"
+        }
+    }
+}
+
+/// encodes `value` as `Json`
+fn to_json<T: Encodable>(value: &T) -> Result<Json, EncodeError> {
+    let text = try!(json::encode(value).map_err(EncodeError::Json));
+    //the encoder only writes valid json
+    Ok(Json::from_str(&text).unwrap())
+}
+
+fn encode_value<T: Any+Encodable>(value: &Any) -> Result<Json, EncodeError> {
+    to_json(value.downcast_ref::<T>().unwrap())
+}
+
+fn encode_object<Key>(value: &Any) -> Result<Json, EncodeError>
+    where Key: Eq+Hash+Encodable+'static
+{
+    match value.downcast_ref::<DynObject<Key>>().unwrap().try_aquire_ref() {
+        Ok(inner) => inner.to_json(),
+        Err(_) => Err(EncodeError::Borrowed)
+    }
+}
+
+fn decode_value<T: Any+Decodable>(_: &TypeRegistry, value: Json) -> DecodeResult<DynProperty> {
+    let value: T = try!(Decodable::decode(&mut json::Decoder::new(value)));
+    Ok(DynProperty::from_value(value))
+}
+
+fn decode_object<Key>(registry: &TypeRegistry, value: Json) -> DecodeResult<DynProperty>
+    where Key: Eq+Hash+Decodable+'static
+{
+    registry.from_json::<Key>(value).map(|inner| DynProperty::from_value(DynObject::from_inner(inner)))
+}
+
+/// encodes a key as the name of a json object member
+fn key_to_string<Key: Encodable>(key: &Key) -> Result<String, EncodeError> {
+    match try!(to_json(key)) {
+        Json::String(key) => Ok(key),
+        Json::I64(key) => Ok(key.to_string()),
+        Json::U64(key) => Ok(key.to_string()),
+        _ => Err(EncodeError::BadKey)
+    }
+}
+
+/// encodes a property as array of its tag and value
+fn encode_property(prop: &DynProperty) -> Result<Json, EncodeError> {
+    let info = match dyn_property::encode_info(prop) {
+        Some(info) => info,
+        None => return Err(EncodeError::NotEncodable(prop.type_name()))
+    };
+    let value = match dyn_property::inner_value(prop) {
+        Some(value) => value,
+        None => return Err(EncodeError::Borrowed)
+    };
+    Ok(Json::Array(vec![Json::String(info.tag.to_string()), try!((info.encode)(value))]))
+}
+
+fn expected(what: &str, found: &Json) -> DecoderError {
+    DecoderError::ExpectedError(what.to_string(), found.to_string())
+}
+
+
+/// maps encodable types to stable string tags
+///
+/// Properties only know their type at runtime, so to encode them
+/// the type has to be registered under a tag. This tag is written
+/// together with the value and used to find the right type when
+/// decoding. The tags have to stay the same between encoding and
+/// decoding.
+///
+/// A property captures the encoding support of its type when it is
+/// created over `new_property` or decoded, `attach` lets existing
+/// properties capture it. `InnerDynObject::encode` (and `to_json`)
+/// fail if a property did not capture it. Objects are written as json
+/// object, each property as array of its tag and value, the keys have
+/// to be encoded as string or number.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{InnerDynObject, TypeRegistry};
+/// let mut registry = TypeRegistry::new();
+/// registry.register::<u32>("u32");
+/// let mut obj = InnerDynObject::<String>::new();
+/// obj.insert_property("counter".to_string(), registry.new_property(Box::new(3u32))).unwrap();
+/// let text = obj.encode().unwrap();
+/// assert_eq!(text, r#"{"counter":["u32",3]}"#);
+/// let restored = registry.decode::<String>(&text).unwrap();
+/// assert_eq!(restored[&"counter".to_string()].as_ref::<u32>(), Some(&3u32));
+/// ```
+///
+pub struct TypeRegistry {
+    by_type: HashMap<TypeId, Entry>,
+    by_tag: HashMap<&'static str, TypeId>
+}
+
+impl TypeRegistry {
+
+    /// creates a empty TypeRegistry
+    ///
+    pub fn new() -> TypeRegistry {
+        TypeRegistry {
+            by_type: HashMap::new(),
+            by_tag: HashMap::new()
+        }
+    }
+
+    /// registers the type `T` under the given tag
+    ///
+    /// Returns false, without changing the registry, if the type
+    /// or the tag is already registered.
+    ///
+    pub fn register<T>(&mut self, tag: &'static str) -> bool
+        where T: Any+Encodable+Decodable
+    {
+        self.insert(TypeId::of::<T>(), Entry {
+            info: EncodeInfo {
+                tag: tag,
+                encode: encode_value::<T> as EncodeFn
+            },
+            decode: decode_value::<T> as DecodeFn
+        })
+    }
+
+    /// registers nested objects of the type `DynObject<Key>` under the given tag
+    ///
+    /// Returns false, without changing the registry, if the type
+    /// or the tag is already registered.
+    ///
+    pub fn register_object<Key>(&mut self, tag: &'static str) -> bool
+        where Key: Eq+Hash+Encodable+Decodable+'static
+    {
+        self.insert(TypeId::of::<DynObject<Key>>(), Entry {
+            info: EncodeInfo {
+                tag: tag,
+                encode: encode_object::<Key> as EncodeFn
+            },
+            decode: decode_object::<Key> as DecodeFn
+        })
+    }
+
+    fn insert(&mut self, type_id: TypeId, entry: Entry) -> bool {
+        if self.by_type.contains_key(&type_id) || self.by_tag.contains_key(entry.info.tag) {
+            return false;
+        }
+        self.by_tag.insert(entry.info.tag, type_id);
+        self.by_type.insert(type_id, entry);
+        true
+    }
+
+    /// returns true if the type `T` is registered
+    ///
+    pub fn is_registered<T: Any>(&self) -> bool {
+        self.by_type.contains_key(&TypeId::of::<T>())
+    }
+
+    /// creates a new property witch captured the encoding support of `T`
+    ///
+    /// The property is created like `DynProperty::new` if `T` is not registered.
+    ///
+    pub fn new_property<T: Any>(&self, initial_value: Box<T>) -> DynProperty {
+        let mut prop = DynProperty::new(initial_value);
+        self.attach_property(&mut prop);
+        prop
+    }
+
+    /// lets all properties of `obj` capture the encoding support of their type
+    ///
+    /// Nested objects are attached too, if they are not aquired at the moment.
+    /// Returns true if all properties can be encoded afterwards.
+    ///
+    pub fn attach<Key, S>(&self, obj: &mut InnerDynObject<Key, S>) -> bool
+        where Key: Eq+'static, S: PropertyStorage<Key>+'static
+    {
+        let mut all_attached = true;
+        for (_, prop) in inner_dyn_object::properties_mut(obj).iter_mut() {
+            all_attached = self.attach_property(prop) && all_attached;
+            match prop.as_ref::<DynObject<Key, S>>() {
+                Some(nested) => all_attached = match nested.try_aquire() {
+                    Ok(mut nested) => self.attach(&mut *nested) && all_attached,
+                    Err(_) => false
+                },
+                None => {}
+            }
+        }
+        all_attached
+    }
+
+    /// lets a property capture the encoding support of its type
+    ///
+    /// Returns false if the type of the property is not registered.
+    ///
+    pub fn attach_property(&self, prop: &mut DynProperty) -> bool {
+        match self.by_type.get(&dyn_property::inner_type_id(prop)) {
+            Some(entry) => {
+                dyn_property::set_encode_info(prop, entry.info);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// decodes a `InnerDynObject<Key>` from json text written by `InnerDynObject::encode`
+    ///
+    pub fn decode<Key>(&self, text: &str) -> DecodeResult<InnerDynObject<Key>>
+        where Key: Eq+Hash+Decodable
+    {
+        match Json::from_str(text) {
+            Ok(value) => self.from_json(value),
+            Err(err) => Err(DecoderError::ParseError(err))
+        }
+    }
+
+    /// decodes a `InnerDynObject<Key>` from `Json` returned by `InnerDynObject::to_json`
+    ///
+    /// The properties capture the encoding support of their type.
+    /// Unknown tags and values witch do not match the type of their
+    /// tag are errors.
+    ///
+    pub fn from_json<Key>(&self, value: Json) -> DecodeResult<InnerDynObject<Key>>
+        where Key: Eq+Hash+Decodable
+    {
+        let members = match value {
+            Json::Object(members) => members,
+            other => return Err(expected("Object", &other))
+        };
+        let mut obj = InnerDynObject::new();
+        for (name, value) in members.into_iter() {
+            let key: Key = try!(Decodable::decode(&mut json::Decoder::new(Json::String(name.clone()))));
+            let prop = try!(self.decode_property(value));
+            if obj.insert_property(key, prop).is_err() {
+                return Err(DecoderError::ApplicationError(format!("duplicate property {}", name)));
+            }
+        }
+        Ok(obj)
+    }
+
+    /// decodes the array of tag and value written by `encode_property`
+    fn decode_property(&self, value: Json) -> DecodeResult<DynProperty> {
+        let mut parts = match value {
+            Json::Array(parts) => parts,
+            other => return Err(expected("Array of type tag and value", &other))
+        };
+        if parts.len() != 2 {
+            return Err(expected("Array of type tag and value", &Json::Array(parts)));
+        }
+        let value = parts.pop().unwrap();
+        let tag = match parts.pop().unwrap() {
+            Json::String(tag) => tag,
+            other => return Err(expected("String", &other))
+        };
+        let entry = match self.by_tag.get(&*tag).and_then(|type_id| self.by_type.get(type_id)) {
+            Some(entry) => entry,
+            None => return Err(DecoderError::ApplicationError(format!("unknown type tag {}", tag)))
+        };
+        let mut prop = try!((entry.decode)(self, value));
+        dyn_property::set_encode_info(&mut prop, entry.info);
+        Ok(prop)
+    }
+}
+
+
+impl<Key, S> InnerDynObject<Key, S> where Key: Eq+Encodable, S: PropertyStorage<Key> {
+
+    /// encodes all own properties as json text
+    ///
+    /// See `TypeRegistry` for the format. Fails if a property did not
+    /// capture the encoding support of its type or is borrowed mutable.
+    /// Guards are not asked.
+    ///
+    pub fn encode(&self) -> Result<String, EncodeError> {
+        self.to_json().map(|value| value.to_string())
+    }
+
+    /// encodes all own properties as `Json`, see `encode`
+    ///
+    pub fn to_json(&self) -> Result<Json, EncodeError> {
+        let mut members = BTreeMap::new();
+        for (key, prop) in inner_dyn_object::properties(self).iter() {
+            members.insert(try!(key_to_string(key)), try!(encode_property(prop)));
+        }
+        Ok(Json::Object(members))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use rustc_serialize::json::DecoderError;
+
+    use super::{TypeRegistry, EncodeError};
+    use super::super::inner_dyn_object::InnerDynObject;
+    use super::super::DynObject;
+
+    fn create_registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::new();
+        assert!(registry.register::<u32>("u32"));
+        assert!(registry.register::<String>("string"));
+        assert!(registry.register::<Vec<i8>>("bytes"));
+        assert!(registry.register_object::<String>("object"));
+        registry
+    }
+
+    #[test]
+    fn register_should_reject_duplicate_tags_and_types() {
+        let mut registry = create_registry();
+        assert!(!registry.register::<u32>("other"));
+        assert!(!registry.register::<u8>("u32"));
+        assert!(registry.is_registered::<u32>());
+        assert!(!registry.is_registered::<u8>());
+    }
+
+    #[test]
+    fn encode_should_fail_for_properties_without_encoding_support() {
+        let registry = create_registry();
+        let mut obj = InnerDynObject::<String>::new();
+        obj.create_value("counter".to_string(), 3u32).unwrap();
+        assert_eq!(obj.encode(), Err(EncodeError::NotEncodable("u32")));
+        assert!(registry.attach(&mut obj));
+        obj.create_value("flag".to_string(), true).unwrap();
+        assert!(!registry.attach(&mut obj));
+        assert_eq!(obj.encode(), Err(EncodeError::NotEncodable("bool")));
+    }
+
+    #[test]
+    fn decode_should_fail_for_unknown_tags_and_wrong_values() {
+        let registry = create_registry();
+        match registry.decode::<String>(r#"{"counter":["u8",3]}"#) {
+            Err(DecoderError::ApplicationError(_)) => {},
+            _ => panic!("expected a unknown tag")
+        }
+        assert!(registry.decode::<String>(r#"{"counter":["u32","three"]}"#).is_err());
+        assert!(registry.decode::<String>(r#"{"counter":3}"#).is_err());
+    }
+
+    #[test]
+    fn objects_should_survive_a_round_trip() {
+        let registry = create_registry();
+        let nested = DynObject::<String>::new();
+        nested.aquire().insert_property("hp".to_string(), registry.new_property(Box::new(10u32))).unwrap();
+        let mut obj = InnerDynObject::<String>::new();
+        obj.create_value("stats".to_string(), nested).unwrap();
+        obj.create_value("name".to_string(), "hero".to_string()).unwrap();
+        obj.create_value("bytes".to_string(), vec![-1i8, 2]).unwrap();
+        assert!(registry.attach(&mut obj));
+        let text = obj.encode().unwrap();
+        assert_eq!(text, r#"{"bytes":["bytes",[-1,2]],"name":["string","hero"],"stats":["object",{"hp":["u32",10]}]}"#);
+        let restored = registry.decode::<String>(&text).unwrap();
+        assert_eq!(restored.encode(), Ok(text));
+        assert_eq!(restored[&"name".to_string()].as_ref::<String>(), Some(&"hero".to_string()));
+        assert_eq!(restored[&"bytes".to_string()].as_ref::<Vec<i8>>(), Some(&vec![-1i8, 2]));
+        let stats = restored[&"stats".to_string()].as_ref::<DynObject<String>>().unwrap();
+        assert_eq!(stats.aquire_ref()[&"hp".to_string()].as_ref::<u32>(), Some(&10u32));
+    }
+
+    #[test]
+    fn number_keys_should_be_written_as_strings() {
+        let mut registry = TypeRegistry::new();
+        registry.register::<u32>("u32");
+        let mut obj = InnerDynObject::<u16>::new();
+        obj.insert_property(7, registry.new_property(Box::new(1u32))).unwrap();
+        let text = obj.encode().unwrap();
+        assert_eq!(text, r#"{"7":["u32",1]}"#);
+        let restored = registry.decode::<u16>(&text).unwrap();
+        assert_eq!(restored[&7].as_ref::<u32>(), Some(&1u32));
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{HashMap, BTreeMap, Bound};
+use std::collections::{hash_map, btree_map};
+use std::hash::Hash;
+use std::iter::Map;
+use std::mem;
+use std::slice;
+
+use super::dyn_property::DynProperty;
+
+
+/// the iterators of a `PropertyStorage` borrowing it for `'a`
+///
+/// They are a separate trait because they depend on the lifetime of the
+/// borrow, every `PropertyStorage` implements it for all lifetimes. The
+/// `Outlives` parameter only exists to imply `Key: 'a`, keep the default.
+///
+pub trait StorageIterators<'a, Key: 'a, Outlives = &'a Key> {
+
+    /// the iterator returned by `iter`
+    type Iter: Iterator<Item=( &'a Key, &'a DynProperty )>;
+
+    /// the iterator returned by `iter_mut`
+    type IterMut: Iterator<Item=( &'a Key, &'a mut DynProperty )>;
+
+    /// returns a iterator over all properties
+    fn iter( &'a self ) -> Self::Iter;
+
+    /// returns a mutable iterator over all properties
+    fn iter_mut( &'a mut self ) -> Self::IterMut;
+}
+
+/// the storage backend of a `InnerDynObject`
+///
+/// It maps keys to properties. `InnerDynObject` only calls `insert`
+/// for keys witch do not exist yet and does all the type checks, so
+/// a backend only has to store the properties. Iterating is provided
+/// by `StorageIterators`.
+///
+/// The crate provides `HashMapStorage` (the default), `BTreeMapStorage`
+/// with ordered iteration, `VecStorage` for objects with only a few keys
+/// and the insertion ordered `OrderedStorage`.
+///
+pub trait PropertyStorage<Key>: for<'a> StorageIterators<'a, Key> {
+
+    /// creates a empty storage
+    fn new() -> Self;
+
+    /// returns the number of properties
+    fn len( &self ) -> usize;
+
+    /// returns true if there are no properties
+    fn is_empty( &self ) -> bool {
+        self.len() == 0
+    }
+
+    /// returns the property defined by key
+    fn get( &self, key: &Key ) -> Option<&DynProperty>;
+
+    /// returns the property defined by key mutable
+    fn get_mut( &mut self, key: &Key ) -> Option<&mut DynProperty>;
+
+    /// returns true if the property defined by key exists
+    fn contains_key( &self, key: &Key ) -> bool {
+        self.get( key ).is_some()
+    }
+
+    /// inserts a property, the key does not exist yet
+    fn insert( &mut self, key: Key, property: DynProperty );
+
+    /// removes the property defined by key
+    fn remove( &mut self, key: &Key ) -> Option<DynProperty>;
+
+    /// removes all properties returning them in the iteration order
+    fn take_all( &mut self ) -> Vec<( Key, DynProperty )>;
+}
+
+
+/// the default storage, a `HashMap`
+pub struct HashMapStorage<Key> {
+    map: HashMap<Key, DynProperty>
+}
+
+impl<Key> PropertyStorage<Key> for HashMapStorage<Key> where Key: Eq + Hash {
+
+    fn new() -> HashMapStorage<Key> {
+        HashMapStorage { map: HashMap::new() }
+    }
+
+    fn len( &self ) -> usize {
+        self.map.len()
+    }
+
+    fn get( &self, key: &Key ) -> Option<&DynProperty> {
+        self.map.get( key )
+    }
+
+    fn get_mut( &mut self, key: &Key ) -> Option<&mut DynProperty> {
+        self.map.get_mut( key )
+    }
+
+    fn contains_key( &self, key: &Key ) -> bool {
+        self.map.contains_key( key )
+    }
+
+    fn insert( &mut self, key: Key, property: DynProperty ) {
+        self.map.insert( key, property );
+    }
+
+    fn remove( &mut self, key: &Key ) -> Option<DynProperty> {
+        self.map.remove( key )
+    }
+
+    fn take_all( &mut self ) -> Vec<( Key, DynProperty )> {
+        mem::replace( &mut self.map, HashMap::new() ).into_iter().collect()
+    }
+}
+
+impl<'a, Key> StorageIterators<'a, Key> for HashMapStorage<Key> where Key: Eq + Hash {
+    type Iter = hash_map::Iter<'a, Key, DynProperty>;
+    type IterMut = hash_map::IterMut<'a, Key, DynProperty>;
+
+    fn iter( &'a self ) -> hash_map::Iter<'a, Key, DynProperty> {
+        self.map.iter()
+    }
+
+    fn iter_mut( &'a mut self ) -> hash_map::IterMut<'a, Key, DynProperty> {
+        self.map.iter_mut()
+    }
+}
+
+
+/// a storage based on a `BTreeMap`, iterating in the order of the keys
+pub struct BTreeMapStorage<Key> {
+    map: BTreeMap<Key, DynProperty>
+}
+
+impl<Key> BTreeMapStorage<Key> where Key: Ord {
+
+    /// returns a iterator over the properties with keys in the given range
+    pub fn range<'a>( &'a self, min: Bound<&Key>, max: Bound<&Key> ) -> btree_map::Range<'a, Key, DynProperty> {
+        self.map.range( min, max )
+    }
+}
+
+impl<Key> PropertyStorage<Key> for BTreeMapStorage<Key> where Key: Ord {
+
+    fn new() -> BTreeMapStorage<Key> {
+        BTreeMapStorage { map: BTreeMap::new() }
+    }
+
+    fn len( &self ) -> usize {
+        self.map.len()
+    }
+
+    fn get( &self, key: &Key ) -> Option<&DynProperty> {
+        self.map.get( key )
+    }
+
+    fn get_mut( &mut self, key: &Key ) -> Option<&mut DynProperty> {
+        self.map.get_mut( key )
+    }
+
+    fn contains_key( &self, key: &Key ) -> bool {
+        self.map.contains_key( key )
+    }
+
+    fn insert( &mut self, key: Key, property: DynProperty ) {
+        self.map.insert( key, property );
+    }
+
+    fn remove( &mut self, key: &Key ) -> Option<DynProperty> {
+        self.map.remove( key )
+    }
+
+    fn take_all( &mut self ) -> Vec<( Key, DynProperty )> {
+        mem::replace( &mut self.map, BTreeMap::new() ).into_iter().collect()
+    }
+}
+
+impl<'a, Key> StorageIterators<'a, Key> for BTreeMapStorage<Key> where Key: Ord {
+    type Iter = btree_map::Iter<'a, Key, DynProperty>;
+    type IterMut = btree_map::IterMut<'a, Key, DynProperty>;
+
+    fn iter( &'a self ) -> btree_map::Iter<'a, Key, DynProperty> {
+        self.map.iter()
+    }
+
+    fn iter_mut( &'a mut self ) -> btree_map::IterMut<'a, Key, DynProperty> {
+        self.map.iter_mut()
+    }
+}
+
+
+/// a storage searching the keys linear, faster then hashing for a few keys
+///
+/// Removing a property moves the last property to its place, so the
+/// iteration order is only stable as long as nothing is removed.
+pub struct VecStorage<Key> {
+    entries: Vec<( Key, DynProperty )>
+}
+
+impl<Key> VecStorage<Key> where Key: Eq {
+
+    fn position( &self, key: &Key ) -> Option<usize> {
+        self.entries.iter().position( |&( ref other, _ )| other == key )
+    }
+}
+
+impl<Key> PropertyStorage<Key> for VecStorage<Key> where Key: Eq {
+
+    fn new() -> VecStorage<Key> {
+        VecStorage { entries: Vec::new() }
+    }
+
+    fn len( &self ) -> usize {
+        self.entries.len()
+    }
+
+    fn get( &self, key: &Key ) -> Option<&DynProperty> {
+        self.entries.iter().find( |&&( ref other, _ )| other == key ).map( |&( _, ref prop )| prop )
+    }
+
+    fn get_mut( &mut self, key: &Key ) -> Option<&mut DynProperty> {
+        self.entries.iter_mut().find( |&&mut ( ref other, _ )| other == key ).map( |&mut ( _, ref mut prop )| prop )
+    }
+
+    fn insert( &mut self, key: Key, property: DynProperty ) {
+        self.entries.push( ( key, property ));
+    }
+
+    fn remove( &mut self, key: &Key ) -> Option<DynProperty> {
+        self.position( key ).map( |idx| self.entries.swap_remove( idx ).1 )
+    }
+
+    fn take_all( &mut self ) -> Vec<( Key, DynProperty )> {
+        mem::replace( &mut self.entries, Vec::new() )
+    }
+}
+
+impl<'a, Key> StorageIterators<'a, Key> for VecStorage<Key> where Key: Eq {
+    type Iter = EntryIter<'a, Key>;
+    type IterMut = EntryIterMut<'a, Key>;
+
+    fn iter( &'a self ) -> EntryIter<'a, Key> {
+        entry_iter( &self.entries )
+    }
+
+    fn iter_mut( &'a mut self ) -> EntryIterMut<'a, Key> {
+        entry_iter_mut( &mut self.entries )
+    }
+}
+
+
+/// a storage iterating in the order the properties were inserted
+///
+/// Looking up a key uses a `HashMap`, removing a property is linear
+/// in the number of properties.
+pub struct OrderedStorage<Key> {
+    index: HashMap<Key, usize>,
+    entries: Vec<( Key, DynProperty )>
+}
+
+impl<Key> PropertyStorage<Key> for OrderedStorage<Key> where Key: Eq + Hash + Clone {
+
+    fn new() -> OrderedStorage<Key> {
+        OrderedStorage {
+            index: HashMap::new(),
+            entries: Vec::new()
+        }
+    }
+
+    fn len( &self ) -> usize {
+        self.entries.len()
+    }
+
+    fn get( &self, key: &Key ) -> Option<&DynProperty> {
+        match self.index.get( key ) {
+            Some( &idx ) => Some( &self.entries[idx].1 ),
+            None => None
+        }
+    }
+
+    fn get_mut( &mut self, key: &Key ) -> Option<&mut DynProperty> {
+        match self.index.get( key ) {
+            Some( &idx ) => Some( &mut self.entries[idx].1 ),
+            None => None
+        }
+    }
+
+    fn contains_key( &self, key: &Key ) -> bool {
+        self.index.contains_key( key )
+    }
+
+    fn insert( &mut self, key: Key, property: DynProperty ) {
+        self.index.insert( key.clone(), self.entries.len() );
+        self.entries.push( ( key, property ));
+    }
+
+    fn remove( &mut self, key: &Key ) -> Option<DynProperty> {
+        let idx = match self.index.remove( key ) {
+            Some( idx ) => idx,
+            None => return None
+        };
+        for ( _, other ) in self.index.iter_mut() {
+            if *other > idx {
+                *other -= 1;
+            }
+        }
+        Some( self.entries.remove( idx ).1 )
+    }
+
+    fn take_all( &mut self ) -> Vec<( Key, DynProperty )> {
+        self.index.clear();
+        mem::replace( &mut self.entries, Vec::new() )
+    }
+}
+
+impl<'a, Key> StorageIterators<'a, Key> for OrderedStorage<Key> where Key: Eq + Hash + Clone {
+    type Iter = EntryIter<'a, Key>;
+    type IterMut = EntryIterMut<'a, Key>;
+
+    fn iter( &'a self ) -> EntryIter<'a, Key> {
+        entry_iter( &self.entries )
+    }
+
+    fn iter_mut( &'a mut self ) -> EntryIterMut<'a, Key> {
+        entry_iter_mut( &mut self.entries )
+    }
+}
+
+/// the iterator of the storages keeping the properties in a `Vec`
+pub type EntryIter<'a, Key> = Map<slice::Iter<'a, ( Key, DynProperty )>,
+                                  fn( &'a ( Key, DynProperty )) -> ( &'a Key, &'a DynProperty )>;
+
+/// the mutable iterator of the storages keeping the properties in a `Vec`
+pub type EntryIterMut<'a, Key> = Map<slice::IterMut<'a, ( Key, DynProperty )>,
+                                     fn( &'a mut ( Key, DynProperty )) -> ( &'a Key, &'a mut DynProperty )>;
+
+fn entry_iter<'a, Key>( entries: &'a [( Key, DynProperty )] ) -> EntryIter<'a, Key> {
+    fn split<'a, Key>( entry: &'a ( Key, DynProperty )) -> ( &'a Key, &'a DynProperty ) {
+        ( &entry.0, &entry.1 )
+    }
+    entries.iter().map( split as fn( &'a ( Key, DynProperty )) -> ( &'a Key, &'a DynProperty ))
+}
+
+fn entry_iter_mut<'a, Key>( entries: &'a mut [( Key, DynProperty )] ) -> EntryIterMut<'a, Key> {
+    fn split<'a, Key>( entry: &'a mut ( Key, DynProperty )) -> ( &'a Key, &'a mut DynProperty ) {
+        ( &entry.0, &mut entry.1 )
+    }
+    entries.iter_mut().map( split as fn( &'a mut ( Key, DynProperty )) -> ( &'a Key, &'a mut DynProperty ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::Bound;
+    use super::{PropertyStorage, StorageIterators, HashMapStorage, BTreeMapStorage, VecStorage, OrderedStorage};
+    use super::super::dyn_property::DynProperty;
+
+    fn fill<S: PropertyStorage<u8>>() -> S {
+        let mut storage = S::new();
+        for &key in [3u8, 1, 4, 2].iter() {
+            storage.insert( key, DynProperty::from_value( key as u32 * 10 ));
+        }
+        storage
+    }
+
+    fn keys<S: PropertyStorage<u8>>( storage: &S ) -> Vec<u8> {
+        storage.iter().map( |( key, _ )| *key ).collect()
+    }
+
+    fn check_storage<S: PropertyStorage<u8>>() {
+        let mut storage = fill::<S>();
+        assert_eq!( storage.len(), 4 );
+        assert_eq!( storage.get( &4 ).unwrap().as_ref::<u32>(), Some( &40u32 ));
+        *storage.get_mut( &1 ).unwrap().as_mut::<u32>().unwrap() += 1;
+        assert_eq!( storage.get( &1 ).unwrap().as_ref::<u32>(), Some( &11u32 ));
+        assert!( storage.remove( &3 ).is_some() );
+        assert!( storage.remove( &3 ).is_none() );
+        assert!( !storage.contains_key( &3 ));
+        assert!( storage.get( &2 ).is_some() );
+        assert_eq!( storage.iter_mut().count(), 3 );
+        assert_eq!( storage.take_all().len(), 3 );
+        assert!( storage.is_empty() );
+    }
+
+    #[test]
+    fn all_storages_should_store_the_properties() {
+        check_storage::<HashMapStorage<u8>>();
+        check_storage::<BTreeMapStorage<u8>>();
+        check_storage::<VecStorage<u8>>();
+        check_storage::<OrderedStorage<u8>>();
+    }
+
+    #[test]
+    fn btree_map_storage_should_iterate_ordered() {
+        let storage = fill::<BTreeMapStorage<u8>>();
+        assert_eq!( keys( &storage ), vec![ 1, 2, 3, 4 ] );
+        let range: Vec<u8> = storage.range( Bound::Included( &2 ), Bound::Excluded( &4 ))
+                                    .map( |( key, _ )| *key ).collect();
+        assert_eq!( range, vec![ 2, 3 ] );
+    }
+
+    #[test]
+    fn ordered_storage_should_keep_the_insertion_order() {
+        let mut storage = fill::<OrderedStorage<u8>>();
+        assert!( storage.remove( &1 ).is_some() );
+        storage.insert( 1, DynProperty::from_value( 0u32 ));
+        assert_eq!( keys( &storage ), vec![ 3, 4, 2, 1 ] );
+        assert_eq!( storage.get( &2 ).unwrap().as_ref::<u32>(), Some( &20u32 ));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::PropertyStorage;
+use super::DynObject;
+
+
+/// a interned string, used as cheap key of a `DynObject<Symbol>`
+///
+/// A `Symbol` is only a number, hashing and comparing it does not
+/// touch the string. The string can be looked up with the `Interner`
+/// the symbol was created by. Symbols of different interners must
+/// not be mixed.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{DynObject, Interner, Symbol};
+/// let mut interner = Interner::new();
+/// let hp = interner.intern("hp");
+/// let obj = DynObject::<Symbol>::new();
+/// obj.aquire().create_value(hp, 100u32).unwrap();
+/// assert!(obj.aquire().exists_property(&interner.intern("hp")));
+/// assert_eq!(interner.resolve(hp), Some("hp"));
+/// ```
+///
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Symbol(u32);
+
+impl Symbol {
+
+    /// returns the number of the symbol, symbols are numbered in creation order
+    ///
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// maps strings to `Symbol`s and back
+///
+pub struct Interner {
+    names: Vec<String>,
+    symbols: HashMap<String, Symbol>
+}
+
+impl Interner {
+
+    /// creates a empty `Interner`
+    ///
+    pub fn new() -> Interner {
+        Interner {
+            names: Vec::new(),
+            symbols: HashMap::new()
+        }
+    }
+
+    /// returns the symbol of `name`, creating it if it does not exist yet
+    ///
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        match self.symbols.get(name) {
+            Some(&symbol) => return symbol,
+            None => {}
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.symbols.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    /// returns the symbol of `name` without creating it
+    ///
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// returns the string of `symbol`
+    ///
+    /// Returns `None` if the symbol was created by a other interner.
+    ///
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.index()).map(|name| &name[..])
+    }
+
+    /// returns the number of interned strings
+    ///
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// returns true if no string was interned
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// returns a wrapper printing the names instead of the numbers of symbols
+    ///
+    /// The wrapper implements `Debug` for `Symbol`, `InnerDynObject<Symbol>`
+    /// and `DynObject<Symbol>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::{InnerDynObject, DynProperty, Interner, Symbol};
+    /// let mut interner = Interner::new();
+    /// let mut obj = InnerDynObject::<Symbol>::new();
+    /// obj.insert_property(interner.intern("hp"), DynProperty::new_debug(Box::new(10u32))).unwrap();
+    /// assert_eq!(format!("{:?}", interner.names(&obj)), "{\"hp\": u32 = 10}");
+    /// ```
+    ///
+    pub fn names<'a, T>(&'a self, value: &'a T) -> Names<'a, T> {
+        Names {
+            value: value,
+            interner: self
+        }
+    }
+
+    fn fmt_symbol(&self, symbol: Symbol, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self.resolve(symbol) {
+            Some(name) => write!(fter, "{:?}", name),
+            None => write!(fter, "{:?}", symbol)
+        }
+    }
+}
+
+/// debug output with resolved symbol names, returned by `Interner::names`
+pub struct Names<'a, T: 'a> {
+    value: &'a T,
+    interner: &'a Interner
+}
+
+impl<'a> fmt::Debug for Names<'a, Symbol> {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        self.interner.fmt_symbol(*self.value, fter)
+    }
+}
+
+impl<'a, S> fmt::Debug for Names<'a, InnerDynObject<Symbol, S>> where S: PropertyStorage<Symbol> {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        let interner = self.interner;
+        inner_dyn_object::fmt_properties(self.value, fter, |&key, fter| interner.fmt_symbol(key, fter))
+    }
+}
+
+impl<'a, S> fmt::Debug for Names<'a, DynObject<Symbol, S>> where S: PropertyStorage<Symbol> {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self.value.try_aquire_ref() {
+            Ok(inner) => write!(fter, "DynObject({:?})", self.interner.names(&*inner)),
+            Err(_) => fter.write_str("DynObject(<aquired>)")
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Interner, Symbol};
+    use super::super::dyn_property::DynProperty;
+    use super::super::DynObject;
+
+    #[test]
+    fn intern_should_return_the_same_symbol_for_the_same_string() {
+        let mut interner = Interner::new();
+        let first = interner.intern("hp");
+        let second = interner.intern("mana");
+        assert!(first != second);
+        assert_eq!(interner.intern("hp"), first);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn symbols_should_be_convertible_in_both_directions() {
+        let mut interner = Interner::new();
+        assert_eq!(interner.get("hp"), None);
+        let hp = interner.intern("hp");
+        assert_eq!(interner.get("hp"), Some(hp));
+        assert_eq!(interner.resolve(hp), Some("hp"));
+        assert_eq!(Interner::new().resolve(hp), None);
+    }
+
+    #[test]
+    fn names_should_resolve_the_keys_of_a_object() {
+        let mut interner = Interner::new();
+        let obj = DynObject::<Symbol>::new();
+        obj.aquire().insert_property(interner.intern("hp"), DynProperty::new_debug(Box::new(3u8))).unwrap();
+        assert_eq!(format!("{:?}", interner.names(&obj)), "DynObject({\"hp\": u8 = 3})");
+        let unknown = Interner::new();
+        assert_eq!(format!("{:?}", unknown.names(&obj)), "DynObject({Symbol(0): u8 = 3})");
+        let _guard = obj.aquire();
+        assert_eq!(format!("{:?}", interner.names(&obj)), "DynObject(<aquired>)");
+    }
+}
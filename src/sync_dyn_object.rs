@@ -0,0 +1,146 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockResult};
+use std::hash::Hash;
+
+use super::inner_sync_dyn_object::InnerSyncDynObject;
+
+
+/// The thread safe counterpart of `DynObject`
+///
+/// Instead of `Rc<RefCell<..>>` a `Arc<RwLock<..>>` is used to share
+/// the inner `InnerSyncDynObject`. Therefor the object can be cloned
+/// and send to other threads. Any number of threads can read the
+/// object at the same time, but writing is exclusive.
+///
+/// The sync types are a deliberately limited subset of the `Rc` based
+/// ones and do not share their implementation. They only support
+/// creating, setting, removing and indexing properties, there are no
+/// prototypes, guards, observers, iterators, entries, storage backends,
+/// paths, transactions, history or deep clones. Use a separate
+/// `DynObject` in each thread if you need these.
+///
+pub struct SyncDynObject<Key> {
+    inner: Arc<RwLock<InnerSyncDynObject<Key>>>
+}
+
+impl<Key> SyncDynObject<Key> where Key: Eq+Hash {
+
+    /// create a new empty SyncDynObject with Key Type `Key`
+    ///
+    pub fn new() -> SyncDynObject<Key> {
+        let x = InnerSyncDynObject::<Key>::new();
+        SyncDynObject {
+            inner: Arc::new(RwLock::new(x))
+        }
+    }
+
+    /// aquire the SyncDynObject for writing
+    ///
+    /// Blocks the current thread until no one else has aquired
+    /// the object.
+    ///
+    /// # Panics
+    /// panics if the lock was poisoned by a thread witch paniced
+    /// while holding it
+    ///
+    pub fn aquire(&self) -> RwLockWriteGuard<InnerSyncDynObject<Key>> {
+        self.inner.write().unwrap()
+    }
+
+    /// aquire the SyncDynObject for writing without blocking or panicing
+    ///
+    /// Returns `TryLockError::WouldBlock` if someone else aquired the
+    /// object and `TryLockError::Poisoned` if the lock was poisoned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dynobject::SyncDynObject;
+    /// let obj = SyncDynObject::<i32>::new();
+    /// let v1 = obj.aquire_ref();
+    /// assert!(obj.try_aquire().is_err());
+    /// ```
+    ///
+    pub fn try_aquire(&self) -> TryLockResult<RwLockWriteGuard<InnerSyncDynObject<Key>>> {
+        self.inner.try_write()
+    }
+
+    /// aquire the SyncDynObject for reading
+    ///
+    /// Blocks the current thread until no one has aquired the object
+    /// for writing. Any number of readers can aquire it at the same time.
+    ///
+    /// # Panics
+    /// panics if the lock was poisoned by a thread witch paniced
+    /// while holding it
+    ///
+    pub fn aquire_ref(&self) -> RwLockReadGuard<InnerSyncDynObject<Key>> {
+        self.inner.read().unwrap()
+    }
+
+    /// aquire the SyncDynObject for reading without blocking or panicing
+    ///
+    /// Returns `TryLockError::WouldBlock` if someone aquired the object
+    /// for writing and `TryLockError::Poisoned` if the lock was poisoned.
+    ///
+    pub fn try_aquire_ref(&self) -> TryLockResult<RwLockReadGuard<InnerSyncDynObject<Key>>> {
+        self.inner.try_read()
+    }
+}
+
+impl<Key> Clone for SyncDynObject<Key> where Key: Eq+Hash {
+
+    /// shalow clons `SyncDynObject` sharing the inner `InnerSyncDynObject`
+    fn clone(&self) -> Self {
+        SyncDynObject {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    #![allow(unused_variables)]
+
+    use std::thread;
+    use super::SyncDynObject;
+
+    fn create_dummy() -> SyncDynObject<&'static str> {
+        SyncDynObject::<&'static str>::new()
+    }
+
+    #[test]
+    fn aquire_ref_multiple_times_should_not_block() {
+        let x = create_dummy();
+        let data = x.aquire_ref();
+        let data2 = x.aquire_ref();
+    }
+
+    #[test]
+    fn try_aquire_should_fail_instead_of_blocking() {
+        let x = create_dummy();
+        {
+            let data = x.aquire_ref();
+            assert!(x.try_aquire().is_err());
+            assert!(x.try_aquire_ref().is_ok());
+        }
+        let data = x.try_aquire().unwrap();
+        assert!(x.try_aquire_ref().is_err());
+    }
+
+    #[test]
+    fn cloned_sync_dyn_object_should_be_usable_from_other_threads() {
+        let obj = create_dummy();
+        assert!(obj.aquire().create_property("counter", Box::new(0u32)).is_ok());
+        let handles = (0..4).map(|_| {
+            let obj = obj.clone();
+            thread::spawn(move || {
+                *obj.aquire()["counter"].as_mut::<u32>().unwrap() += 1;
+            })
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            assert!(handle.join().is_ok());
+        }
+        assert_eq!(obj.aquire_ref()["counter"].as_ref::<u32>(), Some(&4u32));
+    }
+}
@@ -0,0 +1,171 @@
+use std::result::Result;
+use std::mem;
+use std::any::Any;
+use std::boxed::BoxAny;
+
+use super::dyn_property::type_name;
+use super::property_error::PropertyError;
+
+
+/// The `SyncDynProperty` is the thread safe counterpart of `DynProperty`
+///
+/// It wraps a `Box<Any+Send+Sync>` and therefor only accepts values
+/// which can be send to and shared between threads. It only has the
+/// basic methodes of `DynProperty` (`set`, `as_ref`, `as_mut`,
+/// `is_inner_type` and `destruct`), there is no inline storage, cloning,
+/// borrowing, casting or conversion support.
+///
+/// Note that a `SyncDynProperty` has allways the same inner type
+/// after creation.
+///
+pub struct SyncDynProperty {
+    type_name: &'static str,
+    value: Box<Any+Send+Sync+'static>
+}
+
+impl SyncDynProperty {
+
+    /// creats a new SyncDynProperty with given initial value
+    ///
+    pub fn new<T>(initial_value: Box<T>) -> SyncDynProperty
+        where T: Any+Send+Sync
+    {
+        SyncDynProperty {
+            type_name: type_name::<T>(),
+            value: initial_value
+        }
+    }
+
+    /// replaces the current inner value with a new one
+    ///
+    /// Works like `DynProperty::set`, returning the old value
+    /// as `Ok(Box(T))` or a `PropertyError::TypeMismatch` containing
+    /// the given value if the type does not match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dynobject::SyncDynProperty;
+    /// let mut prop = SyncDynProperty::new(Box::new(123i32));
+    /// match prop.set(Box::new(321i32)) {
+    ///     Ok(old) => assert_eq!(*old, 123i32),
+    ///     Err(err) => panic!("wont happen here")
+    /// }
+    /// ```
+    ///
+    pub fn set<T>(&mut self, value: Box<T>) -> Result<Box<T>,PropertyError<Box<T>>>
+        where T: Any+Send+Sync+'static
+    {
+        if self.is_inner_type::<T>() {
+            let mut any_boxed = value as Box<Any+Send+Sync+'static>;
+            mem::swap(&mut any_boxed, &mut self.value);
+            //drop the Send+Sync bounds so that BoxAny can be used
+            let any_boxed: Box<Any+'static> = any_boxed;
+            Ok(any_boxed.downcast::<T>().unwrap())
+        } else {
+            Err(type_mismatch::<T, _>(self, value))
+        }
+    }
+
+    /// return a referenc to the inner Data if possible
+    ///
+    /// If the given type is the same as the inner type
+    /// return a reference to the inner data (typed) wrapped
+    /// into Some, else return None
+    ///
+    pub fn as_ref<'a, T>(&'a self) -> Option<&'a T>
+        where T: Any+Send+Sync+'static
+    {
+        let any_ref: &Any = &*self.value;
+        any_ref.downcast_ref()
+    }
+
+    /// return a mutable reference to the inner data if possible
+    ///
+    /// If the given type is the same as the inner type
+    /// return a typed mutable reference to the inner data wrapped
+    /// into Some. If not valide return None
+    ///
+    pub fn as_mut<'a, T>(&'a mut self) -> Option<&'a mut T>
+        where T: Any+Send+Sync+'static
+    {
+        let any_mut: &mut Any = &mut *self.value;
+        any_mut.downcast_mut::<T>()
+    }
+
+    /// return true if the given type matches the inner type
+    ///
+    pub fn is_inner_type<T:Any>(&self) -> bool {
+        let any_ref: &Any = &*self.value;
+        any_ref.is::<T>()
+    }
+
+    /// consumes this instance returning the inner data
+    ///
+    /// Works like `DynProperty::destruct`, the inner data will be
+    /// droped if the given type does not match.
+    ///
+    pub fn destruct<T>(self) -> Option<Box<T>>
+        where T: Any+Send+Sync+'static
+    {
+        if self.is_inner_type::<T>() {
+            let any_boxed: Box<Any+'static> = self.value;
+            Some(any_boxed.downcast::<T>().unwrap())
+        } else {
+            None
+        }
+    }
+}
+
+/// creates a `PropertyError::TypeMismatch` for a access of `prop` with type `T`
+pub fn type_mismatch<T, V>(prop: &SyncDynProperty, value: V) -> PropertyError<V> {
+    PropertyError::TypeMismatch {
+        expected: type_name::<T>(),
+        found: prop.type_name,
+        value: value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SyncDynProperty;
+
+    #[derive(Eq, PartialEq, Debug)]
+    struct Point(i32,i32);
+
+    fn create_dummy() -> SyncDynProperty {
+        SyncDynProperty::new(Box::new(Point(12, 25)))
+    }
+
+    #[test]
+    fn as_ref_should_return_some_if_type_matchs() {
+        let x = create_dummy();
+        assert_eq!(x.as_ref::<Point>(), Some(&Point(12, 25)));
+    }
+
+    #[test]
+    fn as_ref_should_return_none_if_type_mismatches() {
+        let x = create_dummy();
+        assert_eq!(x.as_ref::<i32>(), None);
+    }
+
+    #[test]
+    fn as_mut_should_allow_mutating_the_inner_type() {
+        let mut x = create_dummy();
+        x.as_mut::<Point>().unwrap().0 += 10;
+        assert_eq!(x.as_ref::<Point>(), Some(&Point(22, 25)));
+    }
+
+    #[test]
+    fn set_should_return_err_of_the_parameter_if_type_mismatches() {
+        let mut x = create_dummy();
+        let err = x.set(Box::new(3u8)).err().unwrap();
+        assert_eq!(err.into_value(), Box::new(3u8));
+    }
+
+    #[test]
+    fn destruct_shuld_return_inner_value_if_type_matchs() {
+        let x = create_dummy();
+        assert_eq!(x.destruct::<Point>(), Some(Box::new(Point(12, 25))));
+    }
+}
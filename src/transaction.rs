@@ -0,0 +1,296 @@
+use std::any::{Any, TypeId};
+use std::ops::Deref;
+use std::mem;
+
+use super::dyn_property::DynProperty;
+use super::property_error::PropertyError;
+use super::inner_dyn_object::{self, InnerDynObject};
+use super::storage::{PropertyStorage, HashMapStorage};
+use super::history::{History, Edit};
+
+
+/// a change done over a `Transaction`, with everything needed to undo it
+enum JournalEntry<Key> {
+    /// the property was created
+    Created( Key ),
+    /// the property was set, contains the type and the old value
+    Replaced( Key, TypeId, Box<Any> ),
+    /// the property was removed
+    Removed( Key, DynProperty )
+}
+
+/// a set of changes witch is either applied completely or not at all
+///
+/// The changes are applied to the object immediately and recorded in
+/// a journal, so reading over the transaction (it derefs to the object)
+/// shows them. Replaced and removed values are moved into the journal,
+/// they do not have to be cloneable. `commit` keeps the changes,
+/// `rollback` or droping the transaction undoes them in reverse order
+/// without calling the guards. The observers are notified about the
+/// changes and about undoing them. Changes of nested objects are not
+/// part of the transaction. If the object records its history a
+/// committed transaction is one undo step, a rolled back one leaves
+/// no trace in the history. The journal is moved into the history,
+/// so the changed properties do not have to be cloneable.
+/// While the transaction exists the object records nothing.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::InnerDynObject;
+/// let mut obj = InnerDynObject::<&'static str>::new();
+/// obj.create_value( "gold", 100u32 ).unwrap();
+/// {
+///     let mut transaction = obj.begin_transaction();
+///     transaction.set_value( &"gold", 50u32 ).unwrap();
+///     transaction.create_value( "sword", 1u8 ).unwrap();
+///     assert!( transaction.create_value( "sword", 2u8 ).is_err() );
+///     //droped without commit
+/// }
+/// assert_eq!( obj["gold"].as_ref::<u32>(), Some( &100u32 ));
+/// assert!( !obj.exists_property( &"sword" ));
+/// ```
+///
+pub struct Transaction<'a, Key: 'a + Eq, S: 'a + PropertyStorage<Key> = HashMapStorage<Key>> {
+    obj: &'a mut InnerDynObject<Key, S>,
+    journal: Vec<JournalEntry<Key>>,
+    history: Option<Box<History<Key>>>
+}
+
+/// creates a `Transaction` on `obj`
+pub fn begin<'a, Key, S>( obj: &'a mut InnerDynObject<Key, S> ) -> Transaction<'a, Key, S>
+    where Key: Eq, S: PropertyStorage<Key>
+{
+    let history = inner_dyn_object::take_history( obj );
+    Transaction {
+        obj: obj,
+        journal: Vec::new(),
+        history: history
+    }
+}
+
+impl<'a, Key, S> Transaction<'a, Key, S> where Key: Eq + Clone, S: PropertyStorage<Key> {
+
+    /// creates a property, see `InnerDynObject::create_property`
+    ///
+    pub fn create_property<T>( &mut self, key: Key, init_value: Box<T> ) -> Result<(),PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        try!( self.obj.create_property( key.clone(), init_value ));
+        self.journal.push( JournalEntry::Created( key ));
+        Ok( () )
+    }
+
+    /// creates a property from a value, see `InnerDynObject::create_value`
+    ///
+    pub fn create_value<T>( &mut self, key: Key, init_value: T ) -> Result<(),PropertyError<T>>
+        where T: Any + 'static
+    {
+        try!( self.obj.create_value( key.clone(), init_value ));
+        self.journal.push( JournalEntry::Created( key ));
+        Ok( () )
+    }
+
+    /// sets a property, see `InnerDynObject::set_property`
+    ///
+    /// The old value is kept until the transaction ends.
+    ///
+    pub fn set_property<T>( &mut self, key: &Key, value: Box<T> ) -> Result<(),PropertyError<Box<T>>>
+        where T: Any + 'static
+    {
+        let old = try!( self.obj.set_property( key, value ));
+        self.journal.push( JournalEntry::Replaced( key.clone(), TypeId::of::<T>(), old ));
+        Ok( () )
+    }
+
+    /// sets a property to a value, see `InnerDynObject::set_value`
+    ///
+    /// The old value is kept until the transaction ends.
+    ///
+    pub fn set_value<T>( &mut self, key: &Key, value: T ) -> Result<(),PropertyError<T>>
+        where T: Any + 'static
+    {
+        let old = try!( self.obj.set_value( key, value ));
+        self.journal.push( JournalEntry::Replaced( key.clone(), TypeId::of::<T>(), Box::new( old )));
+        Ok( () )
+    }
+
+    /// removes a property of any type, see `InnerDynObject::remove_any`
+    ///
+    /// Fails if the property does not exist, is borrowed or a guard
+    /// rejects removing it. The property is kept until the transaction ends.
+    ///
+    pub fn remove( &mut self, key: &Key ) -> Result<(),PropertyError<()>> {
+        let prop = try!( inner_dyn_object::take_property( self.obj, key ));
+        self.journal.push( JournalEntry::Removed( key.clone(), prop ));
+        Ok( () )
+    }
+
+    /// returns the number of changes done over this transaction
+    ///
+    pub fn len( &self ) -> usize {
+        self.journal.len()
+    }
+
+    /// returns true if no changes were done over this transaction
+    ///
+    pub fn is_empty( &self ) -> bool {
+        self.journal.is_empty()
+    }
+
+    /// keeps all changes
+    ///
+    /// The replaced and removed values are droped, or moved into
+    /// the history if the object records it.
+    ///
+    pub fn commit( mut self ) {
+        let journal = mem::replace( &mut self.journal, Vec::new() );
+        match self.history {
+            Some( ref mut history ) => history.record_step( journal.into_iter().map( |entry| match entry {
+                JournalEntry::Created( key ) => Edit::Remove( key ),
+                JournalEntry::Replaced( key, type_id, old ) => Edit::Restore( key, type_id, old ),
+                JournalEntry::Removed( key, prop ) => Edit::Insert( key, prop )
+            }).collect() ),
+            None => {}
+        }
+    }
+
+    /// undoes all changes, the same as droping the transaction
+    ///
+    pub fn rollback( self ) {}
+}
+
+impl<'a, Key, S> Deref for Transaction<'a, Key, S> where Key: Eq, S: PropertyStorage<Key> {
+    type Target = InnerDynObject<Key, S>;
+
+    fn deref( &self ) -> &InnerDynObject<Key, S> {
+        self.obj
+    }
+}
+
+#[unsafe_destructor]
+impl<'a, Key, S> Drop for Transaction<'a, Key, S> where Key: Eq, S: PropertyStorage<Key> {
+    fn drop( &mut self ) {
+        inner_dyn_object::put_history( self.obj, self.history.take() );
+        while let Some( entry ) = self.journal.pop() {
+            match entry {
+                JournalEntry::Created( key ) => inner_dyn_object::discard_property( self.obj, &key ),
+                JournalEntry::Replaced( key, _, old ) => inner_dyn_object::restore_value( self.obj, &key, old ),
+                JournalEntry::Removed( key, prop ) => inner_dyn_object::restore_property( self.obj, key, prop )
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use super::super::inner_dyn_object::InnerDynObject;
+    use super::super::dyn_property::DynProperty;
+    use super::super::hooks::{Operation, Change};
+    use super::super::property_error::PropertyError;
+
+    fn create_object() -> InnerDynObject<&'static str> {
+        let mut obj = InnerDynObject::new();
+        obj.create_value( "gold", 100u32 ).unwrap();
+        obj.insert_property( "name", DynProperty::new_cloneable( Box::new( "hero".to_string() ))).unwrap();
+        obj
+    }
+
+    #[test]
+    fn commit_should_keep_the_changes() {
+        let mut obj = create_object();
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.set_value( &"gold", 50u32 ).unwrap();
+            assert_eq!( transaction.remove( &"name" ), Ok( () ));
+            transaction.create_value( "sword", 1u8 ).unwrap();
+            assert_eq!( transaction.len(), 3 );
+            assert_eq!( transaction["gold"].as_ref::<u32>(), Some( &50u32 ));
+            transaction.commit();
+        }
+        assert_eq!( obj["gold"].as_ref::<u32>(), Some( &50u32 ));
+        assert!( !obj.exists_property( &"name" ));
+        assert_eq!( obj["sword"].as_ref::<u8>(), Some( &1u8 ));
+    }
+
+    #[test]
+    fn rollback_should_restore_the_previous_state() {
+        let mut obj = create_object();
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.set_value( &"gold", 50u32 ).unwrap();
+            transaction.set_value( &"gold", 20u32 ).unwrap();
+            assert_eq!( transaction.remove( &"name" ), Ok( () ));
+            transaction.create_value( "name", 3i64 ).unwrap();
+            transaction.rollback();
+        }
+        assert_eq!( obj.len(), 2 );
+        assert_eq!( obj["gold"].as_ref::<u32>(), Some( &100u32 ));
+        //the removed property is restored as it was, including its clone support
+        assert_eq!( obj["name"].as_ref::<String>(), Some( &"hero".to_string() ));
+        assert!( obj["name"].is_cloneable() );
+    }
+
+    #[test]
+    fn rollback_should_not_call_the_guards_but_notify_the_observers() {
+        let mut obj = create_object();
+        let changes = Rc::new( RefCell::new( Vec::new() ));
+        let log = changes.clone();
+        obj.subscribe( move |key: &&'static str, change| log.borrow_mut().push( ( *key, change )));
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.create_value( "sword", 1u8 ).unwrap();
+            transaction.set_value( &"gold", 50u32 ).unwrap();
+        }
+        obj.add_guard( |_: &&'static str, op| op == Operation::Access );
+        {
+            let mut transaction = obj.begin_transaction();
+            assert!( transaction.set_value( &"gold", 1u32 ).is_err() );
+            assert_eq!( transaction.remove( &"gold" ), Err( PropertyError::Rejected( () )));
+            assert_eq!( transaction.remove( &"shield" ), Err( PropertyError::NotFound( () )));
+            assert!( transaction.is_empty() );
+        }
+        assert_eq!( obj["gold"].as_ref::<u32>(), Some( &100u32 ));
+        assert_eq!( &*changes.borrow(), &[
+            ( "sword", Change::Create ), ( "gold", Change::Set ),
+            ( "gold", Change::Set ), ( "sword", Change::Remove )
+        ] );
+    }
+
+    #[test]
+    fn transactions_should_be_one_history_step() {
+        let mut obj = create_object();
+        obj.enable_history( 5 );
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.set_value( &"gold", 50u32 ).unwrap();
+            transaction.create_value( "sword", 1u8 ).unwrap();
+            transaction.commit();
+        }
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.create_value( "shield", 1u8 ).unwrap();
+        }
+        assert!( obj.undo() );
+        assert!( !obj.can_undo() );
+        assert_eq!( obj["gold"].as_ref::<u32>(), Some( &100u32 ));
+        assert_eq!( obj.len(), 2 );
+    }
+
+    #[test]
+    fn rollback_should_not_affect_borrowed_values() {
+        let mut obj = create_object();
+        let guard;
+        {
+            let mut transaction = obj.begin_transaction();
+            transaction.set_value( &"gold", 50u32 ).unwrap();
+            guard = transaction.obj.borrow_prop::<u32>( &"gold" ).unwrap();
+        }
+        assert_eq!( *guard, 50u32 );
+        drop( guard );
+        assert_eq!( obj["gold"].as_ref::<u32>(), Some( &100u32 ));
+    }
+}
@@ -0,0 +1,60 @@
+use std::marker::PhantomData;
+
+
+/// a key witch knows the type of the property it refers to
+///
+/// Using a `TypedKey` with the typed accessors of `InnerDynObject`
+/// (`get`, `get_mut`, `set`, `create`) moves the type annotation from
+/// every call site to the definition of the key. Using the wrong type
+/// for a given key then is a compile time error. The object itself is
+/// not changed, so the same property can still be accessed with the
+/// plain key.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{InnerDynObject, TypedKey};
+/// let counter = TypedKey::<&'static str, u32>::new("counter");
+/// let mut obj = InnerDynObject::<&'static str>::new();
+/// assert!(obj.create(&counter, Box::new(1u32)).is_ok());
+/// *obj.get_mut(&counter).unwrap() += 1;
+/// assert_eq!(obj.get(&counter), Some(&2u32));
+/// assert_eq!(obj["counter"].as_ref::<u32>(), Some(&2u32));
+/// ```
+///
+pub struct TypedKey<Key, T> {
+    key: Key,
+    marker: PhantomData<fn() -> T>
+}
+
+impl<Key, T> TypedKey<Key, T> {
+
+    /// creates a new `TypedKey` for the property with the given key
+    ///
+    pub fn new(key: Key) -> TypedKey<Key, T> {
+        TypedKey {
+            key: key,
+            marker: PhantomData
+        }
+    }
+
+    /// returns a reference to the untyped key
+    ///
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// consumes the `TypedKey` returning the untyped key
+    ///
+    pub fn into_key(self) -> Key {
+        self.key
+    }
+}
+
+impl<Key, T> Clone for TypedKey<Key, T> where Key: Clone {
+    fn clone(&self) -> TypedKey<Key, T> {
+        TypedKey::new(self.key.clone())
+    }
+}
+
+impl<Key, T> Copy for TypedKey<Key, T> where Key: Copy {}
@@ -0,0 +1,154 @@
+use std::rc::{self, Weak};
+use std::cell::RefCell;
+use std::fmt;
+
+use super::inner_dyn_object::InnerDynObject;
+use super::storage::{PropertyStorage, HashMapStorage};
+use super::DynObject;
+
+
+/// a non-owning reference to a `DynObject`, returned by `DynObject::downgrade`
+///
+/// A `WeakDynObject` does not keep the object alive, so it can be used
+/// for back-pointers (e.g. from a child to its parent) and observer
+/// lists without creating reference cycles.
+///
+/// # Example
+///
+/// ```
+/// # use dynobject::{DynObject, WeakDynObject};
+/// let parent = DynObject::<&'static str>::new();
+/// let child = DynObject::<&'static str>::new();
+/// child.aquire().create_value("parent", parent.downgrade()).unwrap();
+/// parent.aquire().create_value("child", child.clone()).unwrap();
+/// assert!(child.aquire()["parent"].as_ref::<WeakDynObject<&'static str>>().unwrap().upgrade().is_some());
+/// drop(parent);
+/// assert!(child.aquire()["parent"].as_ref::<WeakDynObject<&'static str>>().unwrap().upgrade().is_none());
+/// ```
+///
+pub struct WeakDynObject<Key, S = HashMapStorage<Key>> {
+    inner: Weak<RefCell<InnerDynObject<Key, S>>>
+}
+
+/// creates a `WeakDynObject` refering to `obj`
+pub fn downgrade<Key, S>(obj: &DynObject<Key, S>) -> WeakDynObject<Key, S> {
+    WeakDynObject {
+        inner: obj.inner.downgrade()
+    }
+}
+
+impl<Key, S> WeakDynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
+
+    /// returns the object, if it is still alive
+    ///
+    pub fn upgrade(&self) -> Option<DynObject<Key, S>> {
+        self.inner.upgrade().map(|inner| DynObject { inner: inner })
+    }
+
+    /// returns the number of `DynObject`s sharing the object, 0 if it was dropped
+    ///
+    pub fn strong_count(&self) -> usize {
+        match self.inner.upgrade() {
+            //the upgraded Rc is one of the strong references
+            Some(inner) => rc::strong_count(&inner) - 1,
+            None => 0
+        }
+    }
+
+    /// returns the number of `WeakDynObject`s refering to the object
+    ///
+    /// Returns 0 if the object was dropped.
+    ///
+    pub fn weak_count(&self) -> usize {
+        match self.inner.upgrade() {
+            Some(inner) => rc::weak_count(&inner),
+            None => 0
+        }
+    }
+
+    /// returns true if both refer to the same object, which is still alive
+    ///
+    pub fn ptr_eq(&self, other: &WeakDynObject<Key, S>) -> bool {
+        match (self.upgrade(), other.upgrade()) {
+            (Some(first), Some(second)) => first.ptr_eq(&second),
+            _ => false
+        }
+    }
+}
+
+impl<Key, S> Clone for WeakDynObject<Key, S> {
+    fn clone(&self) -> WeakDynObject<Key, S> {
+        WeakDynObject {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+impl<Key, S> fmt::Debug for WeakDynObject<Key, S> where Key: Eq, S: PropertyStorage<Key> {
+
+    /// does not format the object, it might be part of a cycle
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner.upgrade() {
+            Some(_) => fter.write_str("WeakDynObject(<alive>)"),
+            None => fter.write_str("WeakDynObject(<dropped>)")
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::DynObject;
+
+    #[test]
+    fn upgrade_should_fail_after_the_object_was_dropped() {
+        let obj = DynObject::<&'static str>::new();
+        let weak = obj.downgrade();
+        assert!(weak.upgrade().unwrap().ptr_eq(&obj));
+        drop(obj);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(format!("{:?}", weak), "WeakDynObject(<dropped>)");
+    }
+
+    #[test]
+    fn counts_should_only_count_the_outside_references() {
+        let obj = DynObject::<&'static str>::new();
+        let weak = obj.downgrade();
+        let weak2 = weak.clone();
+        let obj2 = obj.clone();
+        assert_eq!(obj.strong_count(), 2);
+        assert_eq!(obj.weak_count(), 2);
+        assert_eq!(weak.strong_count(), 2);
+        assert_eq!(weak.weak_count(), 2);
+        drop(weak2);
+        drop(obj2);
+        assert_eq!(weak.strong_count(), 1);
+        assert_eq!(obj.weak_count(), 1);
+        drop(obj);
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(weak.weak_count(), 0);
+    }
+
+    #[test]
+    fn ptr_eq_should_compare_the_identity() {
+        let obj = DynObject::<&'static str>::new();
+        let other = DynObject::<&'static str>::new();
+        assert!(obj.ptr_eq(&obj.clone()));
+        assert!(!obj.ptr_eq(&other));
+        assert!(obj.downgrade().ptr_eq(&obj.downgrade()));
+        assert!(!obj.downgrade().ptr_eq(&other.downgrade()));
+    }
+
+    #[test]
+    fn back_pointers_should_not_keep_objects_alive() {
+        let parent = DynObject::<&'static str>::new();
+        let child = DynObject::<&'static str>::new();
+        child.aquire().create_value("parent", parent.downgrade()).unwrap();
+        parent.aquire().create_value("child", child.clone()).unwrap();
+        let weak_child = child.downgrade();
+        drop(child);
+        assert!(weak_child.upgrade().is_some());
+        drop(parent);
+        assert!(weak_child.upgrade().is_none());
+    }
+}